@@ -0,0 +1,45 @@
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use myslowlog::aggregate::{
+    aggregate_normalized, sort_aggregated, AggregateLogEntry, AggregateOptions,
+};
+use myslowlog::log_parser::LogEntry;
+use myslowlog::normalize::{NormalizeCache, NormalizedLogEntry};
+use myslowlog::opt::SortOrder;
+use rayon::prelude::*;
+use time::Duration;
+
+/// A log dominated by a few query shapes, which is the common case
+fn entries() -> Vec<LogEntry> {
+    (0..20_000)
+        .map(|i| LogEntry {
+            query: match i % 4 {
+                0 => format!("SELECT * FROM orders WHERE id = {};", i),
+                1 => format!("SELECT * FROM orders WHERE customer IN ({}, {});", i, i + 1),
+                2 => format!("UPDATE customers SET visits = visits + 1 WHERE id = {};", i % 100),
+                _ => format!("DELETE FROM sessions WHERE created < '2021-05-{:02}';", i % 28),
+            },
+            query_time: Duration::microseconds(i % 5000),
+            ..Default::default()
+        })
+        .collect()
+}
+
+fn top_10_by_total_time(entries: Vec<LogEntry>) -> Vec<AggregateLogEntry> {
+    let cache = NormalizeCache::default();
+    let normalized: Vec<NormalizedLogEntry> =
+        entries.into_par_iter().map(|entry| cache.normalize(entry)).collect();
+    let mut aggregated: Vec<AggregateLogEntry> =
+        aggregate_normalized(normalized, AggregateOptions::default()).into_values().collect();
+    sort_aggregated(&mut aggregated, Some(SortOrder::TotalTime), None);
+    aggregated.into_iter().rev().take(10).collect()
+}
+
+fn bench_normalized_top_10(c: &mut Criterion) {
+    let entries = entries();
+    c.bench_function("top 10 by total time, normalized", |b| {
+        b.iter_batched(|| entries.clone(), top_10_by_total_time, BatchSize::LargeInput)
+    });
+}
+
+criterion_group!(benches, bench_normalized_top_10);
+criterion_main!(benches);
@@ -1,66 +1,995 @@
 use std::cmp::max;
+use std::io::{BufRead, BufReader, Read};
 
-use ahash::{HashMap, HashMapExt};
+use ahash::{HashMap, HashMapExt, HashSet};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
 
-use crate::log_parser::LogEntry;
-use crate::normalize::NormalizedLogEntry;
+use crate::log_parser::{LogEntry, UNKNOWN};
+use crate::normalize::{query_shape, NormalizeCache, NormalizedLogEntry};
+use crate::opt::{GroupBy, SortOrder};
+use crate::tdigest::TDigest;
 
-#[derive(Clone, Debug)]
+/// Settings that bound how much memory aggregation uses
+#[derive(Copy, Clone, Debug, Default)]
+pub struct AggregateOptions {
+    /// Collect all queries beyond this many distinct ones in the overflow group
+    pub max_groups: Option<usize>,
+    /// Keep the details of up to this many individual executions per record
+    pub max_executions: usize,
+    /// Keep up to this many of the slowest distinct raw queries per record
+    pub max_examples: usize,
+    /// Keep the raw text of the query that examined the most rows in each record
+    pub keep_max_rows_query: bool,
+}
+
+/// The details of one individual query that was combined into an aggregated record
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Execution {
+    #[serde(with = "time::serde::rfc3339")]
+    pub timestamp: OffsetDateTime,
+    #[serde(rename = "query_time_us")]
+    pub query_time: i128,
+    pub rows_examined: i32,
+    pub user: String,
+}
+
+impl Execution {
+    fn new(entry: &LogEntry) -> Self {
+        Execution {
+            timestamp: entry.timestamp,
+            query_time: entry.query_time.whole_microseconds(),
+            rows_examined: entry.rows_examined,
+            user: entry.user.clone(),
+        }
+    }
+}
+
+/// A raw query of an aggregated record, with the longest time it took in any execution
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Example {
+    #[serde(rename = "query_time_us")]
+    pub query_time: i128,
+    pub query: String,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct AggregateLogEntry {
     pub query: String,
     pub count: i64,
     pub total_query_time: i128,
     pub avg_query_time: i128,
     pub max_query_time: i128,
+    pub stddev_query_time: i128,
+    /// Estimated percentiles of the query time, filled in once aggregation is complete
+    #[serde(default)]
+    pub p50_query_time: i128,
+    #[serde(default)]
+    pub p90_query_time: i128,
+    #[serde(default)]
+    pub p95_query_time: i128,
+    #[serde(default)]
+    pub p99_query_time: i128,
+    /// The literal values of the slowest query in a normalized group, if captured
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sample_values: Vec<String>,
+    /// The raw text of the slowest query in a normalized group
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub slowest_raw_query: String,
+    /// The highest number of rows examined by a single execution
+    #[serde(default)]
+    pub max_rows_examined: i64,
+    /// The raw text of the execution that examined the most rows, which is often a different
+    /// parameterization than the slowest one, if requested
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_rows_raw_query: Option<String>,
+    /// The first individual executions in the record, up to `AggregateOptions::max_executions`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub executions: Vec<Execution>,
+    #[serde(skip)]
+    max_executions: usize,
+    /// The slowest distinct raw queries in the record, slowest first,
+    /// up to `AggregateOptions::max_examples`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub examples: Vec<Example>,
+    #[serde(skip)]
+    max_examples: usize,
+    // running state for Welford's online variance algorithm, written to reports so that
+    // merging them does not depend on the rounded average and standard deviation
+    #[serde(default, rename = "welford_mean")]
+    mean: f64,
+    #[serde(default, rename = "welford_m2")]
+    m2: f64,
+    // distribution of the query times, kept for estimating percentiles in bounded memory and
+    // written to reports so that merging them keeps the percentiles of all runs
+    #[serde(default, skip_serializing_if = "TDigest::is_empty")]
+    digest: TDigest,
 }
 
 impl AggregateLogEntry {
-    fn new(query: String, query_time: i128) -> Self {
-        AggregateLogEntry {
+    fn new(query: String, entry: &LogEntry, options: AggregateOptions) -> Self {
+        let query_time = entry.query_time.whole_microseconds();
+        let mut executions = Vec::new();
+        if options.max_executions > 0 {
+            executions.push(Execution::new(entry));
+        }
+        let mut aggregate = AggregateLogEntry {
             query,
             count: 1,
             total_query_time: query_time,
             avg_query_time: query_time,
             max_query_time: query_time,
-        }
+            stddev_query_time: 0,
+            sample_values: Vec::new(),
+            slowest_raw_query: String::new(),
+            max_rows_examined: i64::from(entry.rows_examined),
+            max_rows_raw_query: options.keep_max_rows_query.then(|| entry.query.clone()),
+            executions,
+            max_executions: options.max_executions,
+            examples: Vec::new(),
+            max_examples: options.max_examples,
+            mean: query_time as f64,
+            m2: 0.0,
+            p50_query_time: query_time,
+            p90_query_time: query_time,
+            p95_query_time: query_time,
+            p99_query_time: query_time,
+            digest: {
+                let mut digest = TDigest::new();
+                digest.add(query_time as f64);
+                digest
+            },
+        };
+        aggregate.add_example(query_time, &entry.query);
+        aggregate
     }
 
-    fn update_with(&mut self, query_time: i128) {
+    fn update_with(&mut self, entry: &LogEntry) {
+        let query_time = entry.query_time.whole_microseconds();
+        if i64::from(entry.rows_examined) > self.max_rows_examined {
+            self.max_rows_examined = i64::from(entry.rows_examined);
+            if self.max_rows_raw_query.is_some() {
+                self.max_rows_raw_query = Some(entry.query.clone());
+            }
+        }
+        if self.executions.len() < self.max_executions {
+            self.executions.push(Execution::new(entry));
+        }
+        self.add_example(query_time, &entry.query);
         self.total_query_time += query_time;
         self.max_query_time = max(self.max_query_time, query_time);
-        self.avg_query_time =
-            (self.avg_query_time * (self.count as i128) + query_time) / (self.count as i128 + 1);
         self.count += 1;
+        self.avg_query_time = self.total_query_time / self.count as i128;
+
+        let delta = query_time as f64 - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (query_time as f64 - self.mean);
+        self.stddev_query_time = (self.m2 / self.count as f64).sqrt().round() as i128;
+        self.digest.add(query_time as f64);
+    }
+
+    /// The running mean and sum of squared deviations. For records from a report that was
+    /// written without them, they are reconstructed from the published statistics.
+    fn running_variance(&self) -> (f64, f64) {
+        if self.mean == 0.0 && self.avg_query_time != 0 {
+            let m2 = (self.stddev_query_time as f64).powi(2) * self.count as f64;
+            (self.avg_query_time as f64, m2)
+        } else {
+            (self.mean, self.m2)
+        }
+    }
+
+    /// The distribution of the query times. For records from a report that was written without
+    /// it, it is approximated by the published percentiles, each standing in for the executions
+    /// between it and the previous one.
+    fn take_distribution(&mut self) -> TDigest {
+        if !self.digest.is_empty() || self.count == 0 {
+            return std::mem::take(&mut self.digest);
+        }
+        let count = self.count as f64;
+        TDigest::from_centroids([
+            (self.p50_query_time as f64, 0.5 * count),
+            (self.p90_query_time as f64, 0.4 * count),
+            (self.p95_query_time as f64, 0.05 * count),
+            (self.p99_query_time as f64, 0.04 * count),
+            (self.max_query_time as f64, 0.01 * count),
+        ])
+    }
+
+    /// Combines the statistics of another record of the same query into this one
+    pub fn merge(&mut self, mut other: AggregateLogEntry) {
+        let other_distribution = other.take_distribution();
+        let (count, other_count) = (self.count as f64, other.count as f64);
+        let combined_count = count + other_count;
+
+        // Chan et al.'s method for combining the variances of two samples
+        let (mean, m2) = self.running_variance();
+        let (other_mean, other_m2) = other.running_variance();
+        let delta = other_mean - mean;
+        self.mean = mean + delta * other_count / combined_count;
+        self.m2 = m2 + other_m2 + delta * delta * count * other_count / combined_count;
+
+        if other.max_query_time > self.max_query_time {
+            self.sample_values = other.sample_values;
+            self.slowest_raw_query = other.slowest_raw_query;
+        }
+        if other.max_rows_examined > self.max_rows_examined {
+            self.max_rows_examined = other.max_rows_examined;
+            self.max_rows_raw_query = other.max_rows_raw_query;
+        }
+        let capacity = self.max_executions.saturating_sub(self.executions.len());
+        self.executions.extend(other.executions.into_iter().take(capacity));
+        for example in other.examples {
+            self.add_example(example.query_time, &example.query);
+        }
+        self.count += other.count;
+        self.total_query_time += other.total_query_time;
+        self.max_query_time = max(self.max_query_time, other.max_query_time);
+        self.avg_query_time = self.total_query_time / self.count as i128;
+        self.stddev_query_time = (self.m2 / combined_count).sqrt().round() as i128;
+        let mut digest = self.take_distribution();
+        digest.merge(&other_distribution);
+        self.digest = digest;
+        self.update_percentiles();
+    }
+
+    /// Keeps the raw query if it is among the slowest distinct ones seen so far. Queries that
+    /// occur repeatedly are only kept once, with the longest time they took.
+    fn add_example(&mut self, query_time: i128, query: &str) {
+        if self.max_examples == 0 {
+            return;
+        }
+        if let Some(example) = self.examples.iter_mut().find(|example| example.query == query) {
+            example.query_time = max(example.query_time, query_time);
+        } else if self.examples.len() < self.max_examples {
+            self.examples.push(Example { query_time, query: query.to_string() });
+        } else if self.examples.last().is_some_and(|fastest| fastest.query_time < query_time) {
+            self.examples.pop();
+            self.examples.push(Example { query_time, query: query.to_string() });
+        } else {
+            return;
+        }
+        self.examples.sort_by_key(|example| std::cmp::Reverse(example.query_time));
+    }
+
+    /// Estimates the query time below or at which the given percentage of queries fall.
+    /// Records read from a report do not know the distribution of their query times,
+    /// so the maximum is used instead.
+    pub fn percentile_query_time(&self, percentile: f64) -> i128 {
+        match self.digest.percentile(percentile) {
+            Some(estimate) => (estimate.round() as i128).min(self.max_query_time),
+            None => self.max_query_time,
+        }
+    }
+
+    /// Fills in the p50, p90, p95 and p99 fields from the distribution of the query times.
+    /// Records read from a report keep the values they were written with.
+    fn update_percentiles(&mut self) {
+        if self.digest.is_empty() {
+            return;
+        }
+        self.p50_query_time = self.percentile_query_time(50.0);
+        self.p90_query_time = self.percentile_query_time(90.0);
+        self.p95_query_time = self.percentile_query_time(95.0);
+        self.p99_query_time = self.percentile_query_time(99.0);
+    }
+
+    /// The raw text of the slowest query in this record
+    pub fn slowest_query(&self) -> &str {
+        match self.slowest_raw_query.as_str() {
+            "" => &self.query,
+            raw => raw,
+        }
+    }
+
+    /// `total_query_time * (1 + weight * ln(count))`, which ranks a query that is executed often
+    /// above a single execution of similar total time. With a weight of 0, this is the total time.
+    pub fn impact(&self, weight: f64) -> f64 {
+        self.total_query_time as f64 * (1.0 + weight * (self.count.max(1) as f64).ln())
+    }
+
+    fn sort_key(&self, order: SortOrder, impact_weight: f64) -> i128 {
+        match order {
+            SortOrder::Count => self.count as i128,
+            SortOrder::AvgTime => self.avg_query_time,
+            SortOrder::MaxTime => self.max_query_time,
+            SortOrder::TotalTime => self.total_query_time,
+            SortOrder::StddevTime => self.stddev_query_time,
+            SortOrder::P50Time => self.p50_query_time,
+            SortOrder::P90Time => self.p90_query_time,
+            SortOrder::P95Time => self.p95_query_time,
+            SortOrder::P99Time => self.p99_query_time,
+            SortOrder::Impact => self.impact(impact_weight) as i128,
+        }
     }
 }
 
-pub fn aggregate_entries(entries: Vec<LogEntry>) -> HashMap<String, AggregateLogEntry> {
+/// Selects the given percentile from a non-empty, sorted list of values by the nearest-rank method
+pub fn nearest_rank(sorted: &[i128], percentile: f64) -> i128 {
+    let rank = (percentile / 100.0 * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+/// The weight of the number of executions in the impact sort order
+pub const DEFAULT_IMPACT_WEIGHT: f64 = 1.0;
+
+/// Sorts the entries in ascending order by the primary sort order,
+/// breaking ties by the secondary one if given
+pub fn sort_aggregated(
+    entries: &mut [AggregateLogEntry],
+    order: Option<SortOrder>,
+    secondary_order: Option<SortOrder>,
+) {
+    sort_aggregated_with(entries, order, secondary_order, DEFAULT_IMPACT_WEIGHT);
+}
+
+/// Like `sort_aggregated`, with the given weight of the number of executions in the impact
+pub fn sort_aggregated_with(
+    entries: &mut [AggregateLogEntry],
+    order: Option<SortOrder>,
+    secondary_order: Option<SortOrder>,
+    impact_weight: f64,
+) {
+    if let Some(order) = order {
+        entries.sort_unstable_by_key(|e| {
+            (
+                e.sort_key(order, impact_weight),
+                secondary_order.map(|o| e.sort_key(o, impact_weight)),
+            )
+        });
+    }
+}
+
+/// Collects all queries that did not get a record of their own because `max_groups` was reached
+pub const OVERFLOW_GROUP: &str = "<other>";
+
+/// Returns the key under which to aggregate an entry, which is the overflow group if the entry
+/// would start a new group after the maximum number of groups has been reached
+fn group_key(
+    result: &HashMap<String, AggregateLogEntry>,
+    key: String,
+    options: AggregateOptions,
+) -> String {
+    let groups = result.len() - usize::from(result.contains_key(OVERFLOW_GROUP));
+    match options.max_groups {
+        Some(max) if groups >= max && !result.contains_key(&key) => OVERFLOW_GROUP.to_string(),
+        _ => key,
+    }
+}
+
+/// Fills in the percentiles of the finished records, so that they are estimated only once
+fn with_percentiles(
+    mut result: HashMap<String, AggregateLogEntry>,
+) -> HashMap<String, AggregateLogEntry> {
+    result.values_mut().for_each(AggregateLogEntry::update_percentiles);
+    result
+}
+
+pub fn aggregate_entries(
+    entries: Vec<LogEntry>,
+    options: AggregateOptions,
+) -> HashMap<String, AggregateLogEntry> {
     let mut result: HashMap<String, AggregateLogEntry> = HashMap::new();
     entries.into_iter().for_each(|entry| {
-        let query_time = entry.query_time.whole_microseconds();
-        if result.contains_key(&entry.query) {
-            result.get_mut(&entry.query).unwrap().update_with(query_time);
+        if let Some(aggregate) = result.get_mut(&entry.query) {
+            aggregate.update_with(&entry);
         } else {
-            result.insert(entry.query.clone(), AggregateLogEntry::new(entry.query, query_time));
+            let key = group_key(&result, entry.query.clone(), options);
+            match result.get_mut(&key) {
+                Some(overflow) => overflow.update_with(&entry),
+                None => {
+                    result.insert(key.clone(), AggregateLogEntry::new(key, &entry, options));
+                }
+            }
         }
     });
+    with_percentiles(result)
+}
+
+/// Aggregates entries by their raw query, keeping only those that occur at least `min_count` times
+pub fn aggregate_duplicates(
+    entries: Vec<LogEntry>,
+    min_count: i64,
+    options: AggregateOptions,
+) -> HashMap<String, AggregateLogEntry> {
+    let mut result = aggregate_entries(entries, options);
+    result.retain(|_, aggregate| aggregate.count >= min_count);
     result
 }
 
+/// Reads aggregated records from a report previously written with `--format ndjson`
+pub fn read_report(report: impl Read) -> Result<Vec<AggregateLogEntry>, String> {
+    let reader = BufReader::new(report);
+    let mut entries = Vec::new();
+    for (i, line) in reader.lines().enumerate() {
+        let line = line.map_err(|err| format!("Unable to read report: {}", err))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let entry = serde_json::from_str(&line)
+            .map_err(|err| format!("Invalid record in line {} of report: {}", i + 1, err))?;
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+/// Merges records from a previous report into the current ones, combining the records
+/// for the same query
+pub fn merge_aggregated(
+    entries: &mut HashMap<String, AggregateLogEntry>,
+    previous: Vec<AggregateLogEntry>,
+) {
+    for entry in previous {
+        if let Some(aggregate) = entries.get_mut(&entry.query) {
+            aggregate.merge(entry);
+        } else {
+            entries.insert(entry.query.clone(), entry);
+        }
+    }
+}
+
+/// Keeps only the records whose value for a time key, e.g. `SortOrder::P95Time` for the 95th
+/// percentile, is at least the given number of seconds
+pub fn retain_min_time(entries: &mut Vec<AggregateLogEntry>, key: SortOrder, min_seconds: f64) {
+    let min_time = (min_seconds * 1_000_000.0).round() as i128;
+    entries.retain(|entry| entry.sort_key(key, 0.0) >= min_time);
+}
+
+/// Drops the records whose key also occurs in a baseline report, so that only queries that
+/// newly appeared remain. The baseline has to be aggregated the same way as the current records.
+pub fn retain_new(
+    entries: &mut HashMap<String, AggregateLogEntry>,
+    baseline: &[AggregateLogEntry],
+) {
+    let known: HashSet<&str> = baseline.iter().map(|entry| entry.query.as_str()).collect();
+    entries.retain(|query, _| !known.contains(query.as_str()));
+}
+
+pub const SUCCESS_CLASS: &str = "success";
+
+/// Classifies an entry by how it ended: "killed", "errno <code>" or "success"
+pub fn error_class(entry: &LogEntry) -> String {
+    match (entry.killed.unwrap_or(0), entry.errno.unwrap_or(0)) {
+        (0, 0) => SUCCESS_CLASS.to_string(),
+        (0, errno) => format!("errno {}", errno),
+        _ => "killed".to_string(),
+    }
+}
+
+/// Separates the dimensions of a composite key, e.g. `alice | shop`
+const KEY_SEPARATOR: &str = " | ";
+
+/// Combines the values of the given dimensions of an entry into a single aggregation key.
+/// The cache is only consulted for the normalized query.
+pub fn composite_key(entry: &LogEntry, keys: &[GroupBy], cache: &NormalizeCache) -> String {
+    let values: Vec<String> = keys
+        .iter()
+        .map(|key| match key {
+            GroupBy::Fingerprint => cache.normalize(entry.clone()).normalized_query,
+            GroupBy::Shape => query_shape(&entry.query),
+            GroupBy::Error => error_class(entry),
+            GroupBy::Host => entry.host.clone(),
+            GroupBy::User => entry.user.clone(),
+            GroupBy::Schema => entry.database.clone().unwrap_or_else(|| UNKNOWN.to_string()),
+        })
+        .collect();
+    values.join(KEY_SEPARATOR)
+}
+
+/// Aggregates entries by the combination of the given dimensions
+pub fn aggregate_grouped(
+    entries: Vec<LogEntry>,
+    keys: &[GroupBy],
+    cache: &NormalizeCache,
+    options: AggregateOptions,
+) -> HashMap<String, AggregateLogEntry> {
+    let keyed: Vec<(String, LogEntry)> =
+        entries.into_par_iter().map(|entry| (composite_key(&entry, keys, cache), entry)).collect();
+    aggregate_by_key(keyed, options)
+}
+
+/// Aggregates entries by an arbitrary key that was computed for each of them beforehand
+pub fn aggregate_by_key(
+    entries: Vec<(String, LogEntry)>,
+    options: AggregateOptions,
+) -> HashMap<String, AggregateLogEntry> {
+    let mut result: HashMap<String, AggregateLogEntry> = HashMap::new();
+    entries.into_iter().for_each(|(key, entry)| {
+        let key = group_key(&result, key, options);
+        if let Some(aggregate) = result.get_mut(&key) {
+            aggregate.update_with(&entry);
+        } else {
+            result.insert(key.clone(), AggregateLogEntry::new(key, &entry, options));
+        }
+    });
+    with_percentiles(result)
+}
+
 pub fn aggregate_normalized(
     entries: Vec<NormalizedLogEntry>,
+    options: AggregateOptions,
 ) -> HashMap<String, AggregateLogEntry> {
     let mut result: HashMap<String, AggregateLogEntry> = HashMap::new();
     entries.into_iter().for_each(|entry| {
+        let key = group_key(&result, entry.normalized_query, options);
         let query_time = entry.entry.query_time.whole_microseconds();
-        if result.contains_key(&entry.normalized_query) {
-            result.get_mut(&entry.normalized_query).unwrap().update_with(query_time);
+        if let Some(aggregate) = result.get_mut(&key) {
+            let slowest = query_time > aggregate.max_query_time;
+            aggregate.update_with(&entry.entry);
+            if slowest {
+                aggregate.sample_values = entry.sample_values;
+                aggregate.slowest_raw_query = entry.entry.query;
+            }
         } else {
-            result.insert(
-                entry.normalized_query.clone(),
-                AggregateLogEntry::new(entry.normalized_query, query_time),
-            );
+            let mut aggregate = AggregateLogEntry::new(key.clone(), &entry.entry, options);
+            aggregate.sample_values = entry.sample_values;
+            aggregate.slowest_raw_query = entry.entry.query;
+            result.insert(key, aggregate);
         }
     });
-    result
+    with_percentiles(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use time::Duration;
+
+    use super::*;
+    use crate::format::write_json_line;
+    use crate::normalize::{normalize, NormalizeCache, NormalizeOptions};
+
+    fn entry(query: &str, query_time_ms: i64) -> LogEntry {
+        LogEntry {
+            user: "foo".to_string(),
+            host: "localhost".to_string(),
+            query_time: Duration::milliseconds(query_time_ms),
+            rows_sent: 1,
+            rows_examined: 1,
+            query: query.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn it_computes_the_standard_deviation() {
+        // population standard deviation of these values is exactly 2 seconds
+        let entries = [2, 4, 4, 4, 5, 5, 7, 9]
+            .into_iter()
+            .map(|seconds| entry("SELECT 1;", seconds * 1000))
+            .collect();
+
+        let aggregated = aggregate_entries(entries, AggregateOptions::default());
+        let group = aggregated.get("SELECT 1;").expect("we know this exists");
+        assert_eq!(8, group.count);
+        assert_eq!(5_000_000, group.avg_query_time);
+        assert_eq!(2_000_000, group.stddev_query_time);
+    }
+
+    #[test]
+    fn it_breaks_ties_with_the_secondary_sort_order() {
+        let entries = vec![
+            entry("SELECT 1;", 2000),
+            entry("SELECT 2;", 1000),
+            entry("SELECT 2;", 1000),
+            entry("SELECT 3;", 500),
+            entry("SELECT 3;", 500),
+            entry("SELECT 3;", 500),
+            entry("SELECT 3;", 500),
+            entry("SELECT 4;", 1000),
+        ];
+
+        let mut aggregated: Vec<AggregateLogEntry> =
+            aggregate_entries(entries, AggregateOptions::default()).into_values().collect();
+        sort_aggregated(&mut aggregated, Some(SortOrder::TotalTime), Some(SortOrder::Count));
+
+        let queries: Vec<&str> = aggregated.iter().map(|e| e.query.as_str()).collect();
+        assert_eq!(vec!["SELECT 4;", "SELECT 1;", "SELECT 2;", "SELECT 3;"], queries);
+    }
+
+    #[test]
+    fn it_ranks_frequent_queries_above_a_single_slow_spike_by_impact() {
+        // 100 executions of half a second each take 50 s in total, a single spike takes 60 s
+        let mut entries: Vec<LogEntry> = (0..100).map(|_| entry("SELECT frequent;", 500)).collect();
+        entries.push(entry("SELECT spike;", 60_000));
+        let mut aggregated: Vec<AggregateLogEntry> =
+            aggregate_entries(entries, AggregateOptions::default()).into_values().collect();
+        let first = |aggregated: &[AggregateLogEntry]| aggregated.last().unwrap().query.clone();
+
+        sort_aggregated(&mut aggregated, Some(SortOrder::TotalTime), None);
+        assert_eq!("SELECT spike;", first(&aggregated));
+
+        sort_aggregated(&mut aggregated, Some(SortOrder::Impact), None);
+        assert_eq!("SELECT frequent;", first(&aggregated));
+
+        // without any weight on the count, the impact is just the total time
+        sort_aggregated_with(&mut aggregated, Some(SortOrder::Impact), None, 0.0);
+        assert_eq!("SELECT spike;", first(&aggregated));
+    }
+
+    #[test]
+    fn it_aggregates_cached_and_uncached_normalization_identically() {
+        let entries: Vec<LogEntry> = (0..100)
+            .map(|i| entry(&format!("SELECT * FROM foo WHERE id = {};", i % 7), 10 * i))
+            .chain((0..50).map(|i| entry(&format!("DELETE FROM foo WHERE id = {};", i), 20)))
+            .collect();
+
+        let cache = NormalizeCache::default();
+        let cached = aggregate_normalized(
+            entries.iter().cloned().map(|entry| cache.normalize(entry)).collect(),
+            AggregateOptions::default(),
+        );
+        let options = NormalizeOptions::default();
+        let uncached = aggregate_normalized(
+            entries.into_iter().map(|entry| normalize(entry, &options)).collect(),
+            AggregateOptions::default(),
+        );
+
+        assert_eq!(2, cached.len());
+        assert_eq!(cached.len(), uncached.len());
+        for (key, expected) in uncached {
+            let actual = cached.get(&key).expect("all keys should be present");
+            assert_eq!(expected.count, actual.count);
+            assert_eq!(expected.total_query_time, actual.total_query_time);
+            assert_eq!(expected.max_query_time, actual.max_query_time);
+        }
+    }
+
+    #[test]
+    fn it_reports_zero_deviation_for_a_single_query() {
+        let aggregated =
+            aggregate_entries(vec![entry("SELECT 1;", 1500)], AggregateOptions::default());
+        let group = aggregated.get("SELECT 1;").expect("we know this exists");
+        assert_eq!(0, group.stddev_query_time);
+    }
+
+    #[test]
+    fn it_reports_raw_queries_above_the_duplicate_threshold() {
+        let entries = (0..5)
+            .map(|_| entry("SELECT * FROM foo WHERE id = 1;", 10))
+            .chain((0..2).map(|_| entry("SELECT * FROM foo WHERE id = 2;", 10)))
+            .chain([entry("SELECT * FROM bar;", 10)])
+            .collect();
+
+        let duplicates = aggregate_duplicates(entries, 3, AggregateOptions::default());
+        assert_eq!(1, duplicates.len());
+        let group = duplicates.get("SELECT * FROM foo WHERE id = 1;").expect("we know this exists");
+        assert_eq!(5, group.count);
+    }
+
+    #[test]
+    fn it_selects_groups_by_tail_latency() {
+        let entries = (0..18)
+            .map(|_| entry("SELECT 1;", 10))
+            .chain((0..2).map(|_| entry("SELECT 1;", 5000)))
+            .chain((0..20).map(|_| entry("SELECT 2;", 100)))
+            .collect();
+
+        let aggregated = aggregate_entries(entries, AggregateOptions::default());
+        let tail_heavy = aggregated.get("SELECT 1;").expect("we know this exists");
+        assert_eq!(10_000, tail_heavy.p50_query_time);
+        assert_eq!(5_000_000, tail_heavy.p95_query_time);
+
+        let mut selected: Vec<AggregateLogEntry> = aggregated.into_values().collect();
+        retain_min_time(&mut selected, SortOrder::P95Time, 2.0);
+        let queries: Vec<&str> = selected.iter().map(|e| e.query.as_str()).collect();
+        assert_eq!(vec!["SELECT 1;"], queries);
+    }
+
+    #[test]
+    fn it_rounds_the_minimum_p95_to_microseconds() {
+        let entries = vec![entry("SELECT 1;", 1000)];
+        let mut selected: Vec<AggregateLogEntry> =
+            aggregate_entries(entries, AggregateOptions::default()).into_values().collect();
+        selected[0].p95_query_time = 1_000_999;
+        // 1.001 * 1_000_000 is slightly less than 1_001_000 in floating point
+        retain_min_time(&mut selected, SortOrder::P95Time, 1.001);
+        assert!(selected.is_empty());
+    }
+
+    #[test]
+    fn it_selects_groups_by_any_percentile() {
+        let entries = (0..98)
+            .map(|_| entry("SELECT 1;", 10))
+            .chain((0..2).map(|_| entry("SELECT 1;", 5000)))
+            .chain((0..100).map(|_| entry("SELECT 2;", 100)))
+            .collect();
+        let aggregated: Vec<AggregateLogEntry> =
+            aggregate_entries(entries, AggregateOptions::default()).into_values().collect();
+
+        let queries = |key: SortOrder, min_seconds: f64| {
+            let mut selected = aggregated.clone();
+            retain_min_time(&mut selected, key, min_seconds);
+            selected.into_iter().map(|e| e.query).collect::<Vec<_>>()
+        };
+        assert_eq!(vec!["SELECT 2;"], queries(SortOrder::P50Time, 0.1));
+        assert_eq!(vec!["SELECT 2;"], queries(SortOrder::P90Time, 0.1));
+        assert_eq!(vec!["SELECT 1;"], queries(SortOrder::P99Time, 1.0));
+    }
+
+    #[test]
+    fn it_groups_entries_by_error() {
+        let failed = |errno: i32, killed: i32| LogEntry {
+            errno: Some(errno),
+            killed: Some(killed),
+            ..entry("UPDATE foo SET bar = 1;", 1000)
+        };
+        let entries = vec![
+            entry("SELECT 1;", 10),
+            failed(0, 0),
+            failed(1213, 0),
+            failed(1213, 0),
+            failed(1205, 0),
+            failed(1317, 1),
+        ];
+
+        let keyed = entries.into_iter().map(|e| (error_class(&e), e)).collect();
+        let aggregated = aggregate_by_key(keyed, AggregateOptions::default());
+        let count = |key: &str| aggregated.get(key).map_or(0, |group| group.count);
+        assert_eq!(4, aggregated.len());
+        assert_eq!(2, count(SUCCESS_CLASS));
+        assert_eq!(2, count("errno 1213"));
+        assert_eq!(1, count("errno 1205"));
+        assert_eq!(1, count("killed"));
+    }
+
+    #[test]
+    fn it_groups_entries_by_composite_keys() {
+        let run = |user: &str, database: Option<&str>| LogEntry {
+            user: user.to_string(),
+            database: database.map(String::from),
+            ..entry("SELECT 1;", 1000)
+        };
+        let entries = vec![
+            run("alice", Some("shop")),
+            run("alice", Some("shop")),
+            run("alice", Some("crm")),
+            run("bob", Some("shop")),
+            run("bob", None),
+        ];
+
+        let keys = [GroupBy::User, GroupBy::Schema];
+        let aggregated = aggregate_grouped(
+            entries,
+            &keys,
+            &NormalizeCache::default(),
+            AggregateOptions::default(),
+        );
+        let count = |key: &str| aggregated.get(key).map_or(0, |group| group.count);
+        assert_eq!(4, aggregated.len());
+        assert_eq!(2, count("alice | shop"));
+        assert_eq!(1, count("alice | crm"));
+        assert_eq!(1, count("bob | shop"));
+        assert_eq!(1, count("bob | <unknown>"));
+    }
+
+    #[test]
+    fn it_keeps_the_sample_values_of_the_slowest_query() {
+        let options = NormalizeOptions { capture_sample_values: true, ..Default::default() };
+        let entries = vec![
+            entry("SELECT * FROM foo WHERE name = 'a' AND id > 1;", 100),
+            entry("SELECT * FROM foo WHERE name = 'b' AND id > 2;", 300),
+            entry("SELECT * FROM foo WHERE name = 'c' AND id > 3;", 200),
+        ];
+
+        let aggregated = aggregate_normalized(
+            entries.into_iter().map(|entry| normalize(entry, &options)).collect(),
+            AggregateOptions::default(),
+        );
+        let group = aggregated.values().next().expect("there is exactly one group");
+        assert_eq!(3, group.count);
+        assert_eq!(vec!["'b'", "2"], group.sample_values);
+    }
+
+    #[test]
+    fn it_keeps_the_query_that_examined_the_most_rows() {
+        let examining = |query: &str, query_time_ms: i64, rows_examined: i32| LogEntry {
+            rows_examined,
+            ..entry(query, query_time_ms)
+        };
+        let entries = vec![
+            examining("SELECT * FROM foo WHERE id > 1;", 100, 500),
+            examining("SELECT * FROM foo WHERE id > 2;", 300, 20),
+            examining("SELECT * FROM foo WHERE id > 3;", 200, 90_000),
+            examining("SELECT * FROM foo WHERE id > 4;", 50, 1000),
+        ];
+
+        let options = NormalizeOptions::default();
+        let aggregated = aggregate_normalized(
+            entries.into_iter().map(|entry| normalize(entry, &options)).collect(),
+            AggregateOptions { keep_max_rows_query: true, ..Default::default() },
+        );
+        let group = aggregated.values().next().expect("there is exactly one group");
+        assert_eq!(90_000, group.max_rows_examined);
+        assert_eq!(Some("SELECT * FROM foo WHERE id > 3;"), group.max_rows_raw_query.as_deref());
+        assert_eq!("SELECT * FROM foo WHERE id > 2;", group.slowest_raw_query);
+    }
+
+    #[test]
+    fn it_nests_a_capped_number_of_executions() {
+        let entries: Vec<LogEntry> = (1..=5).map(|i| entry("SELECT 1;", 100 * i)).collect();
+        let options = AggregateOptions { max_executions: 3, ..Default::default() };
+        let aggregated = aggregate_entries(entries.clone(), options);
+
+        let mut json = Vec::new();
+        write_json_line(&mut json, aggregated.get("SELECT 1;").expect("we know this exists"));
+        let record: serde_json::Value = serde_json::from_slice(&json).unwrap();
+        assert_eq!(5, record["count"]);
+        let executions = record["executions"].as_array().expect("executions are included");
+        assert_eq!(3, executions.len());
+        assert_eq!(300_000, executions[2]["query_time_us"]);
+        assert_eq!("foo", executions[2]["user"]);
+
+        let mut json = Vec::new();
+        let aggregated = aggregate_entries(entries, AggregateOptions::default());
+        write_json_line(&mut json, aggregated.get("SELECT 1;").expect("we know this exists"));
+        let record: serde_json::Value = serde_json::from_slice(&json).unwrap();
+        assert!(record.get("executions").is_none());
+    }
+
+    #[test]
+    fn it_collects_excess_groups_in_an_overflow_group() {
+        let entries = vec![
+            entry("SELECT 1;", 1000),
+            entry("SELECT 2;", 1000),
+            entry("SELECT 3;", 1000),
+            entry("SELECT 1;", 1000),
+            entry("SELECT 4;", 1000),
+            entry("SELECT 3;", 1000),
+        ];
+
+        let aggregated = aggregate_entries(
+            entries,
+            AggregateOptions { max_groups: Some(2), ..Default::default() },
+        );
+        let count = |key: &str| aggregated.get(key).map_or(0, |group| group.count);
+        assert_eq!(3, aggregated.len());
+        assert_eq!(2, count("SELECT 1;"));
+        assert_eq!(1, count("SELECT 2;"));
+        assert_eq!(3, count(OVERFLOW_GROUP));
+    }
+
+    #[test]
+    fn it_only_retains_queries_missing_from_the_baseline() {
+        let baseline = aggregate_entries(
+            vec![entry("SELECT * FROM foo WHERE id = ?;", 1000), entry("SELECT 2;", 1000)],
+            AggregateOptions::default(),
+        );
+        let mut report = Vec::new();
+        baseline.values().for_each(|e| write_json_line(&mut report, e));
+
+        let mut current = aggregate_entries(
+            vec![
+                entry("SELECT * FROM foo WHERE id = ?;", 2000),
+                entry("SELECT * FROM bar WHERE id = ?;", 3000),
+            ],
+            AggregateOptions::default(),
+        );
+        retain_new(&mut current, &read_report(report.as_slice()).unwrap());
+
+        let queries: Vec<&String> = current.keys().collect();
+        assert_eq!(vec!["SELECT * FROM bar WHERE id = ?;"], queries);
+    }
+
+    #[test]
+    fn it_merges_previous_reports() {
+        let previous = aggregate_entries(
+            vec![
+                entry("SELECT 1;", 2000),
+                entry("SELECT 1;", 4000),
+                entry("SELECT 1;", 4000),
+                entry("SELECT 1;", 4000),
+                entry("SELECT 2;", 1000),
+            ],
+            AggregateOptions::default(),
+        );
+        let mut report = Vec::new();
+        previous.values().for_each(|e| write_json_line(&mut report, e));
+
+        let mut current = aggregate_entries(
+            vec![
+                entry("SELECT 1;", 5000),
+                entry("SELECT 1;", 5000),
+                entry("SELECT 1;", 7000),
+                entry("SELECT 1;", 9000),
+                entry("SELECT 3;", 3000),
+            ],
+            AggregateOptions::default(),
+        );
+        merge_aggregated(&mut current, read_report(report.as_slice()).unwrap());
+
+        assert_eq!(3, current.len());
+        let merged = current.get("SELECT 1;").expect("we know this exists");
+        assert_eq!(8, merged.count);
+        assert_eq!(40_000_000, merged.total_query_time);
+        assert_eq!(5_000_000, merged.avg_query_time);
+        assert_eq!(9_000_000, merged.max_query_time);
+        assert_eq!(2_000_000, merged.stddev_query_time);
+        // the percentiles of 2, 4, 4, 4, 5, 5, 7 and 9 seconds
+        assert_eq!(4_000_000, merged.p50_query_time);
+        assert_eq!(9_000_000, merged.p90_query_time);
+        assert_eq!(9_000_000, merged.p95_query_time);
+        assert_eq!(9_000_000, merged.p99_query_time);
+        assert_eq!(1, current.get("SELECT 2;").expect("we know this exists").count);
+    }
+
+    #[test]
+    fn it_keeps_the_percentiles_of_reports_written_without_a_distribution() {
+        let previous = aggregate_entries(
+            (0..9_500)
+                .map(|_| entry("SELECT 1;", 100))
+                .chain((0..500).map(|_| entry("SELECT 1;", 10_000)))
+                .collect(),
+            AggregateOptions::default(),
+        );
+        let mut record = serde_json::to_value(&previous["SELECT 1;"]).unwrap();
+        record.as_object_mut().unwrap().remove("digest");
+        let report = serde_json::to_vec(&record).unwrap();
+
+        let mut current =
+            aggregate_entries(vec![entry("SELECT 1;", 100)], AggregateOptions::default());
+        merge_aggregated(&mut current, read_report(report.as_slice()).unwrap());
+
+        let merged = current.get("SELECT 1;").expect("we know this exists");
+        assert_eq!(10_001, merged.count);
+        assert_eq!(100_000, merged.p50_query_time);
+        assert_eq!(10_000_000, merged.p99_query_time);
+    }
+
+    #[test]
+    fn it_keeps_the_running_variance_of_merged_records_in_reports() {
+        let previous = aggregate_entries(
+            vec![entry("SELECT 1;", 2000), entry("SELECT 1;", 4000), entry("SELECT 1;", 4000)],
+            AggregateOptions::default(),
+        );
+        let mut report = Vec::new();
+        previous.values().for_each(|e| write_json_line(&mut report, e));
+
+        // a record that only occurs in a report has to survive being written to a report again
+        let mut cumulative = HashMap::new();
+        merge_aggregated(&mut cumulative, read_report(report.as_slice()).unwrap());
+        let mut report = Vec::new();
+        cumulative.values().for_each(|e| write_json_line(&mut report, e));
+        let record: serde_json::Value = serde_json::from_slice(&report).unwrap();
+        assert!(record["welford_m2"].as_f64().is_some_and(|m2| m2 > 0.0));
+        assert!(record["digest"]["centroids"].as_array().is_some_and(|c| c.len() == 3));
+
+        let mut current = aggregate_entries(
+            vec![
+                entry("SELECT 1;", 4000),
+                entry("SELECT 1;", 5000),
+                entry("SELECT 1;", 5000),
+                entry("SELECT 1;", 7000),
+                entry("SELECT 1;", 9000),
+            ],
+            AggregateOptions::default(),
+        );
+        merge_aggregated(&mut current, read_report(report.as_slice()).unwrap());
+
+        let merged = current.get("SELECT 1;").expect("we know this exists");
+        assert_eq!(8, merged.count);
+        assert_eq!(5_000_000, merged.avg_query_time);
+        assert_eq!(2_000_000, merged.stddev_query_time);
+    }
+
+    #[test]
+    fn it_sorts_records_from_a_report_by_their_stored_percentiles() {
+        let previous = aggregate_entries(
+            (0..19)
+                .map(|_| entry("SELECT 1;", 10))
+                .chain([entry("SELECT 1;", 9000)])
+                .chain((0..20).map(|_| entry("SELECT 2;", 100)))
+                .collect(),
+            AggregateOptions::default(),
+        );
+        let mut report = Vec::new();
+        previous.values().for_each(|e| write_json_line(&mut report, e));
+
+        // the maximum of the first query is higher, but its p95 is lower than that of the second
+        let mut records = read_report(report.as_slice()).unwrap();
+        sort_aggregated(&mut records, Some(SortOrder::P95Time), None);
+        let queries: Vec<&str> = records.iter().map(|e| e.query.as_str()).collect();
+        assert_eq!(vec!["SELECT 1;", "SELECT 2;"], queries);
+        assert_eq!(100_000, records[1].p95_query_time);
+    }
 }
@@ -0,0 +1,117 @@
+use std::fmt;
+use std::fs;
+use std::time::{Duration, Instant};
+
+/// The phases of processing a log whose duration is measured separately
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Phase {
+    Parsing,
+    Filtering,
+    Normalization,
+    Aggregation,
+}
+
+impl Phase {
+    const ALL: [Phase; 4] =
+        [Phase::Parsing, Phase::Filtering, Phase::Normalization, Phase::Aggregation];
+
+    fn name(self) -> &'static str {
+        match self {
+            Phase::Parsing => "parsing",
+            Phase::Filtering => "filtering",
+            Phase::Normalization => "normalization",
+            Phase::Aggregation => "aggregation",
+        }
+    }
+}
+
+/// Accumulates the wall-clock time spent in each phase. Phases that did not run for the
+/// requested report are listed with zero time.
+pub struct Benchmark {
+    start: Instant,
+    durations: [Duration; Phase::ALL.len()],
+}
+
+impl Benchmark {
+    pub fn new() -> Self {
+        Benchmark { start: Instant::now(), durations: Default::default() }
+    }
+
+    /// Runs the given function and adds the time it took to the phase
+    pub fn time<T>(&mut self, phase: Phase, f: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let result = f();
+        self.durations[phase as usize] += start.elapsed();
+        result
+    }
+
+    pub fn duration(&self, phase: Phase) -> Duration {
+        self.durations[phase as usize]
+    }
+}
+
+impl Default for Benchmark {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Display for Benchmark {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Wall-clock time per phase:")?;
+        for phase in Phase::ALL {
+            writeln!(f, "  {:<15}{:>10.3} s", phase.name(), self.duration(phase).as_secs_f64())?;
+        }
+        write!(f, "  {:<15}{:>10.3} s", "total", self.start.elapsed().as_secs_f64())
+    }
+}
+
+/// The peak resident memory of this process in bytes, where the operating system reports it.
+/// On Linux, this is the high water mark `VmHWM` from /proc/self/status.
+pub fn peak_memory() -> Option<u64> {
+    let status = fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmHWM:"))?;
+    let kib: u64 =
+        line.trim_start_matches("VmHWM:").trim().trim_end_matches("kB").trim().parse().ok()?;
+    Some(kib * 1024)
+}
+
+/// Describes the peak resident memory for the diagnostic output
+pub fn memory_report() -> String {
+    match peak_memory() {
+        Some(bytes) => format!("Peak resident memory: {:.1} MiB", bytes as f64 / (1024.0 * 1024.0)),
+        None => "Peak resident memory: not available on this platform".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_reports_all_phases() {
+        let mut benchmark = Benchmark::new();
+        let parsed = benchmark.time(Phase::Parsing, || {
+            std::thread::sleep(Duration::from_millis(5));
+            42
+        });
+        assert_eq!(42, parsed);
+        assert!(benchmark.duration(Phase::Parsing) >= Duration::from_millis(5));
+        assert_eq!(Duration::ZERO, benchmark.duration(Phase::Aggregation));
+
+        let report = benchmark.to_string();
+        for name in ["parsing", "filtering", "normalization", "aggregation", "total"] {
+            assert!(report.contains(&format!("  {} ", name)), "{} missing from {}", name, report);
+        }
+    }
+
+    #[test]
+    fn it_reports_the_peak_memory() {
+        let report = memory_report();
+        assert!(report.starts_with("Peak resident memory: "), "{}", report);
+        if cfg!(target_os = "linux") {
+            assert!(peak_memory().is_some_and(|bytes| bytes > 0));
+            assert!(report.ends_with(" MiB"), "{}", report);
+        }
+    }
+}
@@ -0,0 +1,249 @@
+use std::fmt;
+
+use ahash::HashMap;
+use rayon::prelude::*;
+use serde::Serialize;
+
+use crate::aggregate::{aggregate_normalized, AggregateLogEntry, AggregateOptions};
+use crate::log_parser::LogEntry;
+use crate::normalize::{fingerprint, NormalizeCache, NormalizeOptions};
+
+/// Changes of the average query time by less than this fraction are considered noise
+const SIGNIFICANT_CHANGE: f64 = 0.1;
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Change {
+    Regression,
+    Improvement,
+    Unchanged,
+    New,
+    Disappeared,
+}
+
+/// The statistics of one normalized query in a baseline log and the current one
+#[derive(Debug, Serialize)]
+pub struct Comparison {
+    pub query: String,
+    pub fingerprint: String,
+    pub change: Change,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub baseline: Option<AggregateLogEntry>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub current: Option<AggregateLogEntry>,
+}
+
+impl Comparison {
+    /// How much more (or less, if negative) time the query took in total in the current log
+    pub fn total_query_time_delta(&self) -> i128 {
+        let total =
+            |entry: &Option<AggregateLogEntry>| entry.as_ref().map_or(0, |e| e.total_query_time);
+        total(&self.current) - total(&self.baseline)
+    }
+
+    /// The relative change of the total query time, if the query occurs in both logs
+    pub fn total_query_time_change(&self) -> Option<f64> {
+        let (baseline, current) = (self.baseline.as_ref()?, self.current.as_ref()?);
+        Some(relative_change(baseline.total_query_time, current.total_query_time))
+    }
+}
+
+impl fmt::Display for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let seconds = |usec: i128| usec as f64 / 1_000_000.0;
+        match (&self.baseline, &self.current) {
+            (Some(baseline), Some(current)) => writeln!(
+                f,
+                "count {} -> {}, total {:.3} s -> {:.3} s, avg {:.3} s -> {:.3} s ({:+.1}%), \
+                max {:.3} s -> {:.3} s",
+                baseline.count,
+                current.count,
+                seconds(baseline.total_query_time),
+                seconds(current.total_query_time),
+                seconds(baseline.avg_query_time),
+                seconds(current.avg_query_time),
+                100.0 * relative_change(baseline.avg_query_time, current.avg_query_time),
+                seconds(baseline.max_query_time),
+                seconds(current.max_query_time),
+            )?,
+            (Some(entry), None) | (None, Some(entry)) => writeln!(
+                f,
+                "count {}, total {:.3} s, avg {:.3} s, max {:.3} s",
+                entry.count,
+                seconds(entry.total_query_time),
+                seconds(entry.avg_query_time),
+                seconds(entry.max_query_time),
+            )?,
+            (None, None) => (),
+        }
+        write!(f, "{}", self.query)
+    }
+}
+
+fn relative_change(before: i128, after: i128) -> f64 {
+    (after - before) as f64 / before.max(1) as f64
+}
+
+/// Normalizes and aggregates the entries of both logs, and compares them by query
+pub fn compare_logs(
+    baseline: Vec<LogEntry>,
+    current: Vec<LogEntry>,
+    options: NormalizeOptions,
+) -> Vec<Comparison> {
+    let cache = NormalizeCache::new(options);
+    let aggregate = |entries: Vec<LogEntry>| {
+        let normalized = entries.into_par_iter().map(|entry| cache.normalize(entry)).collect();
+        aggregate_normalized(normalized, AggregateOptions::default())
+    };
+    let baseline = aggregate(baseline);
+    compare(baseline, aggregate(current))
+}
+
+/// Joins the records of both reports by the fingerprint of their query and classifies the
+/// change of each. The result is ordered by the change in total query time, largest first.
+pub fn compare(
+    baseline: HashMap<String, AggregateLogEntry>,
+    current: HashMap<String, AggregateLogEntry>,
+) -> Vec<Comparison> {
+    let mut baseline: HashMap<String, AggregateLogEntry> =
+        baseline.into_values().map(|entry| (fingerprint(&entry.query), entry)).collect();
+
+    let mut comparisons: Vec<Comparison> = Vec::with_capacity(current.len());
+    for current in current.into_values() {
+        let fingerprint = fingerprint(&current.query);
+        let change = match baseline.get(&fingerprint) {
+            None => Change::New,
+            Some(baseline) => {
+                let change = relative_change(baseline.avg_query_time, current.avg_query_time);
+                if change >= SIGNIFICANT_CHANGE {
+                    Change::Regression
+                } else if change <= -SIGNIFICANT_CHANGE {
+                    Change::Improvement
+                } else {
+                    Change::Unchanged
+                }
+            }
+        };
+        comparisons.push(Comparison {
+            query: current.query.clone(),
+            baseline: baseline.remove(&fingerprint),
+            fingerprint,
+            change,
+            current: Some(current),
+        });
+    }
+    comparisons.extend(baseline.into_iter().map(|(fingerprint, baseline)| Comparison {
+        query: baseline.query.clone(),
+        fingerprint,
+        change: Change::Disappeared,
+        baseline: Some(baseline),
+        current: None,
+    }));
+
+    comparisons.sort_by_key(|comparison| -comparison.total_query_time_delta().abs());
+    comparisons
+}
+
+/// Selects the queries occurring in both logs whose total query time grew by more than
+/// the given percentage
+pub fn regressed_beyond(comparisons: &[Comparison], percent: f64) -> Vec<&Comparison> {
+    comparisons
+        .iter()
+        .filter(|c| c.total_query_time_change().is_some_and(|change| 100.0 * change > percent))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use indoc::indoc;
+
+    use super::*;
+    use crate::log_parser::{parse_log, ParseOptions};
+
+    const BASELINE: &[u8] = indoc!(
+        b"
+        # Time: 2021-05-11T07:00:00.000000Z
+        # User@Host: foo[foo] @  [127.0.0.1]  Id: 1
+        # Query_time: 1.000000  Lock_time: 0.000000 Rows_sent: 1  Rows_examined: 1
+        SELECT * FROM foo WHERE id = 1;
+        # Time: 2021-05-11T07:00:01.000000Z
+        # User@Host: foo[foo] @  [127.0.0.1]  Id: 1
+        # Query_time: 4.000000  Lock_time: 0.000000 Rows_sent: 1  Rows_examined: 1
+        SELECT * FROM bar WHERE id = 1;
+        # Time: 2021-05-11T07:00:02.000000Z
+        # User@Host: foo[foo] @  [127.0.0.1]  Id: 1
+        # Query_time: 2.000000  Lock_time: 0.000000 Rows_sent: 1  Rows_examined: 1
+        SELECT * FROM baz WHERE id = 1;
+        # Time: 2021-05-11T07:00:03.000000Z
+        # User@Host: foo[foo] @  [127.0.0.1]  Id: 1
+        # Query_time: 1.000000  Lock_time: 0.000000 Rows_sent: 1  Rows_examined: 1
+        DELETE FROM old WHERE id = 1;
+    "
+    );
+
+    const CURRENT: &[u8] = indoc!(
+        b"
+        # Time: 2021-05-12T07:00:00.000000Z
+        # User@Host: foo[foo] @  [127.0.0.1]  Id: 1
+        # Query_time: 3.000000  Lock_time: 0.000000 Rows_sent: 1  Rows_examined: 1
+        SELECT * FROM foo WHERE id = 2;
+        # Time: 2021-05-12T07:00:01.000000Z
+        # User@Host: foo[foo] @  [127.0.0.1]  Id: 1
+        # Query_time: 1.000000  Lock_time: 0.000000 Rows_sent: 1  Rows_examined: 1
+        SELECT * FROM bar WHERE id = 2;
+        # Time: 2021-05-12T07:00:02.000000Z
+        # User@Host: foo[foo] @  [127.0.0.1]  Id: 1
+        # Query_time: 2.050000  Lock_time: 0.000000 Rows_sent: 1  Rows_examined: 1
+        SELECT * FROM baz WHERE id = 2;
+        # Time: 2021-05-12T07:00:03.000000Z
+        # User@Host: foo[foo] @  [127.0.0.1]  Id: 1
+        # Query_time: 0.500000  Lock_time: 0.000000 Rows_sent: 1  Rows_examined: 1
+        UPDATE new SET a = 1;
+    "
+    );
+
+    #[test]
+    fn it_reports_regressions_and_improvements() {
+        let options = ParseOptions::default();
+        let comparisons = compare_logs(
+            parse_log(BASELINE, &options).entries,
+            parse_log(CURRENT, &options).entries,
+            NormalizeOptions::default(),
+        );
+
+        let changes: Vec<Change> = comparisons.iter().map(|c| c.change).collect();
+        assert_eq!(
+            vec![
+                Change::Improvement,
+                Change::Regression,
+                Change::Disappeared,
+                Change::New,
+                Change::Unchanged
+            ],
+            changes
+        );
+        assert!(comparisons[0].query.contains("bar"));
+        assert!(comparisons[1].query.contains("foo"));
+        assert!(comparisons[2].query.contains("old"));
+        assert!(comparisons[3].query.contains("new"));
+        assert_eq!(-3_000_000, comparisons[0].total_query_time_delta());
+        assert_eq!(2_000_000, comparisons[1].total_query_time_delta());
+    }
+
+    #[test]
+    fn it_detects_regressions_beyond_a_threshold() {
+        let entry = |query: &str, query_time_ms: i64| LogEntry {
+            query: query.to_string(),
+            query_time: time::Duration::milliseconds(query_time_ms),
+            ..Default::default()
+        };
+        let baseline = vec![entry("SELECT * FROM foo;", 1000), entry("SELECT * FROM bar;", 1000)];
+        let current = vec![entry("SELECT * FROM foo;", 1500), entry("SELECT * FROM baz;", 5000)];
+        let comparisons = compare_logs(baseline, current, NormalizeOptions::default());
+
+        let regressions = regressed_beyond(&comparisons, 25.0);
+        assert_eq!(1, regressions.len());
+        assert_eq!(Some(0.5), regressions[0].total_query_time_change());
+        assert!(regressed_beyond(&comparisons, 75.0).is_empty());
+    }
+}
@@ -0,0 +1,105 @@
+use std::path::PathBuf;
+use std::{env, fs};
+
+use serde::Deserialize;
+
+use crate::opt::{Opt, OutputFormat, SortOrder};
+
+/// Default options read from a TOML file. Options given on the command line always take
+/// precedence over the ones from the config file; in particular, any `--filter` argument causes
+/// all filters from the config file to be ignored.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    pub limit: Option<usize>,
+    pub order: Option<SortOrder>,
+    pub format: Option<OutputFormat>,
+    #[serde(default)]
+    pub filters: Vec<String>,
+}
+
+impl Config {
+    /// Loads the config from the given path or, if none is given, from the default location.
+    /// A missing file is only an error if its path was given explicitly.
+    pub fn load(path: Option<&str>) -> Result<Config, String> {
+        let (path, required) = match path {
+            Some(path) => (PathBuf::from(path), true),
+            None => match default_path() {
+                Some(path) => (path, false),
+                None => return Ok(Config::default()),
+            },
+        };
+
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(_) if !required => return Ok(Config::default()),
+            Err(err) => {
+                return Err(format!("Unable to read config file '{}': {}", path.display(), err))
+            }
+        };
+
+        toml::from_str(&contents)
+            .map_err(|err| format!("Invalid config file '{}': {}", path.display(), err))
+    }
+
+    pub fn apply_to(self, opt: &mut Opt) {
+        opt.limit = opt.limit.or(self.limit);
+        opt.order = opt.order.or(self.order);
+        opt.format = opt.format.or(self.format);
+        if opt.filters.is_empty() {
+            opt.filters = self.filters;
+        }
+    }
+}
+
+fn default_path() -> Option<PathBuf> {
+    env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .map(|dir| dir.join("myslowlog").join("config.toml"))
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+    use indoc::indoc;
+
+    use super::*;
+
+    fn config() -> Config {
+        toml::from_str(indoc!(
+            r#"
+            limit = 20
+            order = "total-time"
+            format = "ndjson"
+            filters = ["user!=monitoring"]
+            "#
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn it_applies_config_values() {
+        let mut opt = Opt::parse_from(["test"]);
+        config().apply_to(&mut opt);
+        assert_eq!(20, opt.limit());
+        assert!(matches!(opt.order, Some(SortOrder::TotalTime)));
+        assert_eq!(OutputFormat::Ndjson, opt.format());
+        assert_eq!(vec!["user!=monitoring"], opt.filters);
+    }
+
+    #[test]
+    fn it_prefers_command_line_options() {
+        let mut opt = Opt::parse_from(["test", "--limit", "5", "-o", "count", "-Fuser=foo"]);
+        config().apply_to(&mut opt);
+        assert_eq!(5, opt.limit());
+        assert!(matches!(opt.order, Some(SortOrder::Count)));
+        assert_eq!(vec!["user=foo"], opt.filters);
+    }
+
+    #[test]
+    fn it_rejects_unknown_keys() {
+        let result: Result<Config, _> = toml::from_str("colour = \"blue\"");
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,82 @@
+use std::io::{self, Cursor, Read, Seek, SeekFrom};
+
+use flate2::read::MultiGzDecoder;
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+/// Sniffs the first bytes of the input and transparently decompresses it
+/// if it is a gzip or zstd stream. Other input is passed through unchanged.
+pub fn decompress(mut input: impl Read + 'static) -> io::Result<Box<dyn Read>> {
+    // Read the magic bytes with `take` rather than a single read call,
+    // since a pipe may deliver fewer bytes than requested at first.
+    let mut magic = Vec::with_capacity(ZSTD_MAGIC.len());
+    input.by_ref().take(ZSTD_MAGIC.len() as u64).read_to_end(&mut magic)?;
+    let is_gzip = magic.starts_with(&GZIP_MAGIC);
+    let is_zstd = magic.starts_with(&ZSTD_MAGIC);
+
+    let input = Cursor::new(magic).chain(input);
+    if is_gzip {
+        Ok(Box::new(MultiGzDecoder::new(input)))
+    } else if is_zstd {
+        Ok(Box::new(zstd::stream::read::Decoder::new(input)?))
+    } else {
+        Ok(Box::new(input))
+    }
+}
+
+/// Checks whether a seekable input is a gzip or zstd stream, leaving it at its start
+pub fn is_compressed(input: &mut (impl Read + Seek)) -> io::Result<bool> {
+    let mut magic = Vec::with_capacity(ZSTD_MAGIC.len());
+    input.by_ref().take(ZSTD_MAGIC.len() as u64).read_to_end(&mut magic)?;
+    input.seek(SeekFrom::Start(0))?;
+    Ok(magic.starts_with(&GZIP_MAGIC) || magic.starts_with(&ZSTD_MAGIC))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use indoc::indoc;
+
+    use super::*;
+    use crate::log_parser::{parse_log, ParseOptions};
+
+    const LOG: &[u8] = indoc!(
+        b"
+        # Time: 2019-07-30T13:01:34.887103Z
+        # User@Host: foo[bar] @  [127.0.0.1]  Id: 1337
+        # Query_time: 1.289039  Lock_time: 0.000061 Rows_sent: 50000  Rows_examined: 100000
+        SELECT * FROM baz;
+    "
+    );
+
+    fn parse_queries(input: Vec<u8>) -> Vec<String> {
+        let reader = decompress(Cursor::new(input)).unwrap();
+        parse_log(reader, &ParseOptions::default()).entries.into_iter().map(|e| e.query).collect()
+    }
+
+    #[test]
+    fn it_detects_gzip_input() {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(LOG).unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        assert_eq!(vec!["SELECT * FROM baz;"], parse_queries(compressed));
+    }
+
+    #[test]
+    fn it_detects_zstd_input() {
+        let compressed = zstd::encode_all(LOG, 0).unwrap();
+
+        assert_eq!(vec!["SELECT * FROM baz;"], parse_queries(compressed));
+    }
+
+    #[test]
+    fn it_passes_uncompressed_input_through() {
+        assert_eq!(vec!["SELECT * FROM baz;"], parse_queries(LOG.to_vec()));
+        assert!(parse_queries(Vec::new()).is_empty());
+    }
+}
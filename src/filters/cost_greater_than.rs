@@ -0,0 +1,106 @@
+use std::str::FromStr;
+
+use crate::filters::Filter;
+use crate::log_parser::LogEntry;
+
+/// The weights of the terms of the cost score. By default, one second of query time, one
+/// second of lock time and one million examined rows each add one point to the score.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct CostWeights {
+    pub query_time: f64,
+    pub rows_examined: f64,
+    pub lock_time: f64,
+}
+
+impl Default for CostWeights {
+    fn default() -> Self {
+        CostWeights { query_time: 1.0, rows_examined: 1e-6, lock_time: 1.0 }
+    }
+}
+
+impl CostWeights {
+    /// `query_time_s * w1 + rows_examined * w2 + lock_time_s * w3`
+    pub fn score(&self, log_entry: &LogEntry) -> f64 {
+        log_entry.query_time.as_seconds_f64() * self.query_time
+            + log_entry.rows_examined as f64 * self.rows_examined
+            + log_entry.lock_time.as_seconds_f64() * self.lock_time
+    }
+}
+
+/// Parses three comma-separated weights for query time, rows examined and lock time,
+/// e.g. `1,0.000001,1`
+impl FromStr for CostWeights {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let error = || format!("Expected three comma-separated cost weights, found '{}'", value);
+        let weights: Vec<f64> = value
+            .split(',')
+            .map(|weight| weight.trim().parse::<f64>().map_err(|_err| error()))
+            .collect::<Result<_, _>>()?;
+        match weights[..] {
+            [query_time, rows_examined, lock_time] => {
+                Ok(CostWeights { query_time, rows_examined, lock_time })
+            }
+            _ => Err(error()),
+        }
+    }
+}
+
+/// Selects statements whose cost score is at least the given value, which weighs the
+/// time they took, the rows they examined and the time they waited for locks against each other
+pub struct CostGreaterThan {
+    score: f64,
+    weights: CostWeights,
+}
+
+impl CostGreaterThan {
+    pub fn new(score: f64, weights: CostWeights) -> CostGreaterThan {
+        CostGreaterThan { score, weights }
+    }
+}
+
+impl Filter for CostGreaterThan {
+    fn matches(&self, log_entry: &LogEntry) -> bool {
+        self.weights.score(log_entry) >= self.score
+    }
+
+    fn describe(&self) -> String {
+        format!("cost >= {}", self.score)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use time::Duration;
+
+    use super::*;
+
+    fn entry(query_time_ms: i64, rows_examined: i32, lock_time_ms: i64) -> LogEntry {
+        LogEntry {
+            query_time: Duration::milliseconds(query_time_ms),
+            rows_examined,
+            lock_time: Duration::milliseconds(lock_time_ms),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn it_computes_the_cost_score() {
+        let weights = CostWeights::default();
+        assert_eq!(3.5, weights.score(&entry(1500, 1_500_000, 500)));
+
+        let weights: CostWeights = "2, 0, 10".parse().unwrap();
+        assert_eq!(8.0, weights.score(&entry(1500, 1_500_000, 500)));
+        assert!("1,2".parse::<CostWeights>().is_err());
+        assert!("1,x,2".parse::<CostWeights>().is_err());
+    }
+
+    #[test]
+    fn it_selects_entries_above_the_threshold() {
+        let filter = CostGreaterThan::new(2.0, CostWeights::default());
+        assert!(filter.matches(&entry(1000, 1_000_000, 0)));
+        assert!(filter.matches(&entry(100, 0, 5000)));
+        assert!(!filter.matches(&entry(1000, 500_000, 100)));
+    }
+}
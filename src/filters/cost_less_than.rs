@@ -0,0 +1,44 @@
+use crate::filters::{CostWeights, Filter};
+use crate::log_parser::LogEntry;
+
+/// Selects statements whose cost score is at most the given value
+pub struct CostLessThan {
+    score: f64,
+    weights: CostWeights,
+}
+
+impl CostLessThan {
+    pub fn new(score: f64, weights: CostWeights) -> CostLessThan {
+        CostLessThan { score, weights }
+    }
+}
+
+impl Filter for CostLessThan {
+    fn matches(&self, log_entry: &LogEntry) -> bool {
+        self.weights.score(log_entry) <= self.score
+    }
+
+    fn describe(&self) -> String {
+        format!("cost <= {}", self.score)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use time::Duration;
+
+    use super::*;
+    use crate::filters::CostGreaterThan;
+
+    #[test]
+    fn it_includes_the_bound_in_both_directions() {
+        let entry = |query_time_ms: i64| LogEntry {
+            query_time: Duration::milliseconds(query_time_ms),
+            ..Default::default()
+        };
+        let weights = CostWeights::default();
+        assert!(CostLessThan::new(2.0, weights).matches(&entry(2000)));
+        assert!(CostGreaterThan::new(2.0, weights).matches(&entry(2000)));
+        assert!(!CostLessThan::new(2.0, weights).matches(&entry(2001)));
+    }
+}
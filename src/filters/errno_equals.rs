@@ -0,0 +1,22 @@
+use crate::filters::Filter;
+use crate::log_parser::LogEntry;
+
+pub struct ErrnoEquals {
+    errno: i32,
+}
+
+impl ErrnoEquals {
+    pub fn new(errno: i32) -> ErrnoEquals {
+        ErrnoEquals { errno }
+    }
+}
+
+impl Filter for ErrnoEquals {
+    fn matches(&self, log_entry: &LogEntry) -> bool {
+        log_entry.errno.unwrap_or(0) == self.errno
+    }
+
+    fn describe(&self) -> String {
+        format!("errno = {}", self.errno)
+    }
+}
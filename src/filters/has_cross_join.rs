@@ -0,0 +1,69 @@
+use sqlparser::ast::{Expr, Join, JoinConstraint, JoinOperator};
+
+use crate::filters::{query_contains, Filter, Finder};
+use crate::log_parser::LogEntry;
+use crate::visit::{join_constraint, Visitor};
+
+pub struct HasCrossJoin;
+
+#[derive(Default)]
+struct CrossJoinFinder {
+    found: bool,
+}
+
+impl Visitor for CrossJoinFinder {
+    fn visit_join(&mut self, join: &Join) {
+        self.found |= match (&join.join_operator, join_constraint(&join.join_operator)) {
+            (JoinOperator::CrossJoin, _) => true,
+            (_, Some(JoinConstraint::None)) => true,
+            (_, Some(JoinConstraint::On(expr))) => is_constant(expr),
+            _ => false,
+        };
+    }
+}
+
+impl Finder for CrossJoinFinder {
+    fn found(&self) -> bool {
+        self.found
+    }
+}
+
+/// A join condition without any column reference, such as `ON 1 = 1`, does not constrain the join
+fn is_constant(expr: &Expr) -> bool {
+    match expr {
+        Expr::Value(_) => true,
+        Expr::Nested(expr) | Expr::UnaryOp { expr, .. } => is_constant(expr),
+        Expr::BinaryOp { left, right, .. } => is_constant(left) && is_constant(right),
+        _ => false,
+    }
+}
+
+impl Filter for HasCrossJoin {
+    fn matches(&self, log_entry: &LogEntry) -> bool {
+        query_contains(log_entry, CrossJoinFinder::default())
+    }
+
+    fn describe(&self) -> String {
+        "cross_join".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filters::query_entry;
+
+    #[test]
+    fn it_detects_unconstrained_joins() {
+        let filter = HasCrossJoin;
+        assert!(filter.matches(&query_entry("SELECT * FROM foo CROSS JOIN bar;")));
+        assert!(filter.matches(&query_entry("SELECT * FROM foo JOIN bar;")));
+        assert!(filter.matches(&query_entry("SELECT * FROM foo JOIN bar ON 1 = 1;")));
+        assert!(filter.matches(&query_entry(
+            "SELECT * FROM foo WHERE id IN (SELECT a.id FROM a INNER JOIN b ON TRUE);"
+        )));
+        assert!(!filter.matches(&query_entry("SELECT * FROM foo JOIN bar ON foo.id = bar.foo_id;")));
+        assert!(!filter.matches(&query_entry("SELECT * FROM foo LEFT JOIN bar USING (id);")));
+        assert!(!filter.matches(&query_entry("SELECT * FROM foo CROSS JOIN;")));
+    }
+}
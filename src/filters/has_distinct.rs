@@ -0,0 +1,49 @@
+use sqlparser::ast::Select;
+
+use crate::filters::{query_contains, Filter, Finder};
+use crate::log_parser::LogEntry;
+use crate::visit::Visitor;
+
+pub struct HasDistinct;
+
+#[derive(Default)]
+struct DistinctFinder {
+    found: bool,
+}
+
+impl Visitor for DistinctFinder {
+    fn visit_select(&mut self, select: &Select) {
+        self.found |= select.distinct.is_some();
+    }
+}
+
+impl Finder for DistinctFinder {
+    fn found(&self) -> bool {
+        self.found
+    }
+}
+
+impl Filter for HasDistinct {
+    fn matches(&self, log_entry: &LogEntry) -> bool {
+        query_contains(log_entry, DistinctFinder::default())
+    }
+
+    fn describe(&self) -> String {
+        "distinct".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filters::query_entry;
+
+    #[test]
+    fn it_detects_distinct_selections() {
+        let filter = HasDistinct;
+        assert!(filter.matches(&query_entry("SELECT DISTINCT foo FROM bar;")));
+        assert!(filter.matches(&query_entry("SELECT * FROM (SELECT DISTINCT foo FROM bar) AS b;")));
+        assert!(!filter.matches(&query_entry("SELECT foo FROM bar;")));
+        assert!(!filter.matches(&query_entry("SELECT COUNT(DISTINCT foo) FROM bar;")));
+    }
+}
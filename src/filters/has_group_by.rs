@@ -0,0 +1,54 @@
+use sqlparser::ast::{GroupByExpr, Select};
+
+use crate::filters::{query_contains, Filter, Finder};
+use crate::log_parser::LogEntry;
+use crate::visit::Visitor;
+
+pub struct HasGroupBy;
+
+#[derive(Default)]
+struct GroupByFinder {
+    found: bool,
+}
+
+impl Visitor for GroupByFinder {
+    fn visit_select(&mut self, select: &Select) {
+        self.found |= match &select.group_by {
+            GroupByExpr::All => true,
+            GroupByExpr::Expressions(exprs) => !exprs.is_empty(),
+        };
+    }
+}
+
+impl Finder for GroupByFinder {
+    fn found(&self) -> bool {
+        self.found
+    }
+}
+
+impl Filter for HasGroupBy {
+    fn matches(&self, log_entry: &LogEntry) -> bool {
+        query_contains(log_entry, GroupByFinder::default())
+    }
+
+    fn describe(&self) -> String {
+        "group_by".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filters::query_entry;
+
+    #[test]
+    fn it_detects_grouping() {
+        let filter = HasGroupBy;
+        assert!(filter.matches(&query_entry("SELECT foo, COUNT(*) FROM bar GROUP BY foo;")));
+        assert!(filter.matches(&query_entry(
+            "SELECT * FROM foo WHERE id IN (SELECT MAX(id) FROM bar GROUP BY baz);"
+        )));
+        assert!(!filter.matches(&query_entry("SELECT COUNT(*) FROM bar;")));
+        assert!(!filter.matches(&query_entry("SELECT foo FROM bar GROUP;")));
+    }
+}
@@ -0,0 +1,59 @@
+use sqlparser::ast::{Expr, TableFactor};
+
+use crate::filters::{query_contains, Filter, Finder};
+use crate::log_parser::LogEntry;
+use crate::visit::Visitor;
+
+pub struct HasSubquery;
+
+#[derive(Default)]
+struct SubqueryFinder {
+    found: bool,
+}
+
+impl Visitor for SubqueryFinder {
+    fn visit_table_factor(&mut self, table_factor: &TableFactor) {
+        self.found |= matches!(table_factor, TableFactor::Derived { .. });
+    }
+
+    fn visit_expr(&mut self, expr: &Expr) {
+        self.found |=
+            matches!(expr, Expr::Subquery(_) | Expr::InSubquery { .. } | Expr::Exists { .. });
+    }
+}
+
+impl Finder for SubqueryFinder {
+    fn found(&self) -> bool {
+        self.found
+    }
+}
+
+impl Filter for HasSubquery {
+    fn matches(&self, log_entry: &LogEntry) -> bool {
+        query_contains(log_entry, SubqueryFinder::default())
+    }
+
+    fn describe(&self) -> String {
+        "subquery".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filters::query_entry;
+
+    #[test]
+    fn it_detects_subqueries() {
+        let filter = HasSubquery;
+        assert!(
+            filter.matches(&query_entry("SELECT * FROM foo WHERE id IN (SELECT foo_id FROM bar);"))
+        );
+        assert!(filter.matches(&query_entry("SELECT * FROM (SELECT id FROM foo) AS f;")));
+        assert!(filter.matches(&query_entry(
+            "SELECT * FROM foo WHERE EXISTS (SELECT 1 FROM bar WHERE bar.foo_id = foo.id);"
+        )));
+        assert!(!filter.matches(&query_entry("SELECT * FROM foo WHERE id IN (1, 2, 3);")));
+        assert!(!filter.matches(&query_entry("SELECT * FROM foo JOIN bar ON bar.foo_id = foo.id;")));
+    }
+}
@@ -0,0 +1,53 @@
+use time::UtcOffset;
+
+use crate::filters::Filter;
+use crate::log_parser::LogEntry;
+
+/// Selects entries logged within a range of hours of the day, both ends included. The hour is
+/// taken in UTC, regardless of the time zone in which the timestamp was logged.
+pub struct HourOfDay {
+    from: u8,
+    to: u8,
+}
+
+impl HourOfDay {
+    /// Selects entries logged from the start of hour `from` until the end of hour `to`
+    pub fn between(from: u8, to: u8) -> HourOfDay {
+        HourOfDay { from, to }
+    }
+}
+
+impl Filter for HourOfDay {
+    fn matches(&self, log_entry: &LogEntry) -> bool {
+        let hour = log_entry.timestamp.to_offset(UtcOffset::UTC).hour();
+        (self.from..=self.to).contains(&hour)
+    }
+
+    fn describe(&self) -> String {
+        match (self.from, self.to) {
+            (from, to) if from == to => format!("hour = {}", from),
+            (0, to) => format!("hour <= {}", to),
+            (from, 23) => format!("hour >= {}", from),
+            (from, to) => format!("hour >= {} AND hour <= {}", from, to),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use time::macros::datetime;
+
+    use super::*;
+
+    #[test]
+    fn it_takes_the_hour_in_utc() {
+        let filter = HourOfDay::between(9, 16);
+        let entry = |timestamp| LogEntry { timestamp, ..Default::default() };
+        assert!(filter.matches(&entry(datetime!(2021-05-11 09:00:00 UTC))));
+        assert!(filter.matches(&entry(datetime!(2021-05-11 16:59:59 UTC))));
+        assert!(!filter.matches(&entry(datetime!(2021-05-11 17:00:00 UTC))));
+        // 18:30 in UTC+2 is 16:30 in UTC
+        assert!(filter.matches(&entry(datetime!(2021-05-11 18:30:00 +2))));
+        assert_eq!("hour >= 9 AND hour <= 16", filter.describe());
+    }
+}
@@ -0,0 +1,14 @@
+use crate::filters::Filter;
+use crate::log_parser::LogEntry;
+
+pub struct Killed;
+
+impl Filter for Killed {
+    fn matches(&self, log_entry: &LogEntry) -> bool {
+        log_entry.killed.unwrap_or(0) != 0
+    }
+
+    fn describe(&self) -> String {
+        "killed".to_string()
+    }
+}
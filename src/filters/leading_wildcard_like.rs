@@ -0,0 +1,66 @@
+use sqlparser::ast::{Expr, Value};
+
+use crate::filters::{query_contains, Filter, Finder};
+use crate::log_parser::LogEntry;
+use crate::visit::Visitor;
+
+/// Selects statements with a `LIKE` pattern that starts with a wildcard, such as `LIKE '%foo'`,
+/// which cannot be looked up in an index. Patterns that are not literals are not considered.
+pub struct LeadingWildcardLike;
+
+#[derive(Default)]
+struct LeadingWildcardFinder {
+    found: bool,
+}
+
+impl Visitor for LeadingWildcardFinder {
+    fn visit_expr(&mut self, expr: &Expr) {
+        if let Expr::Like { pattern, .. } | Expr::ILike { pattern, .. } = expr {
+            self.found |= match pattern.as_ref() {
+                Expr::Value(Value::SingleQuotedString(pattern))
+                | Expr::Value(Value::DoubleQuotedString(pattern)) => {
+                    pattern.starts_with(['%', '_'])
+                }
+                _ => false,
+            };
+        }
+    }
+}
+
+impl Finder for LeadingWildcardFinder {
+    fn found(&self) -> bool {
+        self.found
+    }
+}
+
+impl Filter for LeadingWildcardLike {
+    fn matches(&self, log_entry: &LogEntry) -> bool {
+        query_contains(log_entry, LeadingWildcardFinder::default())
+    }
+
+    fn describe(&self) -> String {
+        "leading_wildcard".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filters::query_entry;
+
+    #[test]
+    fn it_detects_patterns_starting_with_a_wildcard() {
+        let filter = LeadingWildcardLike;
+        assert!(filter.matches(&query_entry("SELECT * FROM foo WHERE name LIKE '%bar';")));
+        assert!(filter.matches(&query_entry("SELECT * FROM foo WHERE name NOT LIKE '_bar';")));
+        assert!(filter.matches(&query_entry("SELECT * FROM foo WHERE name ILIKE '%bar';")));
+        assert!(filter.matches(&query_entry(
+            "SELECT * FROM foo WHERE id IN (SELECT foo_id FROM baz WHERE name LIKE '%bar%');"
+        )));
+        assert!(!filter.matches(&query_entry("SELECT * FROM foo WHERE name LIKE 'bar%';")));
+        assert!(
+            !filter.matches(&query_entry("SELECT * FROM foo WHERE name LIKE CONCAT('%', 'bar');"))
+        );
+        assert!(!filter.matches(&query_entry("SELEC * FROM foo WHERE name LIKE '%bar';")));
+    }
+}
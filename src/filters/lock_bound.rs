@@ -0,0 +1,42 @@
+use crate::filters::Filter;
+use crate::log_parser::LogEntry;
+
+/// The share of the query time that must have been spent waiting for locks.
+/// MySQL includes the lock time in the query time, so this is at most 1.
+const LOCK_TIME_RATIO: f64 = 0.5;
+
+pub struct LockBound;
+
+impl Filter for LockBound {
+    fn matches(&self, log_entry: &LogEntry) -> bool {
+        let query_time = log_entry.query_time.as_seconds_f64();
+        query_time > 0.0 && log_entry.lock_time.as_seconds_f64() > query_time * LOCK_TIME_RATIO
+    }
+
+    fn describe(&self) -> String {
+        "lock_bound".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use time::Duration;
+
+    use super::*;
+
+    fn entry(query_time_ms: i64, lock_time_ms: i64) -> LogEntry {
+        LogEntry {
+            query_time: Duration::milliseconds(query_time_ms),
+            lock_time: Duration::milliseconds(lock_time_ms),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn it_selects_lock_dominated_entries() {
+        assert!(LockBound.matches(&entry(2000, 1800)));
+        assert!(!LockBound.matches(&entry(2000, 1000)));
+        assert!(!LockBound.matches(&entry(2000, 1)));
+        assert!(!LockBound.matches(&entry(0, 0)));
+    }
+}
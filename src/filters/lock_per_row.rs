@@ -0,0 +1,71 @@
+use crate::filters::Filter;
+use crate::log_parser::LogEntry;
+
+/// Selects statements by their lock time per examined row in microseconds. A high value means
+/// that the statement spent its time waiting for locks rather than scanning rows. Statements that
+/// examined no rows are treated as having examined one.
+pub struct LockPerRow {
+    usec: f64,
+    at_least: bool,
+}
+
+impl LockPerRow {
+    /// Selects statements with at least this lock time per row
+    pub fn at_least(usec: f64) -> LockPerRow {
+        LockPerRow { usec, at_least: true }
+    }
+
+    /// Selects statements with at most this lock time per row
+    pub fn at_most(usec: f64) -> LockPerRow {
+        LockPerRow { usec, at_least: false }
+    }
+}
+
+impl Filter for LockPerRow {
+    fn matches(&self, log_entry: &LogEntry) -> bool {
+        let rows = log_entry.rows_examined.max(1) as f64;
+        let lock_per_row = log_entry.lock_time.whole_microseconds() as f64 / rows;
+        if self.at_least {
+            lock_per_row >= self.usec
+        } else {
+            lock_per_row <= self.usec
+        }
+    }
+
+    fn describe(&self) -> String {
+        let op = if self.at_least { ">=" } else { "<=" };
+        format!("lock_per_row {} {}us", op, self.usec)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use time::Duration;
+
+    use super::*;
+
+    fn entry(lock_time_ms: i64, rows_examined: i32) -> LogEntry {
+        LogEntry {
+            lock_time: Duration::milliseconds(lock_time_ms),
+            rows_examined,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn it_selects_entries_with_high_lock_time_per_row() {
+        let filter = LockPerRow::at_least(1000.0);
+        assert!(filter.matches(&entry(500, 10)));
+        assert!(filter.matches(&entry(5, 0)));
+        assert!(!filter.matches(&entry(500, 1_000_000)));
+        assert!(!filter.matches(&entry(0, 0)));
+    }
+
+    #[test]
+    fn it_includes_the_bound_in_both_directions() {
+        // 10 ms of lock time for 10 rows are exactly 1000 us per row
+        assert!(LockPerRow::at_least(1000.0).matches(&entry(10, 10)));
+        assert!(LockPerRow::at_most(1000.0).matches(&entry(10, 10)));
+        assert!(!LockPerRow::at_most(1000.0).matches(&entry(11, 10)));
+    }
+}
@@ -1,19 +1,127 @@
+mod cost_greater_than;
+mod cost_less_than;
+mod errno_equals;
+mod has_cross_join;
+mod has_distinct;
+mod has_group_by;
+mod has_subquery;
+mod hour_of_day;
+mod killed;
+mod leading_wildcard_like;
+mod lock_bound;
+mod lock_per_row;
 mod not;
+mod order_by_without_limit;
+mod projection_count;
 mod query_matches;
 mod query_time_greater_than;
 mod query_time_less_than;
+mod registry;
+mod system_schema;
+mod timestamp_after;
+mod timestamp_before;
 mod user_equals;
+mod user_in;
 mod user_matches;
+mod uses_function;
+mod uses_tmp_table;
+mod weekday;
 
 use crate::log_parser::LogEntry;
+use crate::visit::{parse, walk_statements, Visitor};
 
 pub trait Filter: Sync {
     fn matches(&self, log_entry: &LogEntry) -> bool;
+
+    /// A human-readable description of the condition, in the syntax of the `-F` option
+    fn describe(&self) -> String {
+        "<custom filter>".to_string()
+    }
+}
+
+/// Describes all active filters in a single line, e.g. "Filters: user = foo AND query_time >= 1.000s"
+pub fn describe_filters(filters: &[Box<dyn Filter>]) -> String {
+    if filters.is_empty() {
+        return "Filters: none".to_string();
+    }
+    let descriptions: Vec<String> = filters.iter().map(|filter| filter.describe()).collect();
+    format!("Filters: {}", descriptions.join(" AND "))
+}
+
+/// A visitor that looks for a certain construct anywhere in a statement, including subqueries
+trait Finder: Visitor {
+    fn found(&self) -> bool;
+}
+
+/// Whether the finder comes across its construct in any statement of the entry's query.
+/// Queries that cannot be parsed never contain it.
+fn query_contains(log_entry: &LogEntry, mut finder: impl Finder) -> bool {
+    let Some(ast) = parse(&log_entry.query) else {
+        return false;
+    };
+    walk_statements(&ast, &mut finder);
+    finder.found()
 }
 
+/// Writes a time in seconds with millisecond precision, or microsecond precision where needed
+fn describe_seconds(usec: i64) -> String {
+    if usec % 1000 == 0 {
+        format!("{:.3}s", usec as f64 / 1_000_000.0)
+    } else {
+        format!("{:.6}s", usec as f64 / 1_000_000.0)
+    }
+}
+
+pub use self::cost_greater_than::{CostGreaterThan, CostWeights};
+pub use self::cost_less_than::CostLessThan;
+pub use self::errno_equals::ErrnoEquals;
+pub use self::has_cross_join::HasCrossJoin;
+pub use self::has_distinct::HasDistinct;
+pub use self::has_group_by::HasGroupBy;
+pub use self::has_subquery::HasSubquery;
+pub use self::hour_of_day::HourOfDay;
+pub use self::killed::Killed;
+pub use self::leading_wildcard_like::LeadingWildcardLike;
+pub use self::lock_bound::LockBound;
+pub use self::lock_per_row::LockPerRow;
 pub use self::not::Not;
+pub use self::order_by_without_limit::OrderByWithoutLimit;
+pub use self::projection_count::ProjectionCount;
 pub use self::query_matches::QueryMatches;
 pub use self::query_time_greater_than::QueryTimeGreaterThan;
 pub use self::query_time_less_than::QueryTimeLessThan;
+pub use self::registry::{FilterConstructor, FilterOptions, FilterRegistry};
+pub use self::system_schema::SystemSchema;
+pub use self::timestamp_after::TimestampAfter;
+pub use self::timestamp_before::TimestampBefore;
 pub use self::user_equals::UserEquals;
+pub use self::user_in::UserIn;
 pub use self::user_matches::UserMatches;
+pub use self::uses_function::UsesFunction;
+pub use self::uses_tmp_table::UsesTmpTable;
+pub use self::weekday::Weekday;
+
+/// An entry with the given query and default values otherwise, for the filters on the query
+#[cfg(test)]
+fn query_entry(query: &str) -> LogEntry {
+    LogEntry { query: query.to_string(), ..Default::default() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_describes_combined_filters() {
+        let filters: Vec<Box<dyn Filter>> = vec![
+            Box::new(UserEquals::new("foo".to_string())),
+            Box::new(QueryTimeGreaterThan::new(1_500_000)),
+            Box::new(Not::new(Box::new(Killed))),
+        ];
+        assert_eq!(
+            "Filters: user = foo AND query_time >= 1.500s AND NOT (killed)",
+            describe_filters(&filters)
+        );
+        assert_eq!("Filters: none", describe_filters(&[]));
+    }
+}
@@ -15,4 +15,8 @@ impl Filter for Not {
     fn matches(&self, log_entry: &LogEntry) -> bool {
         !self.filter.matches(log_entry)
     }
+
+    fn describe(&self) -> String {
+        format!("NOT ({})", self.filter.describe())
+    }
 }
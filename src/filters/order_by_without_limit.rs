@@ -0,0 +1,57 @@
+use sqlparser::ast::{Query, Statement};
+
+use crate::filters::{query_contains, Filter, Finder};
+use crate::log_parser::LogEntry;
+use crate::visit::Visitor;
+
+pub struct OrderByWithoutLimit;
+
+#[derive(Default)]
+struct UnlimitedOrderFinder {
+    found: bool,
+}
+
+impl Visitor for UnlimitedOrderFinder {
+    fn visit_statement(&mut self, statement: &Statement) {
+        self.found |= matches!(
+            statement,
+            Statement::Delete { order_by, limit: None, .. } if !order_by.is_empty()
+        );
+    }
+
+    fn visit_query(&mut self, query: &Query) {
+        self.found |= !query.order_by.is_empty() && query.limit.is_none();
+    }
+}
+
+impl Finder for UnlimitedOrderFinder {
+    fn found(&self) -> bool {
+        self.found
+    }
+}
+
+impl Filter for OrderByWithoutLimit {
+    fn matches(&self, log_entry: &LogEntry) -> bool {
+        query_contains(log_entry, UnlimitedOrderFinder::default())
+    }
+
+    fn describe(&self) -> String {
+        "order_no_limit".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filters::query_entry;
+
+    #[test]
+    fn it_detects_order_by_without_limit() {
+        let filter = OrderByWithoutLimit;
+        assert!(filter.matches(&query_entry("SELECT * FROM foo ORDER BY created;")));
+        assert!(filter.matches(&query_entry("DELETE FROM foo WHERE id > 1 ORDER BY created;")));
+        assert!(!filter.matches(&query_entry("SELECT * FROM foo ORDER BY created LIMIT 10;")));
+        assert!(!filter.matches(&query_entry("SELECT * FROM foo;")));
+        assert!(!filter.matches(&query_entry("THIS IS NOT SQL ORDER BY foo;")));
+    }
+}
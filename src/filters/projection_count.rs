@@ -0,0 +1,95 @@
+use sqlparser::ast::{Select, SelectItem};
+
+use crate::filters::Filter;
+use crate::log_parser::LogEntry;
+use crate::visit::{parse, walk_statements, Visitor};
+
+/// Selects statements by the number of columns in the widest SELECT list they contain,
+/// including subqueries. Since the width of `SELECT *` is unknown without the schema, a wildcard
+/// counts as wider than any explicit list. Statements without a SELECT list (e.g. `INSERT ...
+/// VALUES`) and statements that cannot be parsed are never selected.
+pub struct ProjectionCount {
+    bound: usize,
+    at_least: bool,
+}
+
+impl ProjectionCount {
+    /// Selects statements with at least this many columns
+    pub fn at_least(count: usize) -> ProjectionCount {
+        ProjectionCount { bound: count, at_least: true }
+    }
+
+    /// Selects statements with at most this many columns
+    pub fn at_most(count: usize) -> ProjectionCount {
+        ProjectionCount { bound: count, at_least: false }
+    }
+}
+
+#[derive(Default)]
+struct WidestProjection {
+    width: Option<usize>,
+}
+
+impl Visitor for WidestProjection {
+    fn visit_select(&mut self, select: &Select) {
+        let wildcard = select.projection.iter().any(|item| {
+            matches!(item, SelectItem::Wildcard(_) | SelectItem::QualifiedWildcard(..))
+        });
+        let width = if wildcard { usize::MAX } else { select.projection.len() };
+        self.width = self.width.max(Some(width));
+    }
+}
+
+impl Filter for ProjectionCount {
+    fn matches(&self, log_entry: &LogEntry) -> bool {
+        let Some(ast) = parse(&log_entry.query) else {
+            return false;
+        };
+        let mut widest = WidestProjection::default();
+        walk_statements(&ast, &mut widest);
+        match widest.width {
+            Some(width) if self.at_least => width >= self.bound,
+            Some(width) => width <= self.bound,
+            None => false,
+        }
+    }
+
+    fn describe(&self) -> String {
+        let op = if self.at_least { ">=" } else { "<=" };
+        format!("projection_count {} {}", op, self.bound)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filters::query_entry;
+
+    fn select_columns(count: usize) -> LogEntry {
+        let columns: Vec<String> = (1..=count).map(|i| format!("col{}", i)).collect();
+        query_entry(&format!("SELECT {} FROM foo WHERE id = 1;", columns.join(", ")))
+    }
+
+    #[test]
+    fn it_selects_wide_projections() {
+        let filter = ProjectionCount::at_least(51);
+        assert!(filter.matches(&select_columns(200)));
+        assert!(filter.matches(&select_columns(51)));
+        assert!(!filter.matches(&select_columns(50)));
+        assert!(filter.matches(&query_entry("SELECT * FROM foo;")));
+        assert!(
+            filter.matches(&query_entry("SELECT id FROM foo WHERE id IN (SELECT f.* FROM bar f);"))
+        );
+        assert!(!filter.matches(&query_entry("INSERT INTO foo (a, b) VALUES (1, 2);")));
+        assert!(!filter.matches(&query_entry("SELEC id FROM foo;")));
+    }
+
+    #[test]
+    fn it_selects_narrow_projections() {
+        let filter = ProjectionCount::at_most(2);
+        assert!(filter.matches(&select_columns(2)));
+        assert!(!filter.matches(&select_columns(3)));
+        assert!(!filter.matches(&query_entry("SELECT * FROM foo;")));
+        assert!(!filter.matches(&query_entry("SELEC id FROM foo;")));
+    }
+}
@@ -19,4 +19,8 @@ impl Filter for QueryMatches {
     fn matches(&self, log_entry: &LogEntry) -> bool {
         self.regex.is_match(&log_entry.query)
     }
+
+    fn describe(&self) -> String {
+        format!("query ~= {}", self.regex.as_str())
+    }
 }
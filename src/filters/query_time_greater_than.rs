@@ -1,18 +1,22 @@
-use crate::filters::Filter;
+use crate::filters::{describe_seconds, Filter};
 use crate::log_parser::LogEntry;
 
 pub struct QueryTimeGreaterThan {
-    msec: i64,
+    usec: i64,
 }
 
 impl QueryTimeGreaterThan {
-    pub fn new(msec: i64) -> QueryTimeGreaterThan {
-        QueryTimeGreaterThan { msec }
+    pub fn new(usec: i64) -> QueryTimeGreaterThan {
+        QueryTimeGreaterThan { usec }
     }
 }
 
 impl Filter for QueryTimeGreaterThan {
     fn matches(&self, log_entry: &LogEntry) -> bool {
-        log_entry.query_time.whole_milliseconds() >= self.msec as i128
+        log_entry.query_time.whole_microseconds() >= self.usec as i128
+    }
+
+    fn describe(&self) -> String {
+        format!("query_time >= {}", describe_seconds(self.usec))
     }
 }
@@ -0,0 +1,370 @@
+use ahash::{HashMap, HashMapExt, HashSet};
+use time::format_description::well_known::Iso8601;
+use time::OffsetDateTime;
+
+use super::{
+    CostGreaterThan, CostLessThan, CostWeights, ErrnoEquals, Filter, HasCrossJoin, HasDistinct,
+    HasGroupBy, HasSubquery, HourOfDay, Killed, LeadingWildcardLike, LockBound, LockPerRow, Not,
+    OrderByWithoutLimit, ProjectionCount, QueryMatches, QueryTimeGreaterThan, QueryTimeLessThan,
+    SystemSchema, TimestampAfter, TimestampBefore, UserEquals, UserIn, UserMatches, UsesFunction,
+    UsesTmpTable, Weekday,
+};
+
+/// Builds a filter from the operator and value of a filter definition such as `user!=foo`
+pub type FilterConstructor =
+    Box<dyn Fn(&str, &str) -> Result<Box<dyn Filter>, String> + Send + Sync>;
+
+/// Settings that affect how filters evaluate entries
+#[derive(Copy, Clone, Debug, Default)]
+pub struct FilterOptions {
+    /// Compare user names in Unicode normalization form C
+    pub unicode_normalize: bool,
+    /// The weights of the terms of the `cost` filter's score
+    pub cost_weights: CostWeights,
+}
+
+/// Maps filter names to the constructors of the corresponding filters
+pub struct FilterRegistry {
+    constructors: HashMap<&'static str, FilterConstructor>,
+}
+
+impl FilterRegistry {
+    /// A registry without any filters
+    pub fn empty() -> Self {
+        FilterRegistry { constructors: HashMap::new() }
+    }
+
+    /// A registry with all filters that can be used with the `-F` option
+    pub fn builtin(options: &FilterOptions) -> Self {
+        let mut registry = Self::empty();
+        let options = *options;
+        let user_equals = move |equals: UserEquals| -> Box<dyn Filter> {
+            if options.unicode_normalize {
+                Box::new(equals.normalizing_unicode())
+            } else {
+                Box::new(equals)
+            }
+        };
+        registry.register("user", move |op, value| match op {
+            "=" => Ok(user_equals(UserEquals::new(value.to_string()))),
+            "!=" => Ok(Box::new(Not::new(user_equals(UserEquals::new(value.to_string()))))),
+            "~~=" => Ok(user_equals(UserEquals::ignoring_case(value.to_string()))),
+            "~=" => Ok(Box::new(UserMatches::new(value.to_string())?)),
+            "in" => Ok(Box::new(UserIn::new(parse_list(value)))),
+            _ => Err(operator_error("User", &["=", "!=", "~~=", "~=", "in"], op)),
+        });
+        registry.register("query", |op, value| match op {
+            "~=" => Ok(Box::new(QueryMatches::new(value.to_string())?)),
+            _ => Err(operator_error("Query", &["~="], op)),
+        });
+        registry.register("query_time", |op, value| {
+            let usec = (1_000_000.0 * parse_seconds(value)?).round() as i64;
+            match op {
+                "<" | "<=" => Ok(Box::new(QueryTimeLessThan::new(usec))),
+                ">" | ">=" => Ok(Box::new(QueryTimeGreaterThan::new(usec))),
+                _ => Err(operator_error("Query time", &["<", "<=", ">", ">="], op)),
+            }
+        });
+        registry.register("function", |op, value| match op {
+            "~=" => Ok(Box::new(UsesFunction::new(value.to_string())?)),
+            _ => Err(operator_error("Function", &["~="], op)),
+        });
+        registry.register("errno", |op, value| {
+            let errno: i32 = value.parse().map_err(|_err| {
+                format!("Errno filter requires an integer argument, found '{}'", value)
+            })?;
+            match op {
+                "=" => Ok(Box::new(ErrnoEquals::new(errno))),
+                "!=" => Ok(Box::new(Not::new(Box::new(ErrnoEquals::new(errno))))),
+                _ => Err(operator_error("Errno", &["=", "!="], op)),
+            }
+        });
+        registry.register("lock_per_row", |op, value| {
+            let usec: f64 = value.parse().map_err(|_err| {
+                format!("Lock per row filter requires a numeric argument, found '{}'", value)
+            })?;
+            match op {
+                ">" | ">=" => Ok(Box::new(LockPerRow::at_least(usec))),
+                "<" | "<=" => Ok(Box::new(LockPerRow::at_most(usec))),
+                _ => Err(operator_error("Lock per row", &["<", "<=", ">", ">="], op)),
+            }
+        });
+        registry.register("cost", move |op, value| {
+            let score: f64 = value.parse().map_err(|_err| {
+                format!("Cost filter requires a numeric argument, found '{}'", value)
+            })?;
+            match op {
+                ">" | ">=" => Ok(Box::new(CostGreaterThan::new(score, options.cost_weights))),
+                "<" | "<=" => Ok(Box::new(CostLessThan::new(score, options.cost_weights))),
+                _ => Err(operator_error("Cost", &["<", "<=", ">", ">="], op)),
+            }
+        });
+        registry.register("projection_count", |op, value| {
+            let count: usize = value.parse().map_err(|_err| {
+                format!("Projection count filter requires an integer argument, found '{}'", value)
+            })?;
+            match op {
+                ">" => Ok(Box::new(ProjectionCount::at_least(count.saturating_add(1)))),
+                ">=" => Ok(Box::new(ProjectionCount::at_least(count))),
+                "<" if count > 0 => Ok(Box::new(ProjectionCount::at_most(count - 1))),
+                "<" => Err("Projection count filter cannot select less than 0 columns".to_string()),
+                "<=" => Ok(Box::new(ProjectionCount::at_most(count))),
+                _ => Err(operator_error("Projection count", &["<", "<=", ">", ">="], op)),
+            }
+        });
+        registry.register("timestamp", |op, value| {
+            let timestamp = OffsetDateTime::parse(value, &Iso8601::DEFAULT).map_err(|_err| {
+                format!("Timestamp filter requires an ISO 8601 timestamp, found '{}'", value)
+            })?;
+            match op {
+                ">" | ">=" => Ok(Box::new(TimestampAfter::new(timestamp))),
+                "<" | "<=" => Ok(Box::new(TimestampBefore::new(timestamp))),
+                _ => Err(operator_error("Timestamp", &["<", "<=", ">", ">="], op)),
+            }
+        });
+        registry.register("hour", |op, value| {
+            let hour: u8 = value.parse().ok().filter(|hour| *hour < 24).ok_or_else(|| {
+                format!("Hour filter requires an hour between 0 and 23, found '{}'", value)
+            })?;
+            match op {
+                "=" => Ok(Box::new(HourOfDay::between(hour, hour))),
+                "!=" => Ok(Box::new(Not::new(Box::new(HourOfDay::between(hour, hour))))),
+                ">" if hour < 23 => Ok(Box::new(HourOfDay::between(hour + 1, 23))),
+                ">" => Err("Hour filter cannot select hours after 23".to_string()),
+                ">=" => Ok(Box::new(HourOfDay::between(hour, 23))),
+                "<" if hour > 0 => Ok(Box::new(HourOfDay::between(0, hour - 1))),
+                "<" => Err("Hour filter cannot select hours before 0".to_string()),
+                "<=" => Ok(Box::new(HourOfDay::between(0, hour))),
+                _ => Err(operator_error("Hour", &["=", "!=", "<", "<=", ">", ">="], op)),
+            }
+        });
+        registry.register("weekday", |op, value| match op {
+            "=" => Ok(Box::new(Weekday::new(vec![parse_weekday(value)?]))),
+            "!=" => Ok(Box::new(Not::new(Box::new(Weekday::new(vec![parse_weekday(value)?]))))),
+            "in" => {
+                let days = value.split(',').map(|day| parse_weekday(day.trim()));
+                Ok(Box::new(Weekday::new(days.collect::<Result<_, _>>()?)))
+            }
+            _ => Err(operator_error("Weekday", &["=", "!=", "in"], op)),
+        });
+        registry.register_flag("killed", "Killed", || Box::new(Killed));
+        registry.register_flag("tmp_table", "Tmp table", || Box::new(UsesTmpTable));
+        registry.register_flag("group_by", "Group by", || Box::new(HasGroupBy));
+        registry.register_flag("distinct", "Distinct", || Box::new(HasDistinct));
+        registry.register_flag("subquery", "Subquery", || Box::new(HasSubquery));
+        registry.register_flag("order_no_limit", "Order without limit", || {
+            Box::new(OrderByWithoutLimit)
+        });
+        registry.register_flag("lock_bound", "Lock bound", || Box::new(LockBound));
+        registry.register_flag("cross_join", "Cross join", || Box::new(HasCrossJoin));
+        registry.register_flag("system_schema", "System schema", || Box::new(SystemSchema));
+        registry.register_flag("leading_wildcard", "Leading wildcard", || {
+            Box::new(LeadingWildcardLike)
+        });
+        registry
+    }
+
+    pub fn register<F>(&mut self, name: &'static str, constructor: F)
+    where
+        F: Fn(&str, &str) -> Result<Box<dyn Filter>, String> + Send + Sync + 'static,
+    {
+        self.constructors.insert(name, Box::new(constructor));
+    }
+
+    /// Registers a filter for a property that an entry either has or does not have, which
+    /// accepts `=` and `!=` with a boolean value, e.g. `killed=true` or `killed!=1`
+    pub fn register_flag<F>(&mut self, name: &'static str, label: &'static str, filter: F)
+    where
+        F: Fn() -> Box<dyn Filter> + Send + Sync + 'static,
+    {
+        self.register(name, move |op, value| {
+            let flag = parse_bool(value)?;
+            match (op, flag) {
+                ("=", true) | ("!=", false) => Ok(filter()),
+                ("=", false) | ("!=", true) => Ok(Box::new(Not::new(filter()))),
+                _ => Err(operator_error(label, &["=", "!="], op)),
+            }
+        });
+    }
+
+    pub fn create(&self, name: &str, op: &str, value: &str) -> Result<Box<dyn Filter>, String> {
+        match self.constructors.get(name) {
+            Some(constructor) => constructor(op, value),
+            None => Err(format!("Unknown filter name: '{}'", name)),
+        }
+    }
+}
+
+/// Formats the error for an operator that the filter does not support,
+/// e.g. "Errno filter expects one of '=' or '!=', found '<'"
+fn operator_error(label: &str, expected: &[&str], op: &str) -> String {
+    let quoted: Vec<String> = expected.iter().map(|op| format!("'{}'", op)).collect();
+    match quoted.split_last() {
+        Some((last, [])) => format!("{} filter only supports {}, found '{}'", label, last, op),
+        Some((last, rest)) => {
+            format!(
+                "{} filter expects one of {} or {}, found '{}'",
+                label,
+                rest.join(", "),
+                last,
+                op
+            )
+        }
+        None => format!("{} filter does not support '{}'", label, op),
+    }
+}
+
+fn parse_list(value: &str) -> HashSet<String> {
+    value.split(',').map(str::trim).filter(|item| !item.is_empty()).map(String::from).collect()
+}
+
+/// Parses a duration such as `500ms`, `1.5s` or `2m` into seconds. The units `us`, `ms`, `s`
+/// and `m` are accepted, and a bare number is taken as seconds.
+fn parse_seconds(value: &str) -> Result<f64, String> {
+    let value = value.trim();
+    let (number, factor) = [("us", 1e-6), ("ms", 1e-3), ("s", 1.0), ("m", 60.0)]
+        .into_iter()
+        .find_map(|(unit, factor)| value.strip_suffix(unit).map(|number| (number, factor)))
+        .unwrap_or((value, 1.0));
+    match number.trim().parse::<f64>() {
+        Ok(number) => Ok(number * factor),
+        Err(_) => Err(format!("Expected a duration such as 1.5 or 500ms, found '{}'", value)),
+    }
+}
+
+/// Accepts the English names of the days of the week, or their first three letters
+fn parse_weekday(value: &str) -> Result<time::Weekday, String> {
+    let day = match value.to_ascii_lowercase().as_str() {
+        "mon" | "monday" => time::Weekday::Monday,
+        "tue" | "tuesday" => time::Weekday::Tuesday,
+        "wed" | "wednesday" => time::Weekday::Wednesday,
+        "thu" | "thursday" => time::Weekday::Thursday,
+        "fri" | "friday" => time::Weekday::Friday,
+        "sat" | "saturday" => time::Weekday::Saturday,
+        "sun" | "sunday" => time::Weekday::Sunday,
+        _ => return Err(format!("Expected a day of the week such as 'mon', found '{}'", value)),
+    };
+    Ok(day)
+}
+
+fn parse_bool(value: &str) -> Result<bool, String> {
+    match value {
+        "true" | "1" | "yes" => Ok(true),
+        "false" | "0" | "no" => Ok(false),
+        _ => Err(format!("Expected a boolean value, found '{}'", value)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::log_parser::LogEntry;
+
+    #[test]
+    fn it_creates_registered_filters() {
+        let registry = FilterRegistry::builtin(&FilterOptions::default());
+        assert_eq!("NOT (user = foo)", registry.create("user", "!=", "foo").unwrap().describe());
+        assert_eq!("Unknown filter name: 'foo'", registry.create("foo", "=", "bar").err().unwrap());
+        assert_eq!(
+            "Killed filter expects one of '=' or '!=', found '<'",
+            registry.create("killed", "<", "true").err().unwrap()
+        );
+        assert_eq!(
+            "Query filter only supports '~=', found '='",
+            registry.create("query", "=", "foo").err().unwrap()
+        );
+    }
+
+    #[test]
+    fn it_creates_custom_filters() {
+        let mut registry = FilterRegistry::empty();
+        assert!(registry.create("killed", "=", "true").is_err());
+        registry.register_flag("killed", "Killed", || Box::new(Killed));
+        assert!(registry.create("killed", "=", "true").is_ok());
+    }
+
+    #[test]
+    fn it_selects_wide_projections() {
+        let registry = FilterRegistry::builtin(&FilterOptions::default());
+        let filter = registry.create("projection_count", ">", "50").unwrap();
+        assert_eq!("projection_count >= 51", filter.describe());
+        let columns: Vec<String> = (1..=60).map(|i| format!("col{}", i)).collect();
+        let wide = LogEntry {
+            query: format!("SELECT {} FROM foo;", columns.join(", ")),
+            ..Default::default()
+        };
+        let narrow =
+            LogEntry { query: "SELECT id, name FROM foo;".to_string(), ..Default::default() };
+        assert!(filter.matches(&wide));
+        assert!(!filter.matches(&narrow));
+    }
+
+    #[test]
+    fn it_selects_business_hours() {
+        let registry = FilterRegistry::builtin(&FilterOptions::default());
+        let filters = [
+            registry.create("hour", ">=", "9").unwrap(),
+            registry.create("hour", "<", "17").unwrap(),
+        ];
+        let entry = |hour: u8| LogEntry {
+            timestamp: time::macros::datetime!(2021-05-11 00:00:00 UTC).replace_hour(hour).unwrap(),
+            ..Default::default()
+        };
+        let selected = |entry: &LogEntry| filters.iter().all(|filter| filter.matches(entry));
+        assert!(selected(&entry(13)));
+        assert!(selected(&entry(9)));
+        assert!(!selected(&entry(8)));
+        assert!(!selected(&entry(17)));
+        assert!(registry.create("hour", ">", "23").is_err());
+        assert!(registry.create("hour", "=", "24").is_err());
+    }
+
+    #[test]
+    fn it_parses_weekdays() {
+        let registry = FilterRegistry::builtin(&FilterOptions::default());
+        assert_eq!("weekday = mon", registry.create("weekday", "=", "Monday").unwrap().describe());
+        assert_eq!(
+            "weekday in mon,fri",
+            registry.create("weekday", "in", "fri, mon").unwrap().describe()
+        );
+        assert!(registry.create("weekday", "=", "someday").is_err());
+    }
+
+    #[test]
+    fn it_parses_query_times_with_units() {
+        let registry = FilterRegistry::builtin(&FilterOptions::default());
+        let describe = |value| registry.create("query_time", ">", value).unwrap().describe();
+        assert_eq!("query_time >= 0.500s", describe("500ms"));
+        assert_eq!("query_time >= 1.500s", describe("1.5s"));
+        assert_eq!("query_time >= 120.000s", describe("2m"));
+        assert_eq!("query_time >= 1.500s", describe("1.5"));
+        assert_eq!("query_time >= 0.000250s", describe("250us"));
+        assert_eq!("query_time >= 1.000900s", describe("1.0009s"));
+        assert!(registry.create("query_time", ">", "2h").is_err());
+    }
+
+    #[test]
+    fn it_compares_query_times_in_microseconds() {
+        let registry = FilterRegistry::builtin(&FilterOptions::default());
+        let entry = |usec: i64| LogEntry {
+            query_time: time::Duration::microseconds(usec),
+            ..Default::default()
+        };
+        let longer = registry.create("query_time", ">", "250us").unwrap();
+        assert!(!longer.matches(&entry(0)));
+        assert!(!longer.matches(&entry(249)));
+        assert!(longer.matches(&entry(250)));
+        assert!(longer.matches(&entry(1_000)));
+        let shorter = registry.create("query_time", "<", "250us").unwrap();
+        assert!(shorter.matches(&entry(249)));
+        assert!(!shorter.matches(&entry(251)));
+        let precise = registry.create("query_time", ">", "1.0009s").unwrap();
+        assert!(!precise.matches(&entry(1_000_000)));
+        assert!(precise.matches(&entry(1_000_900)));
+    }
+
+    #[test]
+    fn it_parses_lists() {
+        assert_eq!(3, parse_list("foo,bar, baz,").len());
+    }
+}
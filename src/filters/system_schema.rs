@@ -0,0 +1,85 @@
+use sqlparser::ast::{ObjectName, Statement, TableFactor};
+
+use crate::filters::{query_contains, Filter, Finder};
+use crate::log_parser::LogEntry;
+use crate::visit::Visitor;
+
+/// The schemas holding the server's own metadata and statistics rather than application data
+const SYSTEM_SCHEMAS: [&str; 4] = ["information_schema", "performance_schema", "mysql", "sys"];
+
+/// Matches queries that reference a table in one of the system schemas, which are typically
+/// issued by monitoring tools
+pub struct SystemSchema;
+
+#[derive(Default)]
+struct SystemTableFinder {
+    found: bool,
+}
+
+impl SystemTableFinder {
+    fn check(&mut self, name: &ObjectName) {
+        self.found |= name.0.len() > 1
+            && SYSTEM_SCHEMAS.iter().any(|schema| name.0[0].value.eq_ignore_ascii_case(schema));
+    }
+}
+
+impl Visitor for SystemTableFinder {
+    fn visit_statement(&mut self, statement: &Statement) {
+        if let Statement::Insert { table_name, .. } = statement {
+            self.check(table_name);
+        }
+    }
+
+    fn visit_table_factor(&mut self, table_factor: &TableFactor) {
+        if let TableFactor::Table { name, .. } = table_factor {
+            self.check(name);
+        }
+    }
+}
+
+impl Finder for SystemTableFinder {
+    fn found(&self) -> bool {
+        self.found
+    }
+}
+
+impl Filter for SystemSchema {
+    fn matches(&self, log_entry: &LogEntry) -> bool {
+        query_contains(log_entry, SystemTableFinder::default())
+    }
+
+    fn describe(&self) -> String {
+        "system_schema".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filters::query_entry;
+    use crate::filters::Not;
+
+    #[test]
+    fn it_detects_queries_against_system_schemas() {
+        let filter = SystemSchema;
+        assert!(filter.matches(&query_entry(
+            "SELECT table_name FROM information_schema.tables WHERE table_schema = 'shop';"
+        )));
+        assert!(filter.matches(&query_entry(
+            "SELECT * FROM orders o JOIN PERFORMANCE_SCHEMA.threads t ON t.thread_id = o.id;"
+        )));
+        assert!(filter.matches(&query_entry("SELECT * FROM sys.schema_table_statistics;")));
+        assert!(filter.matches(&query_entry("INSERT INTO mysql.user (User) VALUES ('foo');")));
+        assert!(!filter.matches(&query_entry("SELECT * FROM shop.orders WHERE id = 1;")));
+        assert!(!filter.matches(&query_entry("SELECT * FROM tables;")));
+    }
+
+    #[test]
+    fn it_excludes_monitoring_queries() {
+        let filter = Not::new(Box::new(SystemSchema));
+        assert!(
+            !filter.matches(&query_entry("SELECT COUNT(*) FROM information_schema.processlist;"))
+        );
+        assert!(filter.matches(&query_entry("SELECT * FROM orders WHERE customer_id = 42;")));
+    }
+}
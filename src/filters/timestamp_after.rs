@@ -0,0 +1,27 @@
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+
+use crate::filters::Filter;
+use crate::log_parser::LogEntry;
+
+/// Selects entries logged at or after the given time
+pub struct TimestampAfter {
+    timestamp: OffsetDateTime,
+}
+
+impl TimestampAfter {
+    pub fn new(timestamp: OffsetDateTime) -> TimestampAfter {
+        TimestampAfter { timestamp }
+    }
+}
+
+impl Filter for TimestampAfter {
+    fn matches(&self, log_entry: &LogEntry) -> bool {
+        log_entry.timestamp >= self.timestamp
+    }
+
+    fn describe(&self) -> String {
+        let timestamp = self.timestamp.format(&Rfc3339).unwrap_or_default();
+        format!("timestamp >= {}", timestamp)
+    }
+}
@@ -1,18 +1,77 @@
+use std::borrow::Cow;
+
+use unicode_normalization::{is_nfc, UnicodeNormalization};
+
 use crate::filters::Filter;
 use crate::log_parser::LogEntry;
 
 pub struct UserEquals {
     name: String,
+    ignore_case: bool,
+    unicode_normalize: bool,
 }
 
 impl UserEquals {
     pub fn new(name: String) -> UserEquals {
-        UserEquals { name }
+        UserEquals { name, ignore_case: false, unicode_normalize: false }
+    }
+
+    /// Compares user names regardless of their (ASCII) case
+    pub fn ignoring_case(name: String) -> UserEquals {
+        UserEquals { name, ignore_case: true, unicode_normalize: false }
+    }
+
+    /// Compares user names in Unicode normalization form C, so that e.g. a precomposed "é"
+    /// matches an "e" followed by a combining acute accent
+    pub fn normalizing_unicode(self) -> UserEquals {
+        UserEquals { name: self.name.nfc().collect(), unicode_normalize: true, ..self }
     }
 }
 
 impl Filter for UserEquals {
     fn matches(&self, log_entry: &LogEntry) -> bool {
-        self.name == log_entry.user
+        let user = if self.unicode_normalize && !is_nfc(&log_entry.user) {
+            Cow::Owned(log_entry.user.nfc().collect())
+        } else {
+            Cow::Borrowed(log_entry.user.as_str())
+        };
+        if self.ignore_case {
+            self.name.eq_ignore_ascii_case(&user)
+        } else {
+            self.name == user
+        }
+    }
+
+    fn describe(&self) -> String {
+        let op = if self.ignore_case { "~~=" } else { "=" };
+        format!("user {} {}", op, self.name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_ignores_case_only_if_asked_to() {
+        let entry = LogEntry { user: "FOO".to_string(), ..Default::default() };
+        assert!(!UserEquals::new("foo".to_string()).matches(&entry));
+        assert!(UserEquals::new("FOO".to_string()).matches(&entry));
+        assert!(UserEquals::ignoring_case("foo".to_string()).matches(&entry));
+        assert!(!UserEquals::ignoring_case("fo".to_string()).matches(&entry));
+    }
+
+    #[test]
+    fn it_normalizes_unicode_only_if_asked_to() {
+        // "José" with a combining acute accent (NFD) vs. a precomposed "é" (NFC)
+        let entry = LogEntry { user: "Jose\u{301}".to_string(), ..Default::default() };
+        assert!(!UserEquals::new("Jos\u{e9}".to_string()).matches(&entry));
+        assert!(UserEquals::new("Jos\u{e9}".to_string()).normalizing_unicode().matches(&entry));
+        assert!(UserEquals::ignoring_case("jos\u{e9}".to_string())
+            .normalizing_unicode()
+            .matches(&entry));
+
+        let entry = LogEntry { user: "Jos\u{e9}".to_string(), ..Default::default() };
+        assert!(UserEquals::new("Jose\u{301}".to_string()).normalizing_unicode().matches(&entry));
     }
 }
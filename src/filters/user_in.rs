@@ -0,0 +1,40 @@
+use ahash::HashSet;
+
+use crate::filters::Filter;
+use crate::log_parser::LogEntry;
+
+pub struct UserIn {
+    names: HashSet<String>,
+}
+
+impl UserIn {
+    pub fn new(names: HashSet<String>) -> UserIn {
+        UserIn { names }
+    }
+}
+
+impl Filter for UserIn {
+    fn matches(&self, log_entry: &LogEntry) -> bool {
+        self.names.contains(&log_entry.user)
+    }
+
+    fn describe(&self) -> String {
+        let mut names: Vec<&str> = self.names.iter().map(String::as_str).collect();
+        names.sort_unstable();
+        format!("user in {}", names.join(","))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_matches_users_in_the_set() {
+        let filter = UserIn::new(["foo", "bar"].map(String::from).into_iter().collect());
+        let entry = |user: &str| LogEntry { user: user.to_string(), ..Default::default() };
+        assert!(filter.matches(&entry("foo")));
+        assert!(filter.matches(&entry("bar")));
+        assert!(!filter.matches(&entry("baz")));
+    }
+}
@@ -19,4 +19,8 @@ impl Filter for UserMatches {
     fn matches(&self, log_entry: &LogEntry) -> bool {
         self.regex.is_match(&log_entry.user)
     }
+
+    fn describe(&self) -> String {
+        format!("user ~= {}", self.regex.as_str())
+    }
 }
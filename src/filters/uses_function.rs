@@ -0,0 +1,66 @@
+use regex::{Regex, RegexBuilder};
+use sqlparser::ast::Expr;
+
+use crate::filters::{query_contains, Filter, Finder};
+use crate::log_parser::LogEntry;
+use crate::visit::Visitor;
+
+pub struct UsesFunction {
+    regex: Regex,
+}
+
+impl UsesFunction {
+    pub fn new(pattern: String) -> Result<UsesFunction, String> {
+        let regex = RegexBuilder::new(&pattern)
+            .case_insensitive(true)
+            .build()
+            .map_err(|_err| format!("Invalid regular expression: '{}'", &pattern))?;
+        Ok(UsesFunction { regex })
+    }
+}
+
+struct FunctionFinder<'a> {
+    regex: &'a Regex,
+    found: bool,
+}
+
+impl Visitor for FunctionFinder<'_> {
+    fn visit_expr(&mut self, expr: &Expr) {
+        if let Expr::Function(function) = expr {
+            self.found |= self.regex.is_match(&function.name.to_string());
+        }
+    }
+}
+
+impl Finder for FunctionFinder<'_> {
+    fn found(&self) -> bool {
+        self.found
+    }
+}
+
+impl Filter for UsesFunction {
+    fn matches(&self, log_entry: &LogEntry) -> bool {
+        query_contains(log_entry, FunctionFinder { regex: &self.regex, found: false })
+    }
+
+    fn describe(&self) -> String {
+        format!("function ~= {}", self.regex.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filters::query_entry;
+
+    #[test]
+    fn it_detects_functions_in_where_clauses() {
+        let filter = UsesFunction::new("^NOW$".to_string()).unwrap();
+        assert!(filter.matches(&query_entry("SELECT * FROM foo WHERE created < now();")));
+        assert!(filter.matches(&query_entry(
+            "SELECT * FROM foo WHERE created < DATE_SUB(NOW(), INTERVAL 1 DAY);"
+        )));
+        assert!(!filter.matches(&query_entry("SELECT * FROM foo WHERE created < '2021-05-11';")));
+        assert!(!filter.matches(&query_entry("SELECT * FROM foo WHERE id = RAND();")));
+    }
+}
@@ -0,0 +1,49 @@
+use sqlparser::ast::Statement;
+
+use crate::filters::Filter;
+use crate::log_parser::LogEntry;
+use crate::visit::parse;
+
+/// Selects statements that used a temporary table. Percona Server logs this for implicit
+/// temporary tables (e.g. for GROUP BY on unindexed columns) in its `Tmp_table` field.
+/// If the field is absent, only statements explicitly creating a temporary table are selected.
+pub struct UsesTmpTable;
+
+impl Filter for UsesTmpTable {
+    fn matches(&self, log_entry: &LogEntry) -> bool {
+        if let Some(tmp_table) = log_entry.tmp_table {
+            return tmp_table;
+        }
+        let Some(ast) = parse(&log_entry.query) else {
+            return false;
+        };
+        ast.iter().any(|stmt| matches!(stmt, Statement::CreateTable { temporary: true, .. }))
+    }
+
+    fn describe(&self) -> String {
+        "tmp_table".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(query: &str, tmp_table: Option<bool>) -> LogEntry {
+        LogEntry { query: query.to_string(), tmp_table, ..Default::default() }
+    }
+
+    #[test]
+    fn it_uses_the_logged_field_if_present() {
+        let query = "SELECT foo, COUNT(*) FROM bar GROUP BY foo;";
+        assert!(UsesTmpTable.matches(&entry(query, Some(true))));
+        assert!(!UsesTmpTable.matches(&entry(query, Some(false))));
+        assert!(!UsesTmpTable.matches(&entry(query, None)));
+    }
+
+    #[test]
+    fn it_detects_explicit_temporary_tables() {
+        assert!(UsesTmpTable.matches(&entry("CREATE TEMPORARY TABLE foo (id INT);", None)));
+        assert!(!UsesTmpTable.matches(&entry("CREATE TABLE foo (id INT);", None)));
+    }
+}
@@ -0,0 +1,63 @@
+use time::UtcOffset;
+
+use crate::filters::Filter;
+use crate::log_parser::LogEntry;
+
+/// Selects entries logged on one of the given days of the week, in UTC
+pub struct Weekday {
+    days: Vec<time::Weekday>,
+}
+
+impl Weekday {
+    pub fn new(days: Vec<time::Weekday>) -> Weekday {
+        Weekday { days }
+    }
+}
+
+impl Filter for Weekday {
+    fn matches(&self, log_entry: &LogEntry) -> bool {
+        self.days.contains(&log_entry.timestamp.to_offset(UtcOffset::UTC).weekday())
+    }
+
+    fn describe(&self) -> String {
+        let mut days = self.days.clone();
+        days.sort_unstable_by_key(|day| day.number_days_from_monday());
+        days.dedup();
+        let names: Vec<&str> = days.iter().map(|day| short_name(*day)).collect();
+        match names.as_slice() {
+            [name] => format!("weekday = {}", name),
+            _ => format!("weekday in {}", names.join(",")),
+        }
+    }
+}
+
+fn short_name(day: time::Weekday) -> &'static str {
+    match day {
+        time::Weekday::Monday => "mon",
+        time::Weekday::Tuesday => "tue",
+        time::Weekday::Wednesday => "wed",
+        time::Weekday::Thursday => "thu",
+        time::Weekday::Friday => "fri",
+        time::Weekday::Saturday => "sat",
+        time::Weekday::Sunday => "sun",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use time::macros::datetime;
+
+    use super::*;
+
+    #[test]
+    fn it_matches_entries_on_the_given_days() {
+        let filter = Weekday::new(vec![time::Weekday::Saturday, time::Weekday::Sunday]);
+        let entry = |timestamp| LogEntry { timestamp, ..Default::default() };
+        // 2021-05-15 was a Saturday
+        assert!(filter.matches(&entry(datetime!(2021-05-15 12:00:00 UTC))));
+        assert!(!filter.matches(&entry(datetime!(2021-05-14 12:00:00 UTC))));
+        // Monday 01:00 in UTC+2 is still Sunday in UTC
+        assert!(filter.matches(&entry(datetime!(2021-05-17 01:00:00 +2))));
+        assert_eq!("weekday in sat,sun", filter.describe());
+    }
+}
@@ -0,0 +1,373 @@
+use std::io;
+use std::io::Write;
+
+use serde::Serialize;
+use time::format_description::well_known::Rfc3339;
+use time::format_description::{self, OwnedFormatItem};
+use time::OffsetDateTime;
+
+use crate::aggregate::AggregateLogEntry;
+use crate::normalize::fingerprint;
+
+/// How to render absolute timestamps, given either as a preset name or as a format description
+/// as documented at https://time-rs.github.io/book/api/format-description.html
+#[derive(Clone, Debug)]
+pub enum TimeFormat {
+    Rfc3339,
+    Description(OwnedFormatItem),
+}
+
+impl TimeFormat {
+    pub fn parse(format: &str) -> Result<TimeFormat, String> {
+        let description = match format {
+            "rfc3339" => return Ok(TimeFormat::Rfc3339),
+            "date" => "[year]-[month]-[day]",
+            "datetime" => "[year]-[month]-[day] [hour]:[minute]:[second]",
+            custom => custom,
+        };
+        format_description::parse_owned::<2>(description)
+            .map(TimeFormat::Description)
+            .map_err(|err| format!("Invalid time format '{}': {}", format, err))
+    }
+
+    pub fn format(&self, timestamp: OffsetDateTime) -> String {
+        let formatted = match self {
+            TimeFormat::Rfc3339 => timestamp.format(&Rfc3339),
+            TimeFormat::Description(description) => timestamp.format(description),
+        };
+        formatted.unwrap_or_else(|_| timestamp.to_string())
+    }
+}
+
+/// Renders the age of a timestamp relative to `now` in a compact human-friendly form,
+/// using the largest unit that fits (e.g. "3h ago", "2d ago").
+pub fn relative_time(timestamp: OffsetDateTime, now: OffsetDateTime) -> String {
+    let seconds = (now - timestamp).whole_seconds().max(0);
+    match seconds {
+        0..=59 => format!("{}s ago", seconds),
+        60..=3599 => format!("{}m ago", seconds / 60),
+        3600..=86399 => format!("{}h ago", seconds / 3600),
+        _ => format!("{}d ago", seconds / 86400),
+    }
+}
+
+/// Renders a number with commas separating groups of thousands, e.g. "1,000,000"
+pub fn group_thousands(value: i64) -> String {
+    let digits = value.unsigned_abs().to_string();
+    let mut result = String::with_capacity(digits.len() + digits.len() / 3 + 1);
+    if value < 0 {
+        result.push('-');
+    }
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            result.push(',');
+        }
+        result.push(digit);
+    }
+    result
+}
+
+/// Writes one line per record with the fingerprint, count and query, separated by tabs
+pub fn write_inventory(out: &mut impl Write, entries: &[&AggregateLogEntry]) {
+    for entry in entries {
+        writeln!(out, "{}\t{}\t{}", fingerprint(&entry.query), entry.count, entry.query.trim())
+            .unwrap();
+    }
+}
+
+/// Writes the slowest raw query of each record as a commented SQL script,
+/// e.g. to feed them to a client for running EXPLAIN
+pub fn write_repro_script(out: &mut impl Write, entries: &[&AggregateLogEntry]) {
+    for (i, entry) in entries.iter().enumerate() {
+        writeln!(
+            out,
+            "-- group #{}, fingerprint {}, {:.3} s\n{}\n",
+            i + 1,
+            fingerprint(&entry.query),
+            entry.max_query_time as f64 / 1_000_000.0,
+            entry.slowest_query(),
+        )
+        .unwrap();
+    }
+}
+
+/// Writes the slowest raw query of each record as a SQL script for walking through the records
+/// one by one, each preceded by a comment block with the record's statistics
+pub fn write_annotated_script(out: &mut impl Write, entries: &[&AggregateLogEntry]) {
+    let seconds = |usec: i128| usec as f64 / 1_000_000.0;
+    for (i, entry) in entries.iter().enumerate() {
+        writeln!(out, "-- #{}: {}", i + 1, entry.query.trim().replace('\n', " ")).unwrap();
+        writeln!(out, "-- fingerprint {}", fingerprint(&entry.query)).unwrap();
+        writeln!(
+            out,
+            "-- count {}, total {:.3} s, avg {:.3} s, max {:.3} s, p95 {:.3} s",
+            entry.count,
+            seconds(entry.total_query_time),
+            seconds(entry.avg_query_time),
+            seconds(entry.max_query_time),
+            seconds(entry.p95_query_time),
+        )
+        .unwrap();
+        writeln!(out, "-- max rows examined {}", entry.max_rows_examined).unwrap();
+        writeln!(out, "{}\n", entry.slowest_query()).unwrap();
+    }
+}
+
+/// Passes output through to the inner writer until an optional number of bytes is reached.
+/// Output is only written in complete lines, so everything from the first line that would
+/// exceed the limit onwards is silently discarded.
+pub struct LimitedWriter<W: Write> {
+    inner: W,
+    remaining: Option<usize>,
+    line: Vec<u8>,
+    exhausted: bool,
+}
+
+impl<W: Write> LimitedWriter<W> {
+    pub fn new(inner: W, limit: Option<usize>) -> Self {
+        LimitedWriter { inner, remaining: limit, line: Vec::new(), exhausted: false }
+    }
+
+    fn write_line(&mut self) -> io::Result<()> {
+        let Some(remaining) = self.remaining else {
+            return self.inner.write_all(&self.line);
+        };
+        if self.line.len() <= remaining {
+            self.remaining = Some(remaining - self.line.len());
+            self.inner.write_all(&self.line)?;
+        } else {
+            self.exhausted = true;
+        }
+        self.line.clear();
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for LimitedWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.remaining.is_none() {
+            return self.inner.write(buf);
+        }
+        if !self.exhausted {
+            for line in buf.split_inclusive(|&byte| byte == b'\n') {
+                self.line.extend_from_slice(line);
+                if line.ends_with(b"\n") {
+                    self.write_line()?;
+                }
+            }
+        }
+        Ok(buf.len())
+    }
+
+    /// Also writes an incomplete last line if it fits
+    fn flush(&mut self) -> io::Result<()> {
+        if !self.exhausted && !self.line.is_empty() {
+            self.write_line()?;
+        }
+        self.inner.flush()
+    }
+}
+
+/// Writes a value as a single line of JSON
+pub fn write_json_line(out: &mut impl Write, value: &impl Serialize) {
+    serde_json::to_writer(&mut *out, value).unwrap();
+    writeln!(out).unwrap();
+}
+
+/// Writes a value as JSON, either as a single line or indented for reading
+pub fn write_json(out: &mut impl Write, value: &impl Serialize, pretty: bool) {
+    if pretty {
+        serde_json::to_writer_pretty(&mut *out, value).unwrap();
+        writeln!(out).unwrap();
+    } else {
+        write_json_line(out, value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use time::macros::datetime;
+    use time::Duration;
+
+    use super::*;
+    use crate::aggregate::{aggregate_normalized, sort_aggregated, AggregateOptions};
+    use crate::log_parser::LogEntry;
+    use crate::normalize::{normalize, NormalizeOptions};
+    use crate::opt::SortOrder;
+
+    #[test]
+    fn it_writes_the_slowest_queries_as_a_script() {
+        let entry = |query: &str, query_time_ms: i64| LogEntry {
+            query: query.to_string(),
+            query_time: Duration::milliseconds(query_time_ms),
+            ..Default::default()
+        };
+        let options = NormalizeOptions::default();
+        let normalized = [
+            entry("SELECT * FROM foo WHERE id = 1;", 1000),
+            entry("SELECT * FROM foo WHERE id = 2;", 2150),
+            entry("SELECT * FROM foo WHERE id = 3;", 500),
+            entry("DELETE FROM bar WHERE id = 4;", 300),
+        ]
+        .into_iter()
+        .map(|e| normalize(e, &options))
+        .collect();
+        let mut aggregated: Vec<AggregateLogEntry> =
+            aggregate_normalized(normalized, AggregateOptions::default()).into_values().collect();
+        sort_aggregated(&mut aggregated, Some(SortOrder::MaxTime), None);
+
+        let mut script = Vec::new();
+        write_repro_script(&mut script, &aggregated.iter().rev().collect::<Vec<_>>());
+        let expected = format!(
+            "-- group #1, fingerprint {}, 2.150 s\nSELECT * FROM foo WHERE id = 2;\n\n\
+            -- group #2, fingerprint {}, 0.300 s\nDELETE FROM bar WHERE id = 4;\n\n",
+            fingerprint(&aggregated[1].query),
+            fingerprint(&aggregated[0].query),
+        );
+        assert_eq!(expected, String::from_utf8(script).unwrap());
+    }
+
+    #[test]
+    fn it_annotates_each_query_with_its_statistics() {
+        let entry = |query: &str, query_time_ms: i64| LogEntry {
+            query: query.to_string(),
+            query_time: Duration::milliseconds(query_time_ms),
+            rows_examined: 10,
+            ..Default::default()
+        };
+        let options = NormalizeOptions::default();
+        let normalized = [
+            entry("SELECT * FROM foo WHERE id = 1;", 1000),
+            entry("SELECT * FROM foo WHERE id = 2;", 2150),
+            entry("DELETE FROM bar WHERE id = 3;", 300),
+        ]
+        .into_iter()
+        .map(|e| normalize(e, &options))
+        .collect();
+        let mut aggregated: Vec<AggregateLogEntry> =
+            aggregate_normalized(normalized, AggregateOptions::default()).into_values().collect();
+        sort_aggregated(&mut aggregated, Some(SortOrder::MaxTime), None);
+
+        let mut script = Vec::new();
+        write_annotated_script(&mut script, &aggregated.iter().rev().collect::<Vec<_>>());
+        let script = String::from_utf8(script).unwrap();
+        let lines: Vec<&str> = script.lines().collect();
+        let queries: Vec<usize> = (0..lines.len())
+            .filter(|&i| !lines[i].is_empty() && !lines[i].starts_with("--"))
+            .collect();
+        assert_eq!(2, queries.len());
+        assert_eq!("SELECT * FROM foo WHERE id = 2;", lines[queries[0]]);
+        assert_eq!(
+            "-- count 2, total 3.150 s, avg 1.575 s, max 2.150 s, p95 2.150 s",
+            lines[queries[0] - 2]
+        );
+        assert_eq!("DELETE FROM bar WHERE id = 3;", lines[queries[1]]);
+        assert_eq!(
+            "-- count 1, total 0.300 s, avg 0.300 s, max 0.300 s, p95 0.300 s",
+            lines[queries[1] - 2]
+        );
+        assert!(lines[queries[1] - 4].starts_with("-- #2: DELETE FROM bar WHERE id = ?"));
+    }
+
+    #[test]
+    fn it_writes_pretty_json_only_if_asked_to() {
+        let entry = LogEntry { user: "foo".to_string(), ..Default::default() };
+
+        let mut compact = Vec::new();
+        write_json(&mut compact, &entry, false);
+        let compact = String::from_utf8(compact).unwrap();
+        assert_eq!(1, compact.lines().count());
+        assert!(compact.contains(r#"{"timestamp":"#));
+
+        let mut pretty = Vec::new();
+        write_json(&mut pretty, &entry, true);
+        let pretty = String::from_utf8(pretty).unwrap();
+        assert!(pretty.lines().count() > 1);
+        assert!(pretty.contains("\n  \"user\": \"foo\","));
+    }
+
+    #[test]
+    fn it_writes_an_inventory_of_queries() {
+        let entry = |query: &str| LogEntry { query: query.to_string(), ..Default::default() };
+        let normalized = [
+            "SELECT * FROM foo WHERE id = 1;",
+            "SELECT * FROM foo WHERE id = 2;",
+            "DELETE FROM bar WHERE id = 3;",
+            "SELECT * FROM foo WHERE id = 4;",
+        ]
+        .into_iter()
+        .map(|query| normalize(entry(query), &NormalizeOptions::default()))
+        .collect();
+        let mut aggregated: Vec<AggregateLogEntry> =
+            aggregate_normalized(normalized, AggregateOptions::default()).into_values().collect();
+        sort_aggregated(&mut aggregated, Some(SortOrder::Count), None);
+
+        let mut inventory = Vec::new();
+        write_inventory(&mut inventory, &aggregated.iter().rev().collect::<Vec<_>>());
+        let expected = format!(
+            "{}\t3\tSELECT * FROM foo WHERE id = ?;\n{}\t1\tDELETE FROM bar WHERE id = ?;\n",
+            fingerprint(&aggregated[1].query),
+            fingerprint(&aggregated[0].query),
+        );
+        assert_eq!(expected, String::from_utf8(inventory).unwrap());
+    }
+
+    #[test]
+    fn it_limits_output_to_complete_lines() {
+        let mut out = LimitedWriter::new(Vec::new(), Some(12));
+        write!(out, "first\nsec").unwrap();
+        writeln!(out, "ond").unwrap();
+        writeln!(out, "third").unwrap();
+        writeln!(out, "4").unwrap();
+        out.flush().unwrap();
+        assert_eq!(b"first\n", out.inner.as_slice());
+
+        let mut out = LimitedWriter::new(Vec::new(), Some(12));
+        write!(out, "first\nlast").unwrap();
+        out.flush().unwrap();
+        assert_eq!(b"first\nlast", out.inner.as_slice());
+
+        let mut out = LimitedWriter::new(Vec::new(), None);
+        writeln!(out, "first\nsecond\nthird").unwrap();
+        assert_eq!(b"first\nsecond\nthird\n", out.inner.as_slice());
+    }
+
+    #[test]
+    fn it_groups_thousands() {
+        assert_eq!("0", group_thousands(0));
+        assert_eq!("999", group_thousands(999));
+        assert_eq!("1,000", group_thousands(1000));
+        assert_eq!("100,000", group_thousands(100_000));
+        assert_eq!("1,000,000", group_thousands(1_000_000));
+        assert_eq!("-12,345", group_thousands(-12_345));
+    }
+
+    #[test]
+    fn it_formats_relative_times() {
+        let now = OffsetDateTime::now_utc();
+        assert_eq!("1h ago", relative_time(now - Duration::hours(1), now));
+        assert_eq!("42s ago", relative_time(now - Duration::seconds(42), now));
+        assert_eq!("59m ago", relative_time(now - Duration::seconds(3599), now));
+        assert_eq!("2d ago", relative_time(now - Duration::hours(50), now));
+        assert_eq!("0s ago", relative_time(now + Duration::minutes(5), now));
+    }
+
+    #[test]
+    fn it_formats_timestamps() {
+        let timestamp = datetime!(2019-07-30 13:01:34.887103 UTC);
+
+        let format = TimeFormat::parse("[year]-[month]-[day]").unwrap();
+        assert_eq!("2019-07-30", format.format(timestamp));
+
+        let format = TimeFormat::parse("datetime").unwrap();
+        assert_eq!("2019-07-30 13:01:34", format.format(timestamp));
+
+        let format = TimeFormat::parse("rfc3339").unwrap();
+        assert_eq!("2019-07-30T13:01:34.887103Z", format.format(timestamp));
+    }
+
+    #[test]
+    fn it_rejects_invalid_time_formats() {
+        assert!(TimeFormat::parse("[year]-[moonphase]").is_err());
+    }
+}
@@ -0,0 +1,93 @@
+use std::fmt;
+
+use serde::Serialize;
+
+use crate::log_parser::LogEntry;
+
+pub const DEFAULT_EDGES: &str = "1,5,30";
+
+/// The number of entries whose query time (in seconds) lies in the range from `lower`
+/// (inclusive) to `upper` (exclusive); the last bucket has no upper bound
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Bucket {
+    pub lower: f64,
+    pub upper: Option<f64>,
+    pub count: usize,
+}
+
+impl fmt::Display for Bucket {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let range = match self.upper {
+            Some(upper) => format!("{}-{}s", self.lower, upper),
+            None => format!("{}s+", self.lower),
+        };
+        write!(f, "{:>10}: {}", range, self.count)
+    }
+}
+
+/// Parses a comma-separated list of strictly ascending, positive bucket edges in seconds
+pub fn parse_edges(value: &str) -> Result<Vec<f64>, String> {
+    let edges = value
+        .split(',')
+        .map(|edge| edge.trim().parse::<f64>())
+        .collect::<Result<Vec<f64>, _>>()
+        .map_err(|_err| {
+            format!("Histogram buckets must be a list of numbers, found '{}'", value)
+        })?;
+    let ascending = edges.windows(2).all(|pair| pair[0] < pair[1]);
+    if edges.iter().any(|&edge| edge <= 0.0) || !ascending {
+        return Err(format!("Histogram buckets must be positive and ascending, found '{}'", value));
+    }
+    Ok(edges)
+}
+
+/// Counts the entries in the query time buckets delimited by the given edges,
+/// starting at zero and ending with an open bucket above the last edge
+pub fn query_time_histogram(entries: &[LogEntry], edges: &[f64]) -> Vec<Bucket> {
+    let lowers = [0.0].into_iter().chain(edges.iter().copied());
+    let uppers = edges.iter().copied().map(Some).chain([None]);
+    let mut buckets: Vec<Bucket> =
+        lowers.zip(uppers).map(|(lower, upper)| Bucket { lower, upper, count: 0 }).collect();
+
+    for entry in entries {
+        let seconds = entry.query_time.as_seconds_f64();
+        let index = edges.partition_point(|&edge| edge <= seconds);
+        buckets[index].count += 1;
+    }
+    buckets
+}
+
+#[cfg(test)]
+mod tests {
+    use time::Duration;
+
+    use super::*;
+
+    fn entry(query_time_ms: i64) -> LogEntry {
+        LogEntry { query_time: Duration::milliseconds(query_time_ms), ..Default::default() }
+    }
+
+    #[test]
+    fn it_counts_entries_per_bucket() {
+        let entries: Vec<LogEntry> =
+            [200, 999, 1000, 4000, 5000, 29_999, 30_000, 120_000].into_iter().map(entry).collect();
+
+        let edges = parse_edges(DEFAULT_EDGES).unwrap();
+        let counts: Vec<usize> =
+            query_time_histogram(&entries, &edges).iter().map(|b| b.count).collect();
+        assert_eq!(vec![2, 2, 2, 2], counts);
+
+        let edges = parse_edges("0.5, 60").unwrap();
+        let buckets = query_time_histogram(&entries, &edges);
+        assert_eq!(Bucket { lower: 0.0, upper: Some(0.5), count: 1 }, buckets[0]);
+        assert_eq!(Bucket { lower: 0.5, upper: Some(60.0), count: 6 }, buckets[1]);
+        assert_eq!(Bucket { lower: 60.0, upper: None, count: 1 }, buckets[2]);
+    }
+
+    #[test]
+    fn it_rejects_invalid_edges() {
+        assert!(parse_edges("5,1").is_err());
+        assert!(parse_edges("0,1").is_err());
+        assert!(parse_edges("1,foo").is_err());
+    }
+}
@@ -0,0 +1,209 @@
+use std::fmt;
+
+use ahash::{HashMap, HashMapExt};
+use serde::Serialize;
+use sqlparser::ast::{BinaryOperator, Expr, Select, Statement};
+
+use crate::normalize::NormalizedLogEntry;
+use crate::visit::{parse, walk_statements, Visitor};
+
+/// Queries examining at least this many rows per row sent are considered for suggestions
+const MIN_SCAN_RATIO: f64 = 100.0;
+/// Queries examining fewer rows per execution are fast enough even with a full scan
+const MIN_AVG_ROWS_EXAMINED: f64 = 1000.0;
+
+/// Columns that an index might help with, guessed from the WHERE clause of a normalized query
+/// that examines far more rows than it returns. Without access to the actual schema and
+/// query plan, this is a heuristic that has to be verified with EXPLAIN.
+#[derive(Debug, Serialize)]
+pub struct IndexSuggestion {
+    pub query: String,
+    pub count: i64,
+    pub rows_examined: i64,
+    pub rows_sent: i64,
+    pub columns: Vec<String>,
+}
+
+impl IndexSuggestion {
+    pub fn scan_ratio(&self) -> f64 {
+        self.rows_examined as f64 / self.rows_sent.max(1) as f64
+    }
+}
+
+impl fmt::Display for IndexSuggestion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{}", self.query)?;
+        writeln!(
+            f,
+            "  count {}, rows examined {}, rows sent {} ({:.0} examined per row sent)",
+            self.count,
+            self.rows_examined,
+            self.rows_sent,
+            self.scan_ratio()
+        )?;
+        write!(f, "  Candidate columns: {}", self.columns.join(", "))
+    }
+}
+
+#[derive(Default)]
+struct Group {
+    count: i64,
+    rows_examined: i64,
+    rows_sent: i64,
+    raw_query: String,
+}
+
+/// Groups the entries by normalized query and suggests index candidates for those groups
+/// with a bad ratio of rows examined to rows sent, ordered by the number of rows examined
+pub fn suggest_indexes(entries: Vec<NormalizedLogEntry>) -> Vec<IndexSuggestion> {
+    let mut groups: HashMap<String, Group> = HashMap::new();
+    for entry in entries {
+        let group = groups.entry(entry.normalized_query).or_default();
+        group.count += 1;
+        group.rows_examined += i64::from(entry.entry.rows_examined);
+        group.rows_sent += i64::from(entry.entry.rows_sent);
+        if group.raw_query.is_empty() {
+            group.raw_query = entry.entry.query;
+        }
+    }
+
+    let mut suggestions: Vec<IndexSuggestion> = groups
+        .into_iter()
+        .filter_map(|(query, group)| {
+            let suggestion = IndexSuggestion {
+                query,
+                count: group.count,
+                rows_examined: group.rows_examined,
+                rows_sent: group.rows_sent,
+                columns: filtered_columns(&group.raw_query),
+            };
+            let avg_rows_examined = group.rows_examined as f64 / group.count as f64;
+            let bad_scan = suggestion.scan_ratio() >= MIN_SCAN_RATIO
+                && avg_rows_examined >= MIN_AVG_ROWS_EXAMINED;
+            (bad_scan && !suggestion.columns.is_empty()).then_some(suggestion)
+        })
+        .collect();
+    suggestions.sort_by_key(|suggestion| std::cmp::Reverse(suggestion.rows_examined));
+    suggestions
+}
+
+#[derive(Default)]
+struct ColumnCollector {
+    columns: Vec<String>,
+}
+
+impl ColumnCollector {
+    /// Collects the columns that are compared with something in a WHERE clause
+    fn collect(&mut self, expr: &Expr) {
+        match expr {
+            Expr::BinaryOp { left, op: BinaryOperator::And | BinaryOperator::Or, right } => {
+                self.collect(left);
+                self.collect(right);
+            }
+            Expr::BinaryOp { left, op, right } if is_comparison(op) => {
+                self.add(left);
+                self.add(right);
+            }
+            Expr::Nested(expr) => self.collect(expr),
+            Expr::InList { expr, .. }
+            | Expr::InSubquery { expr, .. }
+            | Expr::Between { expr, .. }
+            | Expr::Like { expr, .. }
+            | Expr::IsNull(expr)
+            | Expr::IsNotNull(expr) => self.add(expr),
+            _ => (),
+        }
+    }
+
+    fn add(&mut self, expr: &Expr) {
+        let column = match expr {
+            Expr::Identifier(ident) => ident.value.clone(),
+            Expr::CompoundIdentifier(idents) => {
+                idents.iter().map(|ident| ident.value.as_str()).collect::<Vec<&str>>().join(".")
+            }
+            _ => return,
+        };
+        if !self.columns.contains(&column) {
+            self.columns.push(column);
+        }
+    }
+}
+
+impl Visitor for ColumnCollector {
+    fn visit_select(&mut self, select: &Select) {
+        if let Some(selection) = &select.selection {
+            self.collect(selection);
+        }
+    }
+}
+
+fn is_comparison(op: &BinaryOperator) -> bool {
+    matches!(
+        op,
+        BinaryOperator::Eq
+            | BinaryOperator::NotEq
+            | BinaryOperator::Lt
+            | BinaryOperator::LtEq
+            | BinaryOperator::Gt
+            | BinaryOperator::GtEq
+            | BinaryOperator::Spaceship
+    )
+}
+
+/// Extracts the columns filtered on in the WHERE clauses of a query, including subqueries
+fn filtered_columns(query: &str) -> Vec<String> {
+    let Some(ast) = parse(query) else {
+        return Vec::new();
+    };
+    let mut collector = ColumnCollector::default();
+    for stmt in &ast {
+        if let Statement::Update { selection: Some(selection), .. }
+        | Statement::Delete { selection: Some(selection), .. } = stmt
+        {
+            collector.collect(selection);
+        }
+    }
+    walk_statements(&ast, &mut collector);
+    collector.columns
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::log_parser::LogEntry;
+    use crate::normalize::NormalizeCache;
+
+    fn entry(query: &str, rows_examined: i32, rows_sent: i32) -> NormalizedLogEntry {
+        let entry =
+            LogEntry { query: query.to_string(), rows_examined, rows_sent, ..Default::default() };
+        NormalizeCache::default().normalize(entry)
+    }
+
+    #[test]
+    fn it_suggests_columns_of_queries_with_bad_scan_ratios() {
+        let entries = vec![
+            entry("SELECT * FROM foo WHERE a = 1 AND b = 'x';", 50_000, 1),
+            entry("SELECT * FROM foo WHERE a = 2 AND b = 'y';", 40_000, 2),
+            entry("SELECT * FROM bar WHERE c = 1;", 10, 1),
+            entry("SELECT * FROM baz;", 100_000, 10),
+        ];
+
+        let suggestions = suggest_indexes(entries);
+        assert_eq!(1, suggestions.len());
+        assert_eq!(vec!["a", "b"], suggestions[0].columns);
+        assert_eq!(2, suggestions[0].count);
+        assert_eq!(90_000, suggestions[0].rows_examined);
+    }
+
+    #[test]
+    fn it_extracts_filtered_columns() {
+        assert_eq!(
+            vec!["f.a", "b", "c", "d"],
+            filtered_columns(
+                "SELECT * FROM foo f WHERE (f.a > 1 OR b IN (1, 2)) AND c IS NULL AND d LIKE 'x%';"
+            )
+        );
+        assert_eq!(vec!["id"], filtered_columns("DELETE FROM foo WHERE id = 1;"));
+        assert!(filtered_columns("SELECT * FROM foo;").is_empty());
+    }
+}
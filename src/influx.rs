@@ -0,0 +1,130 @@
+use crate::aggregate::AggregateLogEntry;
+use crate::log_parser::LogEntry;
+use crate::normalize::fingerprint;
+use crate::summary::Summary;
+use crate::timeseries::{Point, TimeSeries};
+
+const MEASUREMENT: &str = "slow_query";
+
+/// Renders an individual entry as a line of InfluxDB line protocol,
+/// using the entry's timestamp in nanoseconds as the line's timestamp
+pub fn entry_line(entry: &LogEntry, fingerprint: &str) -> String {
+    format!(
+        "{}{} query_time_us={}i,lock_time_us={}i,rows_sent={}i,rows_examined={}i,query={} {}",
+        MEASUREMENT,
+        tags(&[("user", &entry.user), ("host", &entry.host), ("fingerprint", fingerprint)]),
+        entry.query_time.whole_microseconds(),
+        entry.lock_time.whole_microseconds(),
+        entry.rows_sent,
+        entry.rows_examined,
+        string_field(&entry.query),
+        entry.timestamp.unix_timestamp_nanos(),
+    )
+}
+
+/// Renders an aggregated record as a line of InfluxDB line protocol without a timestamp,
+/// so that the server assigns the time of insertion
+pub fn aggregate_line(entry: &AggregateLogEntry) -> String {
+    format!(
+        "{}{} count={}i,total_query_time_us={}i,avg_query_time_us={}i,max_query_time_us={}i,\
+        stddev_query_time_us={}i,p50_query_time_us={}i,p90_query_time_us={}i,\
+        p95_query_time_us={}i,p99_query_time_us={}i,query={}",
+        MEASUREMENT,
+        tags(&[("fingerprint", &fingerprint(&entry.query))]),
+        entry.count,
+        entry.total_query_time,
+        entry.avg_query_time,
+        entry.max_query_time,
+        entry.stddev_query_time,
+        entry.p50_query_time,
+        entry.p90_query_time,
+        entry.p95_query_time,
+        entry.p99_query_time,
+        string_field(&entry.query),
+    )
+}
+
+/// Renders one interval of a query's time series, using the start of the interval as timestamp
+pub fn time_series_line(series: &TimeSeries, point: &Point) -> String {
+    format!(
+        "{}_interval{} count={}i,total_query_time_us={}i {}",
+        MEASUREMENT,
+        tags(&[("fingerprint", &series.fingerprint)]),
+        point.count,
+        point.total_query_time,
+        point.start.unix_timestamp_nanos(),
+    )
+}
+
+pub fn summary_line(summary: &Summary) -> String {
+    format!(
+        "{}_summary total_queries={}i,unique_queries={}i,total_query_time_us={}i,\
+        avg_query_time_us={}i,max_query_time_us={}i,p50_query_time_us={}i,p90_query_time_us={}i,\
+        p95_query_time_us={}i,p99_query_time_us={}i,queries_per_second={}",
+        MEASUREMENT,
+        summary.total_queries,
+        summary.unique_queries,
+        summary.total_query_time,
+        summary.avg_query_time,
+        summary.max_query_time,
+        summary.p50_query_time,
+        summary.p90_query_time,
+        summary.p95_query_time,
+        summary.p99_query_time,
+        summary.queries_per_second,
+    )
+}
+
+/// Renders the tag set including the leading comma, omitting empty values,
+/// which the line protocol does not allow
+fn tags(tags: &[(&str, &str)]) -> String {
+    tags.iter()
+        .filter(|(_, value)| !value.is_empty())
+        .map(|(key, value)| format!(",{}={}", key, escape_tag_value(value)))
+        .collect()
+}
+
+/// Tag values need commas, equals signs and spaces escaped
+fn escape_tag_value(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, ',' | '=' | ' ') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// String field values are double-quoted, with double quotes and backslashes escaped
+fn string_field(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use time::macros::datetime;
+    use time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn it_renders_entries_as_line_protocol() {
+        let entry = LogEntry {
+            timestamp: datetime!(2019-07-30 13:01:34.887103 UTC),
+            user: "foo bar".to_string(),
+            host: "".to_string(),
+            query_time: Duration::microseconds(1_289_039),
+            lock_time: Duration::microseconds(61),
+            rows_sent: 1,
+            rows_examined: 100,
+            query: r#"SELECT * FROM baz WHERE name = "a,b=c" OR path = 'C:\tmp';"#.to_string(),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            r#"slow_query,user=foo\ bar,fingerprint=a\,b query_time_us=1289039i,lock_time_us=61i,rows_sent=1i,rows_examined=100i,query="SELECT * FROM baz WHERE name = \"a,b=c\" OR path = 'C:\\tmp';" 1564491694887103000"#,
+            entry_line(&entry, "a,b")
+        );
+    }
+}
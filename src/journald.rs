@@ -0,0 +1,125 @@
+use std::io::{self, Read};
+
+/// A source of journal messages, in the order in which they were logged
+pub trait JournalSource {
+    fn next_message(&mut self) -> io::Result<Option<String>>;
+}
+
+/// Presents the messages of a journal as a continuous log, one message per line. Journald stores
+/// every line that a service writes as a separate message without its line break, so the break
+/// is restored here; a message that still carries one does not get a second one.
+pub struct JournalReader<S: JournalSource> {
+    source: S,
+    pending: Vec<u8>,
+    position: usize,
+}
+
+impl<S: JournalSource> JournalReader<S> {
+    pub fn new(source: S) -> Self {
+        JournalReader { source, pending: Vec::new(), position: 0 }
+    }
+}
+
+impl<S: JournalSource> Read for JournalReader<S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.position == self.pending.len() {
+            let Some(message) = self.source.next_message()? else {
+                return Ok(0);
+            };
+            self.pending = message.trim_end_matches(['\r', '\n']).as_bytes().to_vec();
+            self.pending.push(b'\n');
+            self.position = 0;
+        }
+        let count = buf.len().min(self.pending.len() - self.position);
+        buf[..count].copy_from_slice(&self.pending[self.position..self.position + count]);
+        self.position += count;
+        Ok(count)
+    }
+}
+
+/// The messages that a systemd unit logged to the system journal, from the oldest one on
+#[cfg(feature = "journald")]
+pub struct SystemJournal {
+    journal: systemd::journal::Journal,
+}
+
+#[cfg(feature = "journald")]
+impl SystemJournal {
+    pub fn open(unit: &str) -> io::Result<Self> {
+        let mut journal = systemd::journal::OpenOptions::default().system(true).open()?;
+        journal.match_add("_SYSTEMD_UNIT", unit)?;
+        journal.seek_head()?;
+        Ok(SystemJournal { journal })
+    }
+}
+
+#[cfg(feature = "journald")]
+impl JournalSource for SystemJournal {
+    fn next_message(&mut self) -> io::Result<Option<String>> {
+        loop {
+            match self.journal.next_entry()? {
+                Some(mut record) => {
+                    if let Some(message) = record.remove("MESSAGE") {
+                        return Ok(Some(message));
+                    }
+                }
+                None => return Ok(None),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use indoc::indoc;
+
+    use super::*;
+    use crate::log_parser::{parse_log, ParseOptions};
+
+    struct MockJournal {
+        messages: std::vec::IntoIter<String>,
+    }
+
+    impl JournalSource for MockJournal {
+        fn next_message(&mut self) -> io::Result<Option<String>> {
+            Ok(self.messages.next())
+        }
+    }
+
+    const LOG: &str = indoc!(
+        "
+        # Time: 2021-05-11T07:00:00.000000Z
+        # User@Host: foo[foo] @  [127.0.0.1]  Id: 1
+        # Query_time: 1.000000  Lock_time: 0.000000 Rows_sent: 1  Rows_examined: 1
+        SELECT *
+        FROM foo WHERE id = 1;
+        # Time: 2021-05-11T07:00:01.000000Z
+        # User@Host: bar[bar] @  [127.0.0.1]  Id: 2
+        # Query_time: 2.000000  Lock_time: 0.000000 Rows_sent: 1  Rows_examined: 1
+        SELECT * FROM bar;
+    "
+    );
+
+    #[test]
+    fn it_reads_the_same_entries_as_from_a_file() {
+        // one message per line, some of them with their line break left in place
+        let messages = LOG
+            .lines()
+            .enumerate()
+            .map(|(i, line)| if i % 3 == 0 { format!("{}\n", line) } else { line.to_string() })
+            .collect::<Vec<String>>();
+        let journal = MockJournal { messages: messages.into_iter() };
+
+        let mut text = String::new();
+        JournalReader::new(journal).read_to_string(&mut text).unwrap();
+        assert_eq!(LOG, text);
+
+        let options = ParseOptions::default();
+        let from_file = parse_log(LOG.as_bytes(), &options).entries;
+        let messages = LOG.lines().map(String::from).collect::<Vec<String>>();
+        let journal = MockJournal { messages: messages.into_iter() };
+        let from_journal = parse_log(JournalReader::new(journal), &options).entries;
+        assert_eq!(2, from_journal.len());
+        assert_eq!(from_file, from_journal);
+    }
+}
@@ -0,0 +1,22 @@
+pub mod aggregate;
+pub mod benchmark;
+pub mod compare;
+pub mod config;
+pub mod decompress;
+pub mod filters;
+pub mod format;
+pub mod histogram;
+pub mod indexes;
+pub mod influx;
+pub mod journald;
+pub mod log_parser;
+pub mod logger;
+pub mod normalize;
+pub mod opt;
+pub mod postgres_log;
+pub mod prometheus;
+pub mod summary;
+pub mod tail;
+pub mod tdigest;
+pub mod timeseries;
+pub mod visit;
@@ -1,112 +1,618 @@
 use std::io::{BufRead, BufReader, Read};
 use std::iter::Peekable;
 
-use regex::{Match, Regex};
+use ahash::HashMap;
+use clap::ValueEnum;
+use regex::{Captures, Regex};
+use serde::{Deserialize, Serialize};
 use time::format_description::well_known::Iso8601;
 use time::{Duration, OffsetDateTime};
 
+use crate::postgres_log::parse_postgres_log;
+
 trait AdvanceWhile<I: Iterator> {
     fn advance_while<P>(&mut self, predicate: P) -> Option<I::Item>
     where
-        P: Fn(&I::Item) -> bool;
+        P: FnMut(&I::Item) -> bool;
 }
 
 impl<I: Iterator> AdvanceWhile<I> for Peekable<I> {
-    fn advance_while<P>(&mut self, predicate: P) -> Option<I::Item>
+    fn advance_while<P>(&mut self, mut predicate: P) -> Option<I::Item>
     where
-        P: Fn(&I::Item) -> bool,
+        P: FnMut(&I::Item) -> bool,
     {
         let mut result: Option<I::Item> = None;
-        while let Some(true) = self.peek().map(&predicate) {
+        while let Some(true) = self.peek().map(&mut predicate) {
             result = self.next();
         }
         result
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
 pub struct LogEntry {
+    #[serde(with = "time::serde::rfc3339")]
     pub timestamp: OffsetDateTime,
     pub user: String,
     pub host: String,
+    #[serde(rename = "query_time_us", with = "microseconds")]
     pub query_time: Duration,
+    #[serde(rename = "lock_time_us", with = "microseconds")]
     pub lock_time: Duration,
     pub rows_sent: i32,
     pub rows_examined: i32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rows_affected: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub thread_id: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub errno: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub killed: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tmp_table: Option<bool>,
+    /// The database selected by the last `use` statement of the entry's connection, if requested
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub database: Option<String>,
     pub query: String,
 }
 
-pub fn parse_log(log: impl Read) -> Vec<LogEntry> {
-    let reader = BufReader::new(log);
-    let mut lines = reader.lines().peekable();
-    let mut entries = Vec::new();
-
-    let time_regex = Regex::new(r"# Time: (\S+)").unwrap();
-    let user_regex = Regex::new(r"^# User@Host: ([\w-]+)\[[^]]+] @ (\w*) \[([\d.]*)]").unwrap();
-    let metric_regex = Regex::new(
-        r"^# Query_time: ([\d.]+)\s+Lock_time: ([\d.]+)\s+Rows_sent: (\d+)\s+Rows_examined: (\d+)",
-    )
-    .unwrap();
-    let whitespace_regex = Regex::new(r"\t|\s\s+").unwrap();
+/// (De)serializes durations as whole microseconds, which is the precision of the slow log
+mod microseconds {
+    use serde::{Deserialize, Deserializer, Serializer};
+    use time::Duration;
 
-    while let Some(l) = lines.next() {
-        let line = l.unwrap();
+    pub fn serialize<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_i64(duration.whole_microseconds() as i64)
+    }
 
-        if !line.starts_with("# Time") {
-            continue;
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        i64::deserialize(deserializer).map(Duration::microseconds)
+    }
+}
+
+impl Default for LogEntry {
+    fn default() -> Self {
+        LogEntry {
+            timestamp: OffsetDateTime::UNIX_EPOCH,
+            user: String::new(),
+            host: String::new(),
+            query_time: Duration::ZERO,
+            lock_time: Duration::ZERO,
+            rows_sent: 0,
+            rows_examined: 0,
+            rows_affected: None,
+            thread_id: None,
+            errno: None,
+            killed: None,
+            tmp_table: None,
+            database: None,
+            query: String::new(),
+        }
+    }
+}
+
+/// Optional metrics logged by MySQL 8.0 (with `log_slow_extra`) and some forks,
+/// either on the metric line itself or on additional header lines following it
+#[derive(Default)]
+struct ExtraMetrics {
+    rows_affected: Option<i32>,
+    thread_id: Option<u64>,
+    errno: Option<i32>,
+    killed: Option<i32>,
+    tmp_table: Option<bool>,
+}
+
+impl ExtraMetrics {
+    fn read_from(&mut self, line: &str, regex: &Regex) {
+        for caps in regex.captures_iter(line) {
+            let value = &caps[2];
+            match &caps[1] {
+                "Rows_affected" => self.rows_affected = value.parse().ok(),
+                "Thread_id" => self.thread_id = value.parse().ok(),
+                "Errno" | "Last_errno" => self.errno = value.parse().ok(),
+                "Killed" => self.killed = value.parse().ok(),
+                "Tmp_table" => self.tmp_table = Some(value == "Yes"),
+                _ => (),
+            }
         }
+    }
+}
 
-        let time_caps = time_regex.captures(&line);
-        if time_caps.is_none() {
-            panic!("Could not parse time from line:\n{line}");
+/// The metrics every entry has. They are read by key rather than by position, since some
+/// exports separate them differently or list them in a different order.
+#[derive(Default)]
+struct Metrics<'a> {
+    query_time: Option<&'a str>,
+    lock_time: Option<&'a str>,
+    rows_sent: Option<&'a str>,
+    rows_examined: Option<&'a str>,
+}
+
+impl<'a> Metrics<'a> {
+    fn read_from(line: &'a str, regex: &Regex) -> Self {
+        let mut metrics = Metrics::default();
+        for caps in regex.captures_iter(line) {
+            let value = caps.get(2).map(|m| m.as_str());
+            match &caps[1] {
+                "Query_time" => metrics.query_time = value,
+                "Lock_time" => metrics.lock_time = value,
+                "Rows_sent" => metrics.rows_sent = value,
+                "Rows_examined" => metrics.rows_examined = value,
+                _ => (),
+            }
         }
+        metrics
+    }
+}
 
-        let time_cap = time_caps.unwrap().get(1);
-        let timestamp = match time_cap {
-            Some(cap) => OffsetDateTime::parse(cap.as_str(), &Iso8601::DEFAULT).unwrap(),
-            _ => break,
-        };
+/// Determines where a query that may span multiple lines ends
+enum Terminator {
+    Semicolon,
+    /// The body of a stored program, which may contain semicolons inside BEGIN...END blocks
+    Compound(Blocks),
+    /// A custom delimiter set by a `DELIMITER` command
+    Delimiter(String),
+}
 
-        let line = match lines.next() {
-            Some(l) => l.unwrap(),
-            _ => break,
+impl Terminator {
+    /// Tells whether the query ends with the given line, which must be the latest one read
+    fn ends_with(&mut self, line: &str, context: Context) -> bool {
+        if let Terminator::Compound(blocks) = self {
+            *blocks = blocks.after(line);
+        }
+        if context != Context::Code {
+            return false;
+        }
+        match self {
+            Terminator::Semicolon => line.ends_with(';'),
+            Terminator::Compound(blocks) => line.ends_with(';') && blocks.depth() <= 0,
+            Terminator::Delimiter(delimiter) => line.ends_with(delimiter.as_str()),
+        }
+    }
+}
+
+/// What a query that is read line by line is inside of at the end of the last line
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+enum Context {
+    #[default]
+    Code,
+    /// A string literal or quoted identifier, with its quote character
+    Quoted(char),
+    /// A `/* ... */` comment
+    Comment,
+}
+
+impl Context {
+    /// Scans the next line of a query. Only the new line is scanned, so that reading a long
+    /// query does not take quadratic time. Quotes inside comments are ignored, and `--` and `#`
+    /// comments end with the line.
+    fn after(mut self, line: &str) -> Context {
+        let mut chars = line.chars().peekable();
+        while let Some(c) = chars.next() {
+            self = match self {
+                Context::Code => match c {
+                    '\'' | '"' | '`' => Context::Quoted(c),
+                    '#' => break,
+                    // MySQL only starts a comment at `--` followed by whitespace
+                    '-' if chars.next_if_eq(&'-').is_some() => {
+                        if chars.peek().is_none_or(|next| next.is_whitespace()) {
+                            break;
+                        }
+                        Context::Code
+                    }
+                    '/' if chars.next_if_eq(&'*').is_some() => Context::Comment,
+                    _ => Context::Code,
+                },
+                Context::Quoted(quote) if c == '\\' && quote != '`' => {
+                    chars.next();
+                    self
+                }
+                Context::Quoted(quote) if c == quote => Context::Code,
+                Context::Comment if c == '*' && chars.next_if_eq(&'/').is_some() => Context::Code,
+                _ => self,
+            };
+        }
+        self
+    }
+}
+
+/// Counts the BEGIN...END and CASE...END blocks that are still open at the end of the lines
+/// scanned so far. Flow control blocks like IF...END IF are balanced on their own, so they are
+/// ignored.
+#[derive(Copy, Clone, Debug, Default)]
+struct Blocks {
+    depth: i32,
+    /// The last word was END, which closes a block unless it is followed by IF, LOOP, etc.
+    after_end: bool,
+}
+
+impl Blocks {
+    fn after(mut self, line: &str) -> Blocks {
+        let words = line.split(|c: char| !(c.is_alphanumeric() || c == '_'));
+        for word in words.filter(|word| !word.is_empty()).map(|word| word.to_ascii_uppercase()) {
+            if std::mem::take(&mut self.after_end) {
+                match word.as_str() {
+                    "IF" | "LOOP" | "WHILE" | "REPEAT" => continue,
+                    "CASE" => {
+                        self.depth -= 1;
+                        continue;
+                    }
+                    _ => self.depth -= 1,
+                }
+            }
+            match word.as_str() {
+                "BEGIN" | "CASE" => self.depth += 1,
+                "END" => self.after_end = true,
+                _ => (),
+            }
+        }
+        self
+    }
+
+    fn depth(&self) -> i32 {
+        self.depth - i32::from(self.after_end)
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, ValueEnum)]
+pub enum InputFormat {
+    /// MySQL slow query log
+    #[default]
+    Mysql,
+    /// One JSON-serialized entry per line, as written by `--format ndjson`
+    Ndjson,
+    /// PostgreSQL log with statements logged by `log_min_duration_statement`
+    Postgres,
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, ValueEnum)]
+pub enum OversizedQueries {
+    /// Drop the entry entirely
+    #[default]
+    Skip,
+    /// Keep the entry, but cut its query off and append a marker
+    Truncate,
+}
+
+/// Appended to queries that were cut off at the size limit
+pub const TRUNCATION_MARKER: &str = " /* truncated */";
+
+#[derive(Clone, Debug, Default)]
+pub struct ParseOptions {
+    pub input_format: InputFormat,
+    /// Count malformed entries without recording a warning for each of them
+    pub quiet_errors: bool,
+    /// Only skip `SET timestamp` instead of all SET statements preceding a query
+    pub keep_set_statements: bool,
+    /// Stop at the first malformed entry instead of skipping it
+    pub strict: bool,
+    /// Attach the database from a preceding `use` statement to the entry and its query
+    pub keep_use: bool,
+    /// Maximum length of a query in bytes, to guard against pathological entries
+    pub max_query_bytes: Option<usize>,
+    /// What to do with entries whose query exceeds `max_query_bytes`
+    pub oversized_queries: OversizedQueries,
+    /// Read `Query_time` and `Lock_time` as whole microseconds instead of fractional seconds
+    pub time_in_micros: bool,
+}
+
+#[derive(Debug, Default)]
+pub struct ParsedLog {
+    pub entries: Vec<LogEntry>,
+    pub warnings: Vec<String>,
+    pub malformed_entries: usize,
+    /// Input files that were skipped because they could not be read
+    pub unreadable_files: usize,
+    /// Entries whose query exceeded the size limit and was skipped or truncated
+    pub oversized_entries: usize,
+    /// Entries that were skipped because their query was blank, e.g. after a botched rotation
+    pub empty_entries: usize,
+}
+
+impl ParsedLog {
+    pub fn append(&mut self, mut other: ParsedLog) {
+        self.entries.append(&mut other.entries);
+        self.warnings.append(&mut other.warnings);
+        self.malformed_entries += other.malformed_entries;
+        self.unreadable_files += other.unreadable_files;
+        self.oversized_entries += other.oversized_entries;
+        self.empty_entries += other.empty_entries;
+    }
+
+    pub(crate) fn push_entry(&mut self, options: &ParseOptions, mut entry: LogEntry) {
+        let oversized = options.max_query_bytes.is_some_and(|max| entry.query.len() > max);
+        if !oversized || self.limit_oversized(options, &mut entry.query) {
+            self.entries.push(entry);
+        }
+    }
+
+    /// Counts an entry whose query exceeds the size limit and truncates the query if requested.
+    /// Returns whether the entry is to be kept.
+    fn limit_oversized(&mut self, options: &ParseOptions, query: &mut String) -> bool {
+        self.oversized_entries += 1;
+        match options.oversized_queries {
+            OversizedQueries::Skip => false,
+            OversizedQueries::Truncate => {
+                let mut end = options.max_query_bytes.unwrap_or(usize::MAX).min(query.len());
+                while !query.is_char_boundary(end) {
+                    end -= 1;
+                }
+                query.truncate(end);
+                query.push_str(TRUNCATION_MARKER);
+                true
+            }
+        }
+    }
+
+    pub(crate) fn skip_entry(&mut self, options: &ParseOptions, warning: String) {
+        self.malformed_entries += 1;
+        if !options.quiet_errors {
+            self.warnings.push(warning);
+        }
+    }
+}
+
+pub fn parse_input(log: impl Read, options: &ParseOptions) -> ParsedLog {
+    match options.input_format {
+        InputFormat::Mysql => parse_log(log, options),
+        InputFormat::Ndjson => parse_ndjson(log, options),
+        InputFormat::Postgres => parse_postgres_log(log, options),
+    }
+}
+
+pub fn parse_ndjson(log: impl Read, options: &ParseOptions) -> ParsedLog {
+    let reader = BufReader::new(log);
+    let mut result = ParsedLog::default();
+
+    for (i, l) in reader.lines().enumerate() {
+        let line = match l {
+            Ok(line) => clean_line(i, line),
+            Err(err) => {
+                result.skip_entry(options, format!("Unreadable line {}: {}", i + 1, err));
+                continue;
+            }
         };
-        let user_caps = user_regex.captures(&line);
-        if user_caps.is_none() {
-            panic!("Could not parse user info from line:\n{line}");
+        if line.trim().is_empty() {
+            continue;
         }
-        let user_caps = user_caps.unwrap();
-        let user = user_caps.get(1).unwrap().as_str().to_string();
-        let mut host = user_caps.get(2).unwrap().as_str().to_string();
-        if host.is_empty() {
-            host = user_caps.get(3).unwrap().as_str().to_string();
+        match serde_json::from_str::<LogEntry>(&line) {
+            Ok(entry) => result.push_entry(options, entry),
+            Err(err) => {
+                result.skip_entry(options, format!("Invalid entry in line {}: {}", i + 1, err))
+            }
+        }
+    }
+
+    result
+}
+
+/// Placeholder for a user or host that the User@Host line does not mention
+pub const UNKNOWN: &str = "<unknown>";
+
+/// Returns the first of the given capture groups that matched a nonempty string
+fn first_nonempty<const N: usize>(caps: &Captures, groups: [usize; N]) -> String {
+    groups
+        .into_iter()
+        .filter_map(|group| caps.get(group))
+        .map(|cap| cap.as_str())
+        .find(|value| !value.is_empty())
+        .unwrap_or(UNKNOWN)
+        .to_string()
+}
+
+pub fn parse_log(log: impl Read, options: &ParseOptions) -> ParsedLog {
+    let reader = BufReader::new(log);
+    // Lines that cannot be read, e.g. due to invalid UTF-8, are turned into a warning right away
+    let mut lines = reader
+        .lines()
+        .enumerate()
+        .map(|(i, l)| match l {
+            Ok(line) => Ok(clean_line(i, line)),
+            Err(err) => Err(format!("Unreadable line {}: {}", i + 1, err)),
+        })
+        .peekable();
+    let mut result = ParsedLog::default();
+
+    // Aurora may append a comment to the timestamp, e.g. `# Time: 2021-05-11T07:00:13.212839Z  # ...`
+    let time_regex = Regex::new(r"^# Time:\s*(\S+)(\s+#.*)?\s*$").unwrap();
+    // user[login] @ host [ip], where any of the parts may be empty or missing
+    let user_regex =
+        Regex::new(r"^# User@Host: ?([^\s\[@]*)(?:\[([^]]*)])?\s*@\s*([^\s\[]*)\s*(?:\[([^]]*)])?")
+            .unwrap();
+    let delimiter_regex = Regex::new(r"(?i)^DELIMITER\s+(\S+)").unwrap();
+    let compound_regex =
+        Regex::new(r"(?i)^\s*CREATE\s+(DEFINER\s*=\s*\S+\s+)?(PROCEDURE|FUNCTION|TRIGGER|EVENT)\b")
+            .unwrap();
+    let id_regex = Regex::new(r"\bId:\s*(\d+)").unwrap();
+    let metric_regex = Regex::new(r"(\w+):\s+(\S+)").unwrap();
+    let whitespace_regex = Regex::new(r"\t|\s\s+").unwrap();
+
+    let mut last_timestamp = None;
+    // MySQL only logs `use` when a connection switches databases, so remember the last one per
+    // connection id. Entries without an id share a connection.
+    let mut databases: HashMap<Option<u64>, String> = HashMap::default();
+
+    'entries: while let Some(l) = lines.next() {
+        if options.strict && result.malformed_entries > 0 {
+            break;
         }
 
+        let line = match l {
+            Ok(line) => line,
+            Err(warning) => {
+                result.skip_entry(options, warning);
+                continue;
+            }
+        };
+
+        // Some configurations only log the time once for several consecutive entries,
+        // so an entry may also start with the user line and reuse the previous timestamp.
+        let (timestamp, line) = if line.starts_with("# Time") {
+            // don't attribute subsequent entries to an earlier time if this one is broken
+            last_timestamp = None;
+
+            let time_caps = match time_regex.captures(&line) {
+                Some(caps) => caps,
+                None => {
+                    result.skip_entry(options, format!("Could not parse time from line: {line}"));
+                    continue;
+                }
+            };
+
+            let timestamp = match OffsetDateTime::parse(&time_caps[1], &Iso8601::DEFAULT) {
+                Ok(timestamp) => timestamp,
+                Err(_) => {
+                    result.skip_entry(options, format!("Invalid timestamp in line: {line}"));
+                    continue;
+                }
+            };
+            last_timestamp = Some(timestamp);
+
+            match lines.next() {
+                Some(Ok(line)) => (timestamp, line),
+                Some(Err(warning)) => {
+                    result.skip_entry(options, warning);
+                    continue;
+                }
+                None => break,
+            }
+        } else if line.starts_with("# User@Host") {
+            match last_timestamp {
+                Some(timestamp) => (timestamp, line),
+                None => continue,
+            }
+        } else {
+            continue;
+        };
+
+        let user_caps = match user_regex.captures(&line) {
+            Some(caps) => caps,
+            None => {
+                result.skip_entry(options, format!("Could not parse user info from line: {line}"));
+                continue;
+            }
+        };
+        let user = first_nonempty(&user_caps, [1, 2]);
+        let host = first_nonempty(&user_caps, [3, 4]);
+        let connection_id = id_regex.captures(&line).and_then(|caps| caps[1].parse().ok());
+
         let line = match lines.next() {
-            Some(l) => l.unwrap(),
-            _ => break,
+            Some(Ok(line)) => line,
+            Some(Err(warning)) => {
+                result.skip_entry(options, warning);
+                continue;
+            }
+            None => break,
+        };
+        let metrics = Metrics::read_from(&line, &metric_regex);
+        let to_duration =
+            if options.time_in_micros { micros_to_duration } else { seconds_to_duration };
+        let times =
+            (metrics.query_time.and_then(to_duration), metrics.lock_time.and_then(to_duration));
+        let (Some(query_time), Some(lock_time), Some(rows_sent), Some(rows_examined)) =
+            (times.0, times.1, metrics.rows_sent, metrics.rows_examined)
+        else {
+            result.skip_entry(options, format!("Could not parse metrics from line: {line}"));
+            continue;
+        };
+        let (rows_sent, rows_examined) = match (rows_sent.parse(), rows_examined.parse()) {
+            (Ok(sent), Ok(examined)) => (sent, examined),
+            _ => {
+                result.skip_entry(options, format!("Invalid row count in line: {line}"));
+                continue;
+            }
         };
-        let metric_caps = metric_regex.captures(&line).expect("Metric matching failed");
-        let query_time = microseconds_to_duration(metric_caps.get(1).unwrap());
-        let lock_time = microseconds_to_duration(metric_caps.get(2).unwrap());
-        let rows_sent = metric_caps.get(3).unwrap().as_str().parse::<i32>().unwrap();
-        let rows_examined = metric_caps.get(4).unwrap().as_str().parse::<i32>().unwrap();
-
-        let _ = lines.by_ref().advance_while(|next| {
-            let q = next.as_ref().unwrap();
-            q.starts_with("SET timestamp") || q.starts_with("use")
+
+        let mut extra_metrics = ExtraMetrics::default();
+        extra_metrics.read_from(&line, &metric_regex);
+        while let Some(Ok(next)) = lines.peek() {
+            if !next.starts_with("# ")
+                || next.starts_with("# Time")
+                || next.starts_with("# User@Host")
+            {
+                break;
+            }
+            extra_metrics.read_from(next, &metric_regex);
+            lines.next();
+        }
+
+        let connection_id = connection_id.or(extra_metrics.thread_id);
+        let skipped = lines.by_ref().advance_while(|next| {
+            let Ok(q) = next else {
+                return false;
+            };
+            if q.starts_with("use") {
+                if let Some(database) = use_statement_database(q) {
+                    databases.insert(connection_id, database);
+                }
+                return true;
+            }
+            q.starts_with("SET timestamp") || (!options.keep_set_statements && is_set_statement(q))
         });
+        let database = if options.keep_use { databases.get(&connection_id).cloned() } else { None };
 
-        let mut query = match lines.next() {
-            Some(q) => q.unwrap(),
-            _ => break,
+        // If the entry ends right after a skipped SET statement, that was the actual query.
+        let entry_ended = !matches!(lines.peek(), Some(Ok(next)) if !next.starts_with("# "));
+        let mut query = match skipped {
+            Some(Ok(statement))
+                if entry_ended
+                    && is_set_statement(&statement)
+                    && !statement.starts_with("SET timestamp") =>
+            {
+                statement
+            }
+            _ => match lines.next() {
+                Some(Ok(q)) => q,
+                Some(Err(warning)) => {
+                    result.skip_entry(options, warning);
+                    continue;
+                }
+                None => break,
+            },
+        };
+
+        let delimiter = delimiter_regex.captures(&query).map(|caps| caps[1].to_string());
+        let mut terminator = match delimiter {
+            Some(delimiter) => {
+                query = match lines.next() {
+                    Some(Ok(q)) => q,
+                    Some(Err(warning)) => {
+                        result.skip_entry(options, warning);
+                        continue;
+                    }
+                    None => break,
+                };
+                Terminator::Delimiter(delimiter)
+            }
+            None if compound_regex.is_match(&query) => Terminator::Compound(Blocks::default()),
+            None => Terminator::Semicolon,
         };
 
-        while !query.ends_with(';') {
-            let next_line = match lines.next() {
-                Some(l) => l.unwrap(),
-                _ => break,
+        let max_bytes = options.max_query_bytes.unwrap_or(usize::MAX);
+        let mut oversized = query.len() > max_bytes;
+        let mut context = Context::Code.after(&query);
+        let mut complete = terminator.ends_with(&query, context);
+        while !complete {
+            // An unterminated query must not swallow the header of the next entry,
+            // so leave that line for the outer loop to start the next entry with.
+            let next_line = match lines.next_if(|next| {
+                !matches!(next, Ok(l) if l.starts_with("# Time") || l.starts_with("# User@Host"))
+            }) {
+                Some(Ok(l)) => l,
+                Some(Err(warning)) => {
+                    result.skip_entry(options, warning);
+                    continue 'entries;
+                }
+                None => break,
             };
+            context = context.after(&next_line);
+            complete = terminator.ends_with(&next_line, context);
+            // Past the size limit, the rest of the query is only read to find where it ends,
+            // so that a pathological query is never held in memory as a whole.
+            if oversized {
+                continue;
+            }
 
             // In general, if a query stretches across multiple log lines, we insert a space
             // to avoid accidentally breaking the syntax. However, in pathological cases
@@ -122,10 +628,25 @@ pub fn parse_log(log: impl Read) -> Vec<LogEntry> {
                 " "
             };
 
-            query = format!("{}{}{}", query, padding, next_line);
+            query.push_str(padding);
+            query.push_str(&next_line);
+            oversized = query.len() > max_bytes;
+        }
+
+        if let Terminator::Delimiter(delimiter) = &terminator {
+            query = format!("{};", query.trim_end_matches(delimiter.as_str()).trim_end());
+        }
+
+        if query.trim().is_empty() {
+            result.empty_entries += 1;
+            continue;
+        }
+        let mut query = whitespace_regex.replace_all(&query, " ").to_string();
+        if let Some(database) = &database {
+            query = format!("USE {}; {}", database, query);
         }
 
-        entries.push(LogEntry {
+        let mut entry = LogEntry {
             timestamp,
             user,
             host,
@@ -133,23 +654,70 @@ pub fn parse_log(log: impl Read) -> Vec<LogEntry> {
             lock_time,
             rows_sent,
             rows_examined,
-            query: whitespace_regex.replace_all(&query, " ").to_string(),
-        });
+            rows_affected: extra_metrics.rows_affected,
+            thread_id: extra_metrics.thread_id,
+            errno: extra_metrics.errno,
+            killed: extra_metrics.killed,
+            tmp_table: extra_metrics.tmp_table,
+            database,
+            query,
+        };
+        if !oversized {
+            result.push_entry(options, entry);
+        } else if result.limit_oversized(options, &mut entry.query) {
+            result.entries.push(entry);
+        }
+    }
+
+    result
+}
+
+/// Removes a byte order mark from the first line and a carriage return left over from CRLF
+pub(crate) fn clean_line(index: usize, mut line: String) -> String {
+    if index == 0 && line.starts_with('\u{feff}') {
+        line.remove(0);
     }
+    if line.ends_with('\r') {
+        line.pop();
+    }
+    line
+}
+
+/// Extracts the database name from a `use <db>;` line
+fn use_statement_database(line: &str) -> Option<String> {
+    let name = line.get(3..)?.trim().trim_end_matches(';').trim().trim_matches('`');
+    (!name.is_empty()).then(|| name.to_string())
+}
+
+fn is_set_statement(line: &str) -> bool {
+    line.get(..4).is_some_and(|prefix| prefix.eq_ignore_ascii_case("SET "))
+}
 
-    entries
+fn seconds_to_duration(value: &str) -> Option<Duration> {
+    if !value.bytes().all(|b| b.is_ascii_digit() || b == b'.') {
+        return None;
+    }
+    // Rounding rather than truncating keeps values like 4.1 s, which are slightly below
+    // 4100000 µs in binary floating point, from losing a microsecond.
+    let usec = value.parse::<f64>().ok()? * 1_000_000.0;
+    Some(Duration::microseconds(usec.round() as i64))
 }
 
-fn microseconds_to_duration(cap: Match) -> Duration {
-    let usec = cap.as_str().parse::<f64>().unwrap() * 1_000_000.0;
-    Duration::microseconds(usec as i64)
+/// Some tools rewrite the slow log with times as integer microseconds, e.g. `Query_time: 1289039`
+fn micros_to_duration(value: &str) -> Option<Duration> {
+    if !value.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    value.parse().ok().map(Duration::microseconds)
 }
 
 #[cfg(test)]
 mod tests {
     use indoc::indoc;
+    use time::macros::datetime;
 
     use super::*;
+    use crate::format::write_json_line;
 
     #[test]
     fn it_parses_logs_correctly() {
@@ -168,7 +736,7 @@ mod tests {
             UPDATE baz SET quux = 2 WHERE id = 42;
         "
         );
-        let entries = parse_log(log as &[u8]);
+        let entries = parse_log(log as &[u8], &ParseOptions::default()).entries;
         assert_eq!(2, entries.len());
 
         let e1 = entries.first().expect("we know this exists");
@@ -184,6 +752,229 @@ mod tests {
         assert_eq!("UPDATE baz SET quux = 2 WHERE id = 42;", e2.query);
     }
 
+    #[test]
+    fn it_parses_metric_fields_in_any_order() {
+        let log = indoc!(
+            b"
+            # Time: 2019-07-30T13:01:34.887103Z
+            # User@Host: foo[bar] @  [127.0.0.1]  Id: 1337
+            # Query_time: 1.289039\tRows_examined: 100000 Lock_time: 0.000061\tRows_sent: 50000
+            SELECT * FROM baz WHERE quux = 1;
+            # Time: 2019-07-30T13:01:34.887103Z
+            # User@Host: foo[bar] @  [127.0.0.1]  Id: 1337
+            # Query_time: 1.289039  Lock_time: 0.000061 Rows_examined: 100000
+            SELECT * FROM baz WHERE quux = 2;
+        "
+        );
+        let parsed = parse_log(log as &[u8], &ParseOptions::default());
+        assert_eq!(1, parsed.entries.len());
+        assert_eq!(1, parsed.malformed_entries);
+
+        let entry = &parsed.entries[0];
+        assert_eq!(1289039, entry.query_time.whole_microseconds());
+        assert_eq!(61, entry.lock_time.whole_microseconds());
+        assert_eq!(50000, entry.rows_sent);
+        assert_eq!(100000, entry.rows_examined);
+    }
+
+    #[test]
+    fn it_skips_unreadable_lines() {
+        let header = "# Time: 2019-07-30T13:01:34.887103Z\n\
+            # User@Host: foo[bar] @  [127.0.0.1]  Id: 1337\n\
+            # Query_time: 1.289039  Lock_time: 0.000061 Rows_sent: 1  Rows_examined: 1\n";
+        let mut log = format!("{header}SELECT 1;\n{header}SELECT 'a\n").into_bytes();
+        log.extend_from_slice(b"\xff';\n");
+        log.extend_from_slice(format!("{header}SELECT 3;\n").as_bytes());
+
+        let parsed = parse_log(log.as_slice(), &ParseOptions::default());
+        assert_eq!(1, parsed.malformed_entries);
+        assert_eq!(
+            vec!["SELECT 1;", "SELECT 3;"],
+            parsed.entries.iter().map(|e| e.query.as_str()).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn it_reads_times_in_either_unit() {
+        let log = |query_time: &str, lock_time: &str| {
+            format!(
+                "# Time: 2019-07-30T13:01:34.887103Z\n\
+                 # User@Host: foo[bar] @  [127.0.0.1]  Id: 1337\n\
+                 # Query_time: {}  Lock_time: {} Rows_sent: 1  Rows_examined: 1\n\
+                 SELECT 1;\n",
+                query_time, lock_time
+            )
+        };
+        let in_seconds =
+            parse_log(log("1.289039", "0.000061").as_bytes(), &ParseOptions::default());
+        let options = ParseOptions { time_in_micros: true, ..Default::default() };
+        let in_micros = parse_log(log("1289039", "61").as_bytes(), &options);
+
+        assert_eq!(1, in_micros.entries.len());
+        assert_eq!(Duration::microseconds(1_289_039), in_micros.entries[0].query_time);
+        assert_eq!(in_seconds.entries[0].query_time, in_micros.entries[0].query_time);
+        assert_eq!(in_seconds.entries[0].lock_time, in_micros.entries[0].lock_time);
+
+        // fractional values are not microseconds, so they are rejected rather than misread
+        let misread = parse_log(log("1.289039", "0.000061").as_bytes(), &options);
+        assert_eq!(1, misread.malformed_entries);
+    }
+
+    #[test]
+    fn it_rounds_times_to_the_nearest_microsecond() {
+        let usec = |value: &str| seconds_to_duration(value).unwrap().whole_microseconds();
+        assert_eq!(4_100_000, usec("4.100000"));
+        assert_eq!(1_290_000, usec("1.2899995"));
+        assert_eq!(1_289_999, usec("1.2899994"));
+        assert_eq!(None, seconds_to_duration("-1.0"));
+    }
+
+    #[test]
+    fn it_parses_extra_metric_fields() {
+        let log = indoc!(b"
+            # Time: 2019-07-30T13:01:34.887103Z
+            # User@Host: foo[bar] @  [127.0.0.1]  Id: 1337
+            # Query_time: 50.000123  Lock_time: 50.000061 Rows_sent: 0  Rows_examined: 1 Thread_id: 1337 Errno: 1205 Killed: 0 Rows_affected: 0
+            UPDATE baz SET quux = 2 WHERE id = 42;
+            # Time: 2019-07-30T13:01:34.887103Z
+            # User@Host: foo[bar] @  [127.0.0.1]  Id: 1337
+            # Query_time: 1.289039  Lock_time: 0.000061 Rows_sent: 0  Rows_examined: 100000
+            # Bytes_sent: 0  Rows_affected: 12
+            # Last_errno: 0  Killed: 1
+            # QC_Hit: No  Full_scan: Yes  Full_join: No  Tmp_table: Yes  Tmp_table_on_disk: No
+            DELETE FROM baz WHERE quux = 1;
+            # Time: 2019-07-30T13:01:34.887103Z
+            # User@Host: foo[bar] @  [127.0.0.1]  Id: 1337
+            # Query_time: 0.123456  Lock_time: 0.000009 Rows_sent: 1  Rows_examined: 1
+            SELECT * FROM baz WHERE quux = 1;
+        ");
+
+        let entries = parse_log(log as &[u8], &ParseOptions::default()).entries;
+        assert_eq!(3, entries.len());
+
+        let e1 = &entries[0];
+        assert_eq!(Some(1337), e1.thread_id);
+        assert_eq!(Some(1205), e1.errno);
+        assert_eq!(Some(0), e1.killed);
+        assert_eq!(Some(0), e1.rows_affected);
+
+        let e2 = &entries[1];
+        assert_eq!(None, e2.thread_id);
+        assert_eq!(Some(0), e2.errno);
+        assert_eq!(Some(1), e2.killed);
+        assert_eq!(Some(12), e2.rows_affected);
+        assert_eq!(Some(true), e2.tmp_table);
+        assert_eq!("DELETE FROM baz WHERE quux = 1;", e2.query);
+
+        let e3 = &entries[2];
+        assert_eq!(None, e3.errno);
+        assert_eq!(None, e3.killed);
+        assert_eq!(None, e3.rows_affected);
+        assert_eq!(None, e3.tmp_table);
+    }
+
+    #[test]
+    fn it_handles_stored_procedure_bodies() {
+        let log = indoc!(
+            b"
+            # Time: 2019-07-30T13:01:34.887103Z
+            # User@Host: foo[bar] @  [127.0.0.1]  Id: 1337
+            # Query_time: 1.289039  Lock_time: 0.000061 Rows_sent: 0  Rows_examined: 0
+            CREATE PROCEDURE foo()
+            BEGIN
+              DECLARE x INT;
+              SELECT COUNT(*) INTO x FROM bar;
+              IF x > 0 THEN
+                UPDATE bar SET baz = CASE WHEN baz > 1 THEN 1 ELSE 0 END;
+              END IF;
+            END;
+            # Time: 2019-07-30T13:01:34.887103Z
+            # User@Host: foo[bar] @  [127.0.0.1]  Id: 1337
+            # Query_time: 0.289039  Lock_time: 0.000061 Rows_sent: 0  Rows_examined: 0
+            DELIMITER //
+            CREATE TRIGGER foo BEFORE INSERT ON bar FOR EACH ROW BEGIN
+              SET NEW.baz = 1;
+            END//
+            DELIMITER ;
+        "
+        );
+
+        let entries = parse_log(log as &[u8], &ParseOptions::default()).entries;
+        assert_eq!(2, entries.len());
+        assert_eq!(
+            "CREATE PROCEDURE foo() BEGIN DECLARE x INT; SELECT COUNT(*) INTO x FROM bar; \
+             IF x > 0 THEN UPDATE bar SET baz = CASE WHEN baz > 1 THEN 1 ELSE 0 END; END IF; END;",
+            entries[0].query
+        );
+        assert_eq!(
+            "CREATE TRIGGER foo BEFORE INSERT ON bar FOR EACH ROW BEGIN SET NEW.baz = 1; END;",
+            entries[1].query
+        );
+    }
+
+    #[test]
+    fn it_strips_a_byte_order_mark() {
+        let log = "\u{feff}# Time: 2019-07-30T13:01:34.887103Z
+# User@Host: foo[bar] @  [127.0.0.1]  Id: 1337
+# Query_time: 1.289039  Lock_time: 0.000061 Rows_sent: 50000  Rows_examined: 100000
+SELECT * FROM baz;
+";
+
+        let entries = parse_log(log.as_bytes(), &ParseOptions::default()).entries;
+        assert_eq!(1, entries.len());
+        assert_eq!("foo", entries[0].user);
+        assert_eq!("SELECT * FROM baz;", entries[0].query);
+    }
+
+    #[test]
+    fn it_handles_crlf_line_endings() {
+        let log = "# Time: 2019-07-30T13:01:34.887103Z\r\n\
+            # User@Host: foo[bar] @  [127.0.0.1]  Id: 1337\r\n\
+            # Query_time: 1.289039  Lock_time: 0.000061 Rows_sent: 50000  Rows_examined: 100000\r\n\
+            SET timestamp=1564491694;\r\n\
+            SELECT *\r\n\
+            FROM baz;\r\n";
+
+        let entries = parse_log(log.as_bytes(), &ParseOptions::default()).entries;
+        assert_eq!(1, entries.len());
+        assert_eq!("foo", entries[0].user);
+        assert_eq!("127.0.0.1", entries[0].host);
+        assert_eq!(100000, entries[0].rows_examined);
+        assert_eq!("SELECT * FROM baz;", entries[0].query);
+    }
+
+    #[test]
+    fn it_skips_set_statements_preceding_the_query() {
+        let log = indoc!(
+            b"
+            # Time: 2019-07-30T13:01:34.887103Z
+            # User@Host: foo[bar] @  [127.0.0.1]  Id: 1337
+            # Query_time: 1.289039  Lock_time: 0.000061 Rows_sent: 50000  Rows_examined: 100000
+            use foo;
+            SET timestamp=1000000000;
+            SET sql_mode='STRICT_TRANS_TABLES';
+            set names utf8mb4;
+            SELECT * FROM baz WHERE quux = 1;
+            # Time: 2019-07-30T13:01:34.887103Z
+            # User@Host: foo[bar] @  [127.0.0.1]  Id: 1337
+            # Query_time: 1.289039  Lock_time: 0.000061 Rows_sent: 0  Rows_examined: 0
+            SET timestamp=1000000000;
+            SET GLOBAL innodb_buffer_pool_size = 1073741824;
+        "
+        );
+
+        let entries = parse_log(log as &[u8], &ParseOptions::default()).entries;
+        assert_eq!(2, entries.len());
+        assert_eq!("SELECT * FROM baz WHERE quux = 1;", entries[0].query);
+        assert_eq!("SET GLOBAL innodb_buffer_pool_size = 1073741824;", entries[1].query);
+
+        let options = ParseOptions { keep_set_statements: true, ..Default::default() };
+        let entries = parse_log(log as &[u8], &options).entries;
+        assert_eq!(2, entries.len());
+        assert_eq!("SET sql_mode='STRICT_TRANS_TABLES';", entries[0].query);
+        assert_eq!("SET GLOBAL innodb_buffer_pool_size = 1073741824;", entries[1].query);
+    }
+
     #[test]
     fn it_skips_additional_lines_at_the_start() {
         // something we might see in AWS RDS
@@ -197,7 +988,7 @@ mod tests {
             SELECT * FROM baz WHERE quux = 1;
         ");
 
-        let entries = parse_log(log as &[u8]);
+        let entries = parse_log(log as &[u8], &ParseOptions::default()).entries;
         assert_eq!(1, entries.len());
 
         let e1 = entries.first().expect("we know this exists");
@@ -207,7 +998,7 @@ mod tests {
     #[test]
     fn it_handles_empty_logs() {
         let log = b"";
-        let entries = parse_log(log as &[u8]);
+        let entries = parse_log(log as &[u8], &ParseOptions::default()).entries;
         assert_eq!(0, entries.len());
     }
 
@@ -219,7 +1010,7 @@ mod tests {
             Time                 Id Command    Argument
         ");
 
-        let entries = parse_log(log as &[u8]);
+        let entries = parse_log(log as &[u8], &ParseOptions::default()).entries;
         assert_eq!(0, entries.len());
     }
 
@@ -256,7 +1047,7 @@ mod tests {
             Time                 Id Command    Argument
         ");
 
-        let entries = parse_log(log as &[u8]);
+        let entries = parse_log(log as &[u8], &ParseOptions::default()).entries;
         assert_eq!(2, entries.len());
 
         entries.iter().for_each(|entry| {
@@ -278,10 +1069,444 @@ mod tests {
                 WHERE quux = 1;
         ");
 
-        let entries = parse_log(log as &[u8]);
+        let entries = parse_log(log as &[u8], &ParseOptions::default()).entries;
         assert_eq!(1, entries.len());
 
         let e1 = entries.first().expect("we know this exists");
         assert_eq!("SELECT * FROM baz WHERE quux = 1;", e1.query);
     }
+
+    #[test]
+    fn it_tolerates_missing_parts_of_the_user_line() {
+        let log = indoc!(
+            b"
+            # Time: 2019-07-30T13:01:34.887103Z
+            # User@Host: [foo] @ localhost []
+            # Query_time: 1.000000  Lock_time: 0.000000 Rows_sent: 1  Rows_examined: 1
+            SELECT 1;
+            # Time: 2019-07-30T13:01:35.887103Z
+            # User@Host: @ localhost []
+            # Query_time: 1.000000  Lock_time: 0.000000 Rows_sent: 1  Rows_examined: 1
+            SELECT 2;
+            # Time: 2019-07-30T13:01:36.887103Z
+            # User@Host: foo[foo] @ []  Id: 1337
+            # Query_time: 1.000000  Lock_time: 0.000000 Rows_sent: 1  Rows_examined: 1
+            SELECT 3;
+            # Time: 2019-07-30T13:01:37.887103Z
+            # User@Host: app.user[app] @ db-1.example.com [::1]  Id: 1338
+            # Query_time: 1.000000  Lock_time: 0.000000 Rows_sent: 1  Rows_examined: 1
+            SELECT 4;
+        "
+        );
+
+        let parsed = parse_log(log as &[u8], &ParseOptions::default());
+        assert_eq!(0, parsed.malformed_entries);
+        let users: Vec<(&str, &str)> =
+            parsed.entries.iter().map(|e| (e.user.as_str(), e.host.as_str())).collect();
+        assert_eq!(
+            vec![
+                ("foo", "localhost"),
+                (UNKNOWN, "localhost"),
+                ("foo", UNKNOWN),
+                ("app.user", "db-1.example.com")
+            ],
+            users
+        );
+    }
+
+    #[test]
+    fn it_skips_malformed_entries() {
+        let log = indoc!(
+            b"
+            # Time: 2019-07-30T13:01:34.887103Z
+            # User@Host: this line is broken
+            # Query_time: 1.289039  Lock_time: 0.000061 Rows_sent: 50000  Rows_examined: 100000
+            SELECT * FROM baz WHERE quux = 1;
+            # Time: 2019-07-30T13:01:34.887103Z
+            # User@Host: foo[bar] @  [127.0.0.1]  Id: 1337
+            # Query_time: 0.123456  Lock_time: 0.000009 Rows_sent: 1  Rows_examined: 1
+            UPDATE baz SET quux = 2 WHERE id = 42;
+            # Time: 2019-07-30T13:01:34.887103Z
+            # User@Host: foo[bar] @  [127.0.0.1]  Id: 1337
+            # Query_time: so slow
+            DELETE FROM baz;
+        "
+        );
+
+        let parsed = parse_log(log as &[u8], &ParseOptions::default());
+        assert_eq!(1, parsed.entries.len());
+        assert_eq!("UPDATE baz SET quux = 2 WHERE id = 42;", parsed.entries[0].query);
+        assert_eq!(2, parsed.malformed_entries);
+        assert_eq!(2, parsed.warnings.len());
+    }
+
+    #[test]
+    fn it_ignores_a_comment_after_the_timestamp() {
+        let log = indoc!(
+            b"
+            # Time: 2021-05-11T07:00:13.212839Z  # aurora
+            # User@Host: foo[bar] @  [127.0.0.1]  Id: 1337
+            # Query_time: 1.000000  Lock_time: 0.000000 Rows_sent: 1  Rows_examined: 1
+            SELECT 1;
+        "
+        );
+
+        let parsed = parse_log(log as &[u8], &ParseOptions::default());
+        assert_eq!(0, parsed.malformed_entries);
+        assert_eq!(1, parsed.entries.len());
+        assert_eq!(datetime!(2021-05-11 07:00:13.212839 UTC), parsed.entries[0].timestamp);
+    }
+
+    #[test]
+    fn it_skips_entries_with_a_malformed_timestamp() {
+        let log = indoc!(
+            b"
+            # Time: 2021-05-11T25:61:13.212839Z
+            # User@Host: foo[bar] @  [127.0.0.1]  Id: 1337
+            # Query_time: 1.000000  Lock_time: 0.000000 Rows_sent: 1  Rows_examined: 1
+            SELECT 1;
+            # Time: 210511  7:00:13 trailing garbage
+            # User@Host: foo[bar] @  [127.0.0.1]  Id: 1337
+            # Query_time: 1.000000  Lock_time: 0.000000 Rows_sent: 1  Rows_examined: 1
+            SELECT 2;
+            # Time: 2021-05-11T07:00:14.000000Z
+            # User@Host: foo[bar] @  [127.0.0.1]  Id: 1337
+            # Query_time: 1.000000  Lock_time: 0.000000 Rows_sent: 1  Rows_examined: 1
+            SELECT 3;
+        "
+        );
+
+        let parsed = parse_log(log as &[u8], &ParseOptions::default());
+        assert_eq!(2, parsed.malformed_entries);
+        assert_eq!(1, parsed.entries.len());
+        assert_eq!("SELECT 3;", parsed.entries[0].query);
+    }
+
+    #[test]
+    fn it_reuses_the_time_for_entries_without_their_own() {
+        let log = indoc!(
+            b"
+            # Time: 2019-07-30T13:01:34.887103Z
+            # User@Host: foo[bar] @  [127.0.0.1]  Id: 1337
+            # Query_time: 0.123456  Lock_time: 0.000009 Rows_sent: 1  Rows_examined: 1
+            SELECT 1;
+            # User@Host: baz[baz] @  [127.0.0.2]  Id: 1338
+            # Query_time: 1.289039  Lock_time: 0.000061 Rows_sent: 50000  Rows_examined: 100000
+            SELECT 2;
+            # Time: 2019-07-30T13:05:00.000000Z
+            # User@Host: foo[bar] @  [127.0.0.1]  Id: 1337
+            # Query_time: 0.123456  Lock_time: 0.000009 Rows_sent: 1  Rows_examined: 1
+            SELECT 3;
+        "
+        );
+
+        let entries = parse_log(log as &[u8], &ParseOptions::default()).entries;
+        assert_eq!(3, entries.len());
+        assert_eq!("baz", entries[1].user);
+        assert_eq!("SELECT 2;", entries[1].query);
+        assert_eq!(datetime!(2019-07-30 13:01:34.887103 UTC), entries[0].timestamp);
+        assert_eq!(entries[0].timestamp, entries[1].timestamp);
+        assert_eq!(datetime!(2019-07-30 13:05:00 UTC), entries[2].timestamp);
+    }
+
+    #[test]
+    fn it_does_not_end_queries_inside_string_literals() {
+        let log = indoc!(
+            b"
+            # Time: 2019-07-30T13:01:34.887103Z
+            # User@Host: foo[bar] @  [127.0.0.1]  Id: 1337
+            # Query_time: 0.123456  Lock_time: 0.000009 Rows_sent: 1  Rows_examined: 1
+            SELECT CONCAT(name, ';
+            ', 'it\\'s;
+            ') FROM foo WHERE bar = 'a;b';
+            # Time: 2019-07-30T13:01:34.887103Z
+            # User@Host: foo[bar] @  [127.0.0.1]  Id: 1337
+            # Query_time: 0.123456  Lock_time: 0.000009 Rows_sent: 1  Rows_examined: 1
+            SELECT 2;
+        "
+        );
+
+        let entries = parse_log(log as &[u8], &ParseOptions::default()).entries;
+        assert_eq!(2, entries.len());
+        assert_eq!(
+            "SELECT CONCAT(name, '; ', 'it\\'s; ') FROM foo WHERE bar = 'a;b';",
+            entries[0].query
+        );
+    }
+
+    #[test]
+    fn it_ignores_quotes_inside_comments() {
+        let log = indoc!(
+            b"
+            # Time: 2019-07-30T13:01:34.887103Z
+            # User@Host: foo[bar] @  [127.0.0.1]  Id: 1337
+            # Query_time: 0.123456  Lock_time: 0.000009 Rows_sent: 1  Rows_examined: 1
+            SELECT 1 -- don't
+            /* it's
+            a comment */ FROM foo;
+            # Time: 2019-07-30T13:01:34.887103Z
+            # User@Host: foo[bar] @  [127.0.0.1]  Id: 1337
+            # Query_time: 0.123456  Lock_time: 0.000009 Rows_sent: 1  Rows_examined: 1
+            SELECT 2;
+        "
+        );
+
+        let entries = parse_log(log as &[u8], &ParseOptions::default()).entries;
+        assert_eq!(2, entries.len());
+        assert_eq!("SELECT 1 -- don't /* it's a comment */ FROM foo;", entries[0].query);
+        assert_eq!(Context::Code, Context::Code.after("SELECT 1--1, '#'"));
+        assert_eq!(Context::Code, Context::Code.after("SELECT `a\\`"));
+        assert_eq!(Context::Quoted('\''), Context::Code.after("SELECT 'a\\'"));
+    }
+
+    #[test]
+    fn it_stops_at_the_first_malformed_entry_in_strict_mode() {
+        let log = indoc!(
+            b"
+            # Time: 2019-07-30T13:01:34.887103Z
+            # User@Host: foo[bar] @  [127.0.0.1]  Id: 1337
+            # Query_time: 0.123456  Lock_time: 0.000009 Rows_sent: 1  Rows_examined: 1
+            SELECT 1;
+            # Time: 2019-07-30T13:01:34.887103Z
+            # User@Host: this line is broken
+            # Query_time: 1.289039  Lock_time: 0.000061 Rows_sent: 50000  Rows_examined: 100000
+            SELECT 2;
+            # Time: 2019-07-30T13:01:34.887103Z
+            # User@Host: foo[bar] @  [127.0.0.1]  Id: 1337
+            # Query_time: 0.123456  Lock_time: 0.000009 Rows_sent: 1  Rows_examined: 1
+            SELECT 3;
+        "
+        );
+
+        let parsed = parse_log(log as &[u8], &ParseOptions::default());
+        assert_eq!(2, parsed.entries.len());
+        assert_eq!(1, parsed.malformed_entries);
+
+        let options = ParseOptions { strict: true, ..Default::default() };
+        let parsed = parse_log(log as &[u8], &options);
+        assert_eq!(1, parsed.entries.len());
+        assert_eq!(1, parsed.malformed_entries);
+        assert_eq!(1, parsed.warnings.len());
+    }
+
+    #[test]
+    fn it_ends_unterminated_queries_at_the_next_entry() {
+        let log = indoc!(
+            b"
+            # Time: 2019-07-30T13:01:34.887103Z
+            # User@Host: foo[bar] @  [127.0.0.1]  Id: 1337
+            # Query_time: 1.289039  Lock_time: 0.000061 Rows_sent: 50000  Rows_examined: 100000
+            SELECT * FROM baz
+            WHERE quux = 1
+            # Time: 2019-07-30T13:01:35.887103Z
+            # User@Host: foo[bar] @  [127.0.0.1]  Id: 1337
+            # Query_time: 0.123456  Lock_time: 0.000009 Rows_sent: 1  Rows_examined: 1
+            SELECT 2;
+            # User@Host: foo[bar] @  [127.0.0.1]  Id: 1337
+            # Query_time: 0.123456  Lock_time: 0.000009 Rows_sent: 1  Rows_examined: 1
+            SELECT 3
+        "
+        );
+
+        let parsed = parse_log(log as &[u8], &ParseOptions::default());
+        let queries: Vec<&str> = parsed.entries.iter().map(|e| e.query.as_str()).collect();
+        assert_eq!(vec!["SELECT * FROM baz WHERE quux = 1", "SELECT 2;", "SELECT 3"], queries);
+        assert_eq!(0, parsed.malformed_entries);
+    }
+
+    #[test]
+    fn it_skips_entries_with_a_blank_query() {
+        let log = indoc!(
+            b"
+            # Time: 2019-07-30T13:01:34.887103Z
+            # User@Host: foo[bar] @  [127.0.0.1]  Id: 1337
+            # Query_time: 1.289039  Lock_time: 0.000061 Rows_sent: 50000  Rows_examined: 100000
+
+            # Time: 2019-07-30T13:01:35.887103Z
+            # User@Host: foo[bar] @  [127.0.0.1]  Id: 1337
+            # Query_time: 0.123456  Lock_time: 0.000009 Rows_sent: 1  Rows_examined: 1
+            SELECT 1;
+        "
+        );
+
+        let parsed = parse_log(log as &[u8], &ParseOptions::default());
+        let queries: Vec<&str> = parsed.entries.iter().map(|e| e.query.as_str()).collect();
+        assert_eq!(vec!["SELECT 1;"], queries);
+        assert_eq!(1, parsed.empty_entries);
+        assert_eq!(0, parsed.malformed_entries);
+    }
+
+    #[test]
+    fn it_attaches_the_database_only_if_asked_to() {
+        let log = indoc!(
+            b"
+            # Time: 2019-07-30T13:01:34.887103Z
+            # User@Host: foo[bar] @  [127.0.0.1]  Id: 1337
+            # Query_time: 1.289039  Lock_time: 0.000061 Rows_sent: 50000  Rows_examined: 100000
+            use `shop`;
+            SET timestamp=1000000000;
+            SELECT * FROM baz WHERE quux = 1;
+        "
+        );
+
+        let entries = parse_log(log as &[u8], &ParseOptions::default()).entries;
+        assert_eq!(None, entries[0].database);
+        assert_eq!("SELECT * FROM baz WHERE quux = 1;", entries[0].query);
+
+        let options = ParseOptions { keep_use: true, ..Default::default() };
+        let entries = parse_log(log as &[u8], &options).entries;
+        assert_eq!(Some("shop".to_string()), entries[0].database);
+        assert_eq!("USE shop; SELECT * FROM baz WHERE quux = 1;", entries[0].query);
+    }
+
+    #[test]
+    fn it_keeps_the_database_of_each_connection() {
+        let log = indoc!(
+            b"
+            # Time: 2019-07-30T13:01:34.887103Z
+            # User@Host: foo[bar] @  [127.0.0.1]  Id: 1337
+            # Query_time: 1.289039  Lock_time: 0.000061 Rows_sent: 1  Rows_examined: 1
+            use shop;
+            SET timestamp=1000000000;
+            SELECT * FROM baz WHERE quux = 1;
+            # Time: 2019-07-30T13:01:35.887103Z
+            # User@Host: foo[bar] @  [127.0.0.1]  Id: 42
+            # Query_time: 1.289039  Lock_time: 0.000061 Rows_sent: 1  Rows_examined: 1
+            SET timestamp=1000000001;
+            SELECT * FROM baz WHERE quux = 2;
+            # Time: 2019-07-30T13:01:36.887103Z
+            # User@Host: foo[bar] @  [127.0.0.1]  Id: 1337
+            # Query_time: 1.289039  Lock_time: 0.000061 Rows_sent: 1  Rows_examined: 1
+            SET timestamp=1000000002;
+            SELECT * FROM baz WHERE quux = 3;
+        "
+        );
+
+        let options = ParseOptions { keep_use: true, ..Default::default() };
+        let entries = parse_log(log as &[u8], &options).entries;
+        assert_eq!(Some("shop".to_string()), entries[0].database);
+        assert_eq!(None, entries[1].database);
+        assert_eq!(Some("shop".to_string()), entries[2].database);
+        assert_eq!("USE shop; SELECT * FROM baz WHERE quux = 3;", entries[2].query);
+    }
+
+    #[test]
+    fn it_skips_or_truncates_oversized_queries() {
+        let log = indoc!(
+            b"
+            # Time: 2019-07-30T13:01:34.887103Z
+            # User@Host: foo[bar] @  [127.0.0.1]  Id: 1337
+            # Query_time: 1.289039  Lock_time: 0.000061 Rows_sent: 50000  Rows_examined: 100000
+            SELECT * FROM baz WHERE quux IN (1, 2, 3, 4, 5, 6, 7, 8, 9, 10);
+            # Time: 2019-07-30T13:01:34.887103Z
+            # User@Host: foo[bar] @  [127.0.0.1]  Id: 1337
+            # Query_time: 0.123456  Lock_time: 0.000009 Rows_sent: 1  Rows_examined: 1
+            SELECT 1;
+        "
+        );
+
+        let options = ParseOptions { max_query_bytes: Some(20), ..Default::default() };
+        let parsed = parse_log(log as &[u8], &options);
+        assert_eq!(1, parsed.entries.len());
+        assert_eq!("SELECT 1;", parsed.entries[0].query);
+        assert_eq!(1, parsed.oversized_entries);
+        assert_eq!(0, parsed.malformed_entries);
+
+        let options = ParseOptions {
+            max_query_bytes: Some(20),
+            oversized_queries: OversizedQueries::Truncate,
+            ..Default::default()
+        };
+        let parsed = parse_log(log as &[u8], &options);
+        assert_eq!(2, parsed.entries.len());
+        assert_eq!("SELECT * FROM baz WH /* truncated */", parsed.entries[0].query);
+        assert_eq!("SELECT 1;", parsed.entries[1].query);
+        assert_eq!(1, parsed.oversized_entries);
+    }
+
+    #[test]
+    fn it_stops_reading_oversized_queries_into_memory() {
+        let log = indoc!(
+            b"
+            # Time: 2019-07-30T13:01:34.887103Z
+            # User@Host: foo[bar] @  [127.0.0.1]  Id: 1337
+            # Query_time: 1.289039  Lock_time: 0.000061 Rows_sent: 50000  Rows_examined: 100000
+            SELECT *
+            FROM baz
+            WHERE quux IN (1, 2, 3,
+            4, 5, 6);
+            # Time: 2019-07-30T13:01:34.887103Z
+            # User@Host: foo[bar] @  [127.0.0.1]  Id: 1337
+            # Query_time: 0.123456  Lock_time: 0.000009 Rows_sent: 1  Rows_examined: 1
+            SELECT 1;
+        "
+        );
+
+        let options = ParseOptions {
+            max_query_bytes: Some(12),
+            oversized_queries: OversizedQueries::Truncate,
+            ..Default::default()
+        };
+        let parsed = parse_log(log as &[u8], &options);
+        assert_eq!(2, parsed.entries.len());
+        assert_eq!("SELECT * FRO /* truncated */", parsed.entries[0].query);
+        assert_eq!("SELECT 1;", parsed.entries[1].query);
+        assert_eq!(1, parsed.oversized_entries);
+        assert_eq!(0, parsed.malformed_entries);
+    }
+
+    #[test]
+    fn it_suppresses_warnings_but_still_counts_them() {
+        let log = indoc!(
+            b"
+            # Time: yesterday
+            # User@Host: foo[bar] @  [127.0.0.1]  Id: 1337
+            # Query_time: 1.289039  Lock_time: 0.000061 Rows_sent: 50000  Rows_examined: 100000
+            SELECT * FROM baz WHERE quux = 1;
+            # Time: 2019-07-30T13:01:34.887103Z
+            # User@Host: this line is broken
+            # Query_time: 0.123456  Lock_time: 0.000009 Rows_sent: 1  Rows_examined: 1
+            UPDATE baz SET quux = 2 WHERE id = 42;
+        "
+        );
+
+        let options = ParseOptions { quiet_errors: true, ..Default::default() };
+        let parsed = parse_log(log as &[u8], &options);
+        assert_eq!(0, parsed.entries.len());
+        assert_eq!(2, parsed.malformed_entries);
+        assert!(parsed.warnings.is_empty());
+    }
+
+    #[test]
+    fn it_round_trips_entries_through_ndjson() {
+        let log = indoc!(
+            b"
+            # Time: 2019-07-30T13:01:34.887103Z
+            # User@Host: foo[bar] @  [127.0.0.1]  Id: 1337
+            # Query_time: 1.289039  Lock_time: 0.000061 Rows_sent: 50000  Rows_examined: 100000
+            SELECT * FROM baz WHERE quux = 1;
+            # Time: 2019-07-30T13:01:35.000001Z
+            # User@Host: foo[bar] @  [127.0.0.1]  Id: 1337
+            # Query_time: 50.000123  Lock_time: 50.000061 Rows_sent: 0  Rows_examined: 1 Errno: 1205
+            UPDATE baz SET quux = 'a \"quoted\" string' WHERE id = 42;
+        "
+        );
+        let entries = parse_log(log as &[u8], &ParseOptions::default()).entries;
+
+        let mut ndjson = Vec::new();
+        entries.iter().for_each(|entry| write_json_line(&mut ndjson, entry));
+
+        let options = ParseOptions { input_format: InputFormat::Ndjson, ..Default::default() };
+        let parsed = parse_input(ndjson.as_slice(), &options);
+        assert_eq!(0, parsed.malformed_entries);
+        assert_eq!(entries, parsed.entries);
+    }
+
+    #[test]
+    fn it_skips_invalid_ndjson_lines() {
+        let ndjson = b"{\"foo\": 1}\n\nnot even json\n";
+        let parsed = parse_ndjson(ndjson as &[u8], &ParseOptions::default());
+        assert_eq!(0, parsed.entries.len());
+        assert_eq!(2, parsed.malformed_entries);
+    }
 }
@@ -0,0 +1,44 @@
+use std::fmt::Display;
+use std::io::{self, Stderr, Write};
+
+/// Writes diagnostic messages to stderr (or any other sink) depending on the verbosity level:
+///
+/// - 1: statistics about the parsed log
+/// - 2: progress for each input file
+/// - 3: every unparseable entry
+///
+/// Warnings are always written.
+pub struct Logger<W: Write = Stderr> {
+    verbosity: u8,
+    out: W,
+}
+
+impl Logger {
+    pub fn stderr(verbosity: u8) -> Self {
+        Logger::new(verbosity, io::stderr())
+    }
+}
+
+impl<W: Write> Logger<W> {
+    pub fn new(verbosity: u8, out: W) -> Self {
+        Logger { verbosity, out }
+    }
+
+    pub fn enabled(&self, level: u8) -> bool {
+        self.verbosity >= level
+    }
+
+    pub fn info(&mut self, level: u8, message: impl Display) {
+        if self.enabled(level) {
+            writeln!(self.out, "{}", message).unwrap();
+        }
+    }
+
+    pub fn warn(&mut self, message: impl Display) {
+        writeln!(self.out, "Warning: {}", message).unwrap();
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+}
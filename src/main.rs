@@ -1,130 +1,1238 @@
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::fs;
 use std::fs::File;
-use std::io::Write;
-use std::{io, process};
+use std::io;
+use std::io::{Cursor, IsTerminal, Read, Write};
+use std::path::Path;
+use std::process;
+use std::thread;
 
 use rayon::prelude::*;
+use time::OffsetDateTime;
 
-use crate::aggregate::{aggregate_entries, aggregate_normalized, AggregateLogEntry};
-use crate::filters::Filter;
-use crate::log_parser::{parse_log, LogEntry};
-use crate::normalize::{normalize, NormalizedLogEntry};
-use crate::opt::{parse_opts, Opt, SortOrder};
-
-mod aggregate;
-mod filters;
-mod log_parser;
-mod normalize;
-mod opt;
+use myslowlog::aggregate::{
+    aggregate_by_key, aggregate_duplicates, aggregate_entries, aggregate_grouped,
+    aggregate_normalized, merge_aggregated, read_report, retain_min_time, retain_new,
+    sort_aggregated, sort_aggregated_with, AggregateLogEntry,
+};
+use myslowlog::benchmark::{memory_report, Benchmark, Phase};
+use myslowlog::compare::{compare_logs, regressed_beyond, Change, Comparison};
+use myslowlog::decompress::{decompress, is_compressed};
+use myslowlog::filters::{describe_filters, Filter};
+use myslowlog::format::{
+    group_thousands, relative_time, write_annotated_script, write_inventory, write_json,
+    write_repro_script, LimitedWriter,
+};
+use myslowlog::histogram::{query_time_histogram, Bucket};
+use myslowlog::indexes::suggest_indexes;
+use myslowlog::influx;
+#[cfg(feature = "journald")]
+use myslowlog::journald::{JournalReader, SystemJournal};
+use myslowlog::log_parser::{parse_input, InputFormat, LogEntry, ParsedLog};
+use myslowlog::logger::Logger;
+use myslowlog::normalize::{
+    fingerprint, fold_keyword_case, merge_placeholder_runs, normalize_literals, parse_errors,
+    redact_literals, NormalizeCache, NormalizedLogEntry,
+};
+use myslowlog::opt::{parse_opts, GroupBy, Opt, OutputFormat, SortOrder, SplitBy};
+use myslowlog::prometheus;
+use myslowlog::summary::{summarize, Summary};
+use myslowlog::tail::read_last_blocks;
+use myslowlog::timeseries::{time_series, TimeSeries};
+use myslowlog::visit::silence_parser_panics;
 
 fn main() {
+    silence_parser_panics();
     let (opt, filters) = parse_opts();
-    if opt.version {
-        print_version();
-        process::exit(0);
+    let mut logger = Logger::stderr(opt.verbosity());
+    if opt.filter_summary {
+        logger.info(0, describe_filters(&filters));
+    }
+    if let Some(interval) = opt.watch {
+        watch(&opt, &filters, interval, &mut logger);
     }
 
-    let all_entries = {
-        if let Some(filename) = &opt.filename {
-            let file = File::open(filename).expect("Unable to read from file");
-            parse_log(file)
-        } else {
-            parse_log(io::stdin())
+    let mut benchmark = Benchmark::new();
+    let parsed = benchmark.time(Phase::Parsing, || read_logs(&opt, &mut logger));
+    report_parse_results(&parsed, &mut logger);
+    let mut status = exit_status(&parsed, &opt);
+    if opt.strict && status != 0 {
+        process::exit(status);
+    }
+    let entries = benchmark.time(Phase::Filtering, || filter_entries(parsed.entries, &filters));
+    let mut out = LimitedWriter::new(io::stdout().lock(), opt.max_output_bytes);
+
+    if opt.summary || opt.summary_json.is_some() {
+        let summary = summarize(&entries);
+        if opt.summary {
+            print_summary(&mut out, &summary, &opt);
         }
-    };
+        if let Some(path) = &opt.summary_json {
+            write_summary_json(Path::new(path), &summary).unwrap_or_else(|error| {
+                panic!("Unable to write the summary to {}: {}", path, error)
+            });
+        }
+    }
 
-    match (opt.aggregate, opt.normalize) {
-        (_, true) => render_normalized(all_entries, &filters, &opt),
-        (true, _) => render_aggregated(all_entries, &filters, &opt),
-        _ => render_individual(all_entries, &filters, &opt),
+    let displayed = if let Some(path) = &opt.baseline {
+        let baseline = filter_entries(read_baseline(path, &opt).entries, &filters);
+        let comparisons = compare_logs(baseline, entries, opt.normalize_options());
+        if let Some(percent) = opt.fail_if_regression {
+            let regressions = regressed_beyond(&comparisons, percent);
+            for regression in &regressions {
+                let change = regression.total_query_time_change().unwrap_or_default();
+                logger.warn(format!(
+                    "Total time regressed by {:.1}%: {}",
+                    100.0 * change,
+                    regression.query
+                ));
+            }
+            if !regressions.is_empty() {
+                status = REGRESSION_STATUS;
+            }
+        }
+        print_comparisons(&mut out, comparisons, &opt);
+        Vec::new()
+    } else if let Some(edges) = &opt.latency_histogram {
+        print_histogram(&mut out, &query_time_histogram(&entries, edges), &opt);
+        Vec::new()
+    } else if let Some(interval) = opt.interval {
+        render_time_series(&mut out, entries, interval, &opt);
+        Vec::new()
+    } else if let Some(min_count) = opt.report_duplicates {
+        print_aggregated(
+            &mut out,
+            aggregate_duplicates(entries, min_count, opt.aggregate_options()),
+            &opt,
+        )
+    } else if opt.suggest_indexes {
+        render_index_suggestions(&mut out, entries, &opt);
+        Vec::new()
+    } else if let (Some(split_by), Some(outdir)) = (opt.split_by, &opt.outdir) {
+        write_split_reports(
+            Path::new(outdir),
+            entries,
+            split_by,
+            &opt,
+            &mut logger,
+            &mut benchmark,
+        )
+        .unwrap_or_else(|error| panic!("Unable to write the reports to {}: {}", outdir, error));
+        Vec::new()
+    } else {
+        render_report(&mut out, entries, &opt, &mut logger, &mut benchmark)
     };
+    out.flush().unwrap();
+    if let Err(error) = push_metrics(&displayed, &opt) {
+        logger.warn(error);
+        status = PUSH_FAILURE_STATUS;
+    }
+    if opt.benchmark {
+        logger.info(0, &benchmark);
+    }
+    if opt.report_memory {
+        logger.info(0, memory_report());
+    }
+
+    if status != 0 {
+        process::exit(status);
+    }
+}
+
+/// Re-reads the logfiles and reprints the aggregated report at the given interval until the
+/// process is interrupted. Like watch(1), the screen is cleared before each refresh, but only if
+/// stdout is a terminal, so that the reports can also be appended to a file.
+fn watch(
+    options: &Opt,
+    filters: &[Box<dyn Filter>],
+    interval: time::Duration,
+    logger: &mut Logger<impl Write>,
+) -> ! {
+    let interval = interval.try_into().expect("The watch interval must be positive");
+    let clear = io::stdout().is_terminal();
+    loop {
+        let report = refresh(options, filters, logger);
+        let mut out = LimitedWriter::new(io::stdout().lock(), options.max_output_bytes);
+        if clear {
+            // move the cursor to the top left corner and erase the screen
+            write!(out, "\x1b[H\x1b[2J").unwrap();
+        }
+        out.write_all(&report).unwrap();
+        out.flush().unwrap();
+        drop(out);
+        thread::sleep(interval);
+    }
+}
+
+/// Parses the logfiles from the start and renders the aggregated report of the matching entries
+fn refresh(options: &Opt, filters: &[Box<dyn Filter>], logger: &mut Logger<impl Write>) -> Vec<u8> {
+    let parsed = read_logs(options, logger);
+    let entries = filter_entries(parsed.entries, filters);
+    let mut out = Vec::new();
+    render_report(&mut out, entries, options, logger, &mut Benchmark::new());
+    out
+}
+
+/// Renders the entries individually or as aggregated records, depending on the options.
+/// Returns the displayed aggregated records, if any.
+fn render_report(
+    out: &mut impl Write,
+    entries: Vec<LogEntry>,
+    options: &Opt,
+    logger: &mut Logger<impl Write>,
+    benchmark: &mut Benchmark,
+) -> Vec<AggregateLogEntry> {
+    if let Some(group_by) = options.group_by() {
+        render_grouped(out, entries, &group_by, options, benchmark)
+    } else {
+        match (options.aggregate(), options.normalize) {
+            (true, true) => render_normalized(out, entries, options, logger, benchmark),
+            (true, false) => render_aggregated(out, entries, options, benchmark),
+            (false, _) => {
+                render_individual(out, entries, options);
+                Vec::new()
+            }
+        }
+    }
+}
+
+/// Pushes the metrics of the displayed records to the Pushgateway if one is given. This happens
+/// once the report is complete, since each push replaces the metrics of the previous one.
+#[cfg_attr(not(feature = "push"), allow(unused_variables))]
+fn push_metrics(records: &[AggregateLogEntry], options: &Opt) -> Result<(), String> {
+    #[cfg(feature = "push")]
+    if let Some(url) = &options.pushgateway {
+        let top: Vec<&AggregateLogEntry> = records.iter().collect();
+        prometheus::push(url, &options.job, &prometheus::exposition(&top))?;
+    }
+    Ok(())
+}
+
+/// Writes one report per distinct user or schema to the output directory, each one covering
+/// only the entries of its key. Entries without a schema end up in a file named `_`.
+fn write_split_reports(
+    outdir: &Path,
+    entries: Vec<LogEntry>,
+    split_by: SplitBy,
+    options: &Opt,
+    logger: &mut Logger<impl Write>,
+    benchmark: &mut Benchmark,
+) -> io::Result<()> {
+    fs::create_dir_all(outdir)?;
+    let mut parts: BTreeMap<String, Vec<LogEntry>> = BTreeMap::new();
+    for entry in entries {
+        let key = match split_by {
+            SplitBy::User => safe_filename(&entry.user),
+            SplitBy::Schema => safe_filename(entry.database.as_deref().unwrap_or_default()),
+        };
+        parts.entry(key).or_default().push(entry);
+    }
+    for (key, entries) in parts {
+        let path = outdir.join(format!("{}.{}", key, file_extension(options.format())));
+        logger.info(1, format!("Writing {} entries to {}", entries.len(), path.display()));
+        let file = io::BufWriter::new(File::create(&path)?);
+        let mut out = LimitedWriter::new(file, options.max_output_bytes);
+        render_report(&mut out, entries, options, logger, benchmark);
+        out.flush()?;
+    }
+    Ok(())
+}
+
+/// Replaces every character that could escape the output directory or trip up a shell with an
+/// underscore. Keys that differ only in such characters share a file.
+fn safe_filename(key: &str) -> String {
+    let name: String = key
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+        .collect();
+    if name.is_empty() {
+        "_".to_string()
+    } else {
+        name
+    }
+}
+
+fn file_extension(format: OutputFormat) -> &'static str {
+    match format {
+        OutputFormat::Text => "txt",
+        OutputFormat::Ndjson => "ndjson",
+        OutputFormat::Influx => "lp",
+        OutputFormat::SqlComments => "sql",
+        OutputFormat::Prometheus => "prom",
+    }
+}
+
+/// Distinguishes a failed regression gate from problems with the input
+const REGRESSION_STATUS: i32 = 2;
+
+/// The metrics could not be pushed to the Pushgateway
+const PUSH_FAILURE_STATUS: i32 = 3;
+
+/// Input problems that did not prevent producing a report still result in a nonzero status,
+/// as do any malformed entries in strict mode
+fn exit_status(parsed: &ParsedLog, options: &Opt) -> i32 {
+    let failed = parsed.unreadable_files > 0 || (options.strict && parsed.malformed_entries > 0);
+    i32::from(failed)
+}
+
+fn filter_entries(entries: Vec<LogEntry>, filters: &[Box<dyn Filter>]) -> Vec<LogEntry> {
+    if filters.is_empty() {
+        return entries;
+    }
+    entries
+        .into_par_iter()
+        .filter(|entry| filters.iter().all(|filter| filter.matches(entry)))
+        .collect()
+}
+
+fn read_logs(options: &Opt, logger: &mut Logger<impl Write>) -> ParsedLog {
+    let mut parsed = read_input(options, logger);
+
+    // Note that this requires the complete input to be materialized before processing.
+    if options.sort_input_by_time {
+        parsed.entries.sort_by_key(|entry| entry.timestamp);
+    }
+    if let Some(last) = options.last {
+        let excess = parsed.entries.len().saturating_sub(last);
+        parsed.entries.drain(..excess);
+    }
+
+    parsed
+}
+
+/// Parses the journal, stdin or the logfiles, whichever the options select
+fn read_input(options: &Opt, logger: &mut Logger<impl Write>) -> ParsedLog {
+    let parse_options = options.parse_options();
+    #[cfg(feature = "journald")]
+    if let Some(unit) = &options.journald {
+        logger.info(2, format!("Reading the journal of {}", unit));
+        let journal = SystemJournal::open(unit)
+            .unwrap_or_else(|err| panic!("Unable to read the journal of {}: {}", unit, err));
+        return parse_input(JournalReader::new(journal), &parse_options);
+    }
+    if options.filenames.is_empty() {
+        logger.info(2, "Reading from stdin");
+        let input = decompress(io::stdin()).expect("Unable to read from stdin");
+        return parse_input(input, &parse_options);
+    }
+
+    let mut parsed = ParsedLog::default();
+    for filename in &options.filenames {
+        logger.info(2, format!("Reading {}", filename));
+        let input = match open_log(filename, options) {
+            Ok(input) => input,
+            Err(err) if options.skip_unreadable => {
+                logger.warn(format!("Skipping unreadable file {}: {}", filename, err));
+                parsed.unreadable_files += 1;
+                continue;
+            }
+            Err(err) => panic!("Unable to read from file {}: {}", filename, err),
+        };
+        let parsed_file = parse_input(input, &parse_options);
+        logger.info(2, format!("Parsed {} entries from {}", parsed_file.entries.len(), filename));
+        parsed.append(parsed_file);
+    }
+    parsed
+}
+
+/// With --last, only the end of an uncompressed slow log is read, which is much faster for
+/// large files. Other input, including pipes that cannot seek, has to be parsed in full and is
+/// cut down to size afterwards.
+fn open_log(filename: &str, options: &Opt) -> io::Result<Box<dyn Read>> {
+    let mut file = File::open(filename)?;
+    match options.last {
+        Some(last)
+            if options.input_format == InputFormat::Mysql
+                && file.metadata()?.is_file()
+                && !is_compressed(&mut file)? =>
+        {
+            Ok(Box::new(Cursor::new(read_last_blocks(file, last)?)))
+        }
+        _ => decompress(file),
+    }
+}
+
+fn read_baseline(path: &str, options: &Opt) -> ParsedLog {
+    let input = File::open(path)
+        .and_then(decompress)
+        .unwrap_or_else(|err| panic!("Unable to read from baseline {}: {}", path, err));
+    parse_input(input, &options.parse_options())
+}
+
+fn report_parse_results(parsed: &ParsedLog, logger: &mut Logger<impl Write>) {
+    parsed.warnings.iter().for_each(|warning| logger.warn(warning));
+    logger.info(
+        1,
+        format!(
+            "Parsed {} entries, skipped {} malformed entries",
+            parsed.entries.len(),
+            parsed.malformed_entries
+        ),
+    );
+    if parsed.oversized_entries > 0 {
+        logger.info(
+            1,
+            format!("{} entries exceeded the maximum query size", parsed.oversized_entries),
+        );
+    }
+    if parsed.empty_entries > 0 {
+        logger.info(1, format!("Skipped {} entries with an empty query", parsed.empty_entries));
+    }
+}
+
+fn print_summary(out: &mut impl Write, summary: &Summary, options: &Opt) {
+    match options.format() {
+        OutputFormat::Ndjson => write_json(out, summary, options.pretty),
+        OutputFormat::Influx => writeln!(out, "{}", influx::summary_line(summary)).unwrap(),
+        OutputFormat::Text => writeln!(out, "{}", summary).unwrap(),
+        OutputFormat::SqlComments => {
+            summary.to_string().lines().for_each(|line| writeln!(out, "-- {}", line).unwrap());
+            writeln!(out).unwrap();
+        }
+        OutputFormat::Prometheus => {
+            summary.to_string().lines().for_each(|line| writeln!(out, "# {}", line).unwrap());
+        }
+    }
 }
 
-fn render_individual(entries: Vec<LogEntry>, filters: &[Box<dyn Filter>], options: &Opt) {
-    let mut filtered: Vec<LogEntry> = entries
+/// Writes the summary as a single JSON object to a separate file, regardless of the output format
+fn write_summary_json(path: &Path, summary: &Summary) -> io::Result<()> {
+    let mut file = io::BufWriter::new(File::create(path)?);
+    write_json(&mut file, summary, true);
+    file.flush()
+}
+
+fn print_histogram(out: &mut impl Write, buckets: &[Bucket], options: &Opt) {
+    for bucket in buckets {
+        match options.format() {
+            OutputFormat::Ndjson => write_json(out, bucket, options.pretty),
+            OutputFormat::Influx => writeln!(
+                out,
+                "slow_query_histogram,bucket={} count={}i",
+                bucket.lower, bucket.count
+            )
+            .unwrap(),
+            OutputFormat::Text | OutputFormat::SqlComments | OutputFormat::Prometheus => {
+                writeln!(out, "{}", bucket).unwrap()
+            }
+        }
+    }
+}
+
+fn render_time_series(
+    out: &mut impl Write,
+    entries: Vec<LogEntry>,
+    interval: time::Duration,
+    options: &Opt,
+) {
+    let cache = NormalizeCache::new(options.normalize_options());
+    let keyed = entries
         .into_par_iter()
-        .filter(|entry| filters.is_empty() || filters.iter().all(|filter| filter.matches(entry)))
+        .map(|entry| (cache.normalize(entry.clone()).normalized_query, entry))
         .collect();
+    let mut series = time_series(keyed, interval);
+    series.sort_unstable_by_key(|s| std::cmp::Reverse(s.total_query_time()));
+    series.truncate(options.limit());
+    print_time_series(out, &series, options);
+}
 
+fn print_time_series(out: &mut impl Write, series: &[TimeSeries], options: &Opt) {
+    for s in series {
+        match options.format() {
+            OutputFormat::Ndjson => write_json(out, s, options.pretty),
+            OutputFormat::Influx => s.points().for_each(|point| {
+                writeln!(out, "{}", influx::time_series_line(s, &point)).unwrap()
+            }),
+            OutputFormat::Text | OutputFormat::SqlComments | OutputFormat::Prometheus => {
+                writeln!(out, "{}", s).unwrap()
+            }
+        }
+    }
+}
+
+/// Writes the individual entries, with their normalized queries if `--normalize` is given
+/// or their literals redacted if `--redact` is given. Only the displayed entries are changed.
+fn render_individual(out: &mut impl Write, mut filtered: Vec<LogEntry>, options: &Opt) {
     match options.order {
-        None | Some(SortOrder::Count) => (),
+        None | Some(SortOrder::Count) | Some(SortOrder::StddevTime) => (),
         _ => filtered.sort_unstable_by_key(|e| e.query_time),
     };
 
-    let mut stdout = io::stdout().lock();
+    let now = OffsetDateTime::now_utc();
+    let cache = NormalizeCache::new(options.normalize_options());
+
+    if options.format() == OutputFormat::Ndjson {
+        cap_per_user(filtered.iter().rev(), options.limit_per_user).take(options.limit()).for_each(
+            |e| {
+                if options.normalize {
+                    write_json(out, &cache.normalize(e.clone()), options.pretty)
+                } else if options.redact {
+                    write_json(out, &redacted(e), options.pretty)
+                } else {
+                    write_json(out, e, options.pretty)
+                }
+            },
+        );
+        return;
+    }
+
+    if options.format() == OutputFormat::Influx {
+        cap_per_user(filtered.iter().rev(), options.limit_per_user).take(options.limit()).for_each(
+            |e| {
+                let fingerprint = fingerprint(&cache.normalize(e.clone()).normalized_query);
+                if options.redact {
+                    writeln!(out, "{}", influx::entry_line(&redacted(e), &fingerprint)).unwrap();
+                } else {
+                    writeln!(out, "{}", influx::entry_line(e, &fingerprint)).unwrap();
+                }
+            },
+        );
+        return;
+    }
 
-    filtered.iter().rev().take(options.limit).enumerate().for_each(|(i, entry)| {
+    let entries = cap_per_user(filtered.iter().rev(), options.limit_per_user);
+    entries.take(options.limit()).enumerate().for_each(|(i, entry)| {
+        let timestamp = match (&options.time_format, options.relative_time) {
+            (_, true) => relative_time(entry.timestamp, now),
+            (Some(format), _) => format.format(entry.timestamp),
+            _ => entry.timestamp.to_string(),
+        };
         writeln!(
-            stdout,
-            "#{}: [{}] {}@{}, query_time {:.3} s, lock_time {}, rows_examined {}, rows_sent {}",
+            out,
+            "#{}: [{}] {}@{}, query_time {:.3} s, lock_time {}, rows_examined {}, rows_sent {}{}",
             i + 1,
-            entry.timestamp,
+            timestamp,
             entry.user,
             entry.host,
             entry.query_time.as_seconds_f64(),
             entry.lock_time,
-            entry.rows_examined,
-            entry.rows_sent,
+            format_number(entry.rows_examined.into(), options),
+            format_number(entry.rows_sent.into(), options),
+            format_extra_metrics(entry, options),
         )
         .unwrap();
-        writeln!(stdout, "{}", entry.query).unwrap();
+        if options.normalize {
+            let normalized = cache.normalize(entry.clone()).normalized_query;
+            writeln!(out, "{}", displayed_query(&normalized, options)).unwrap();
+        } else if options.redact {
+            writeln!(out, "{}", redact_literals(&entry.query)).unwrap();
+        } else {
+            writeln!(out, "{}", entry.query).unwrap();
+        }
     });
 }
 
-fn render_aggregated(entries: Vec<LogEntry>, filters: &[Box<dyn Filter>], options: &Opt) {
-    let filtered: Vec<LogEntry> = entries
-        .into_par_iter()
-        .filter(|entry| filters.is_empty() || filters.iter().all(|filter| filter.matches(entry)))
-        .collect();
+/// A copy of the entry whose query has its literals replaced with placeholders and its
+/// comments removed
+fn redacted(entry: &LogEntry) -> LogEntry {
+    LogEntry { query: redact_literals(&entry.query), ..entry.clone() }
+}
 
-    let aggregated = aggregate_entries(filtered);
-    print_aggregated(aggregated, options);
+/// Skips entries from users who already contributed `cap` entries, if a cap is given
+fn cap_per_user<'a>(
+    entries: impl Iterator<Item = &'a LogEntry>,
+    cap: Option<usize>,
+) -> impl Iterator<Item = &'a LogEntry> {
+    let mut counts: ahash::HashMap<&str, usize> = ahash::HashMap::default();
+    entries.filter(move |entry| {
+        let count = counts.entry(entry.user.as_str()).or_insert(0);
+        *count += 1;
+        cap.is_none_or(|cap| *count <= cap)
+    })
 }
 
-fn render_normalized(entries: Vec<LogEntry>, filters: &[Box<dyn Filter>], options: &Opt) {
-    let normalized: Vec<NormalizedLogEntry> = entries
-        .into_par_iter()
-        .filter(|entry| filters.is_empty() || filters.iter().all(|filter| filter.matches(entry)))
-        .map(normalize)
-        .collect();
+fn format_number(value: i64, options: &Opt) -> String {
+    if options.human_numbers {
+        group_thousands(value)
+    } else {
+        value.to_string()
+    }
+}
 
-    let aggregated = aggregate_normalized(normalized);
-    print_aggregated(aggregated, options);
+fn format_extra_metrics(entry: &LogEntry, options: &Opt) -> String {
+    let mut result = String::new();
+    if let Some(rows_affected) = entry.rows_affected.filter(|&rows| rows != 0) {
+        result += &format!(", rows_affected {}", format_number(rows_affected.into(), options));
+    }
+    if let Some(errno) = entry.errno.filter(|&errno| errno != 0) {
+        result += &format!(", errno {}", errno);
+    }
+    if entry.killed.is_some_and(|killed| killed != 0) {
+        result += ", killed";
+    }
+    result
 }
 
-fn print_aggregated(entries: ahash::HashMap<String, AggregateLogEntry>, options: &Opt) {
-    let mut entries = entries.values().cloned().collect::<Vec<AggregateLogEntry>>();
+fn render_aggregated(
+    out: &mut impl Write,
+    entries: Vec<LogEntry>,
+    options: &Opt,
+    benchmark: &mut Benchmark,
+) -> Vec<AggregateLogEntry> {
+    let aggregated = benchmark.time(Phase::Aggregation, || {
+        if options.merge_case_insensitive_keywords || options.normalize_numbers {
+            let keyed =
+                entries.into_par_iter().map(|e| (textual_key(&e.query, options), e)).collect();
+            aggregate_by_key(keyed, options.aggregate_options())
+        } else {
+            aggregate_entries(entries, options.aggregate_options())
+        }
+    });
+    print_aggregated(out, aggregated, options)
+}
 
-    match options.order {
-        Some(SortOrder::Count) => entries.sort_unstable_by_key(|e| e.count),
-        Some(SortOrder::TotalTime) => entries.sort_unstable_by_key(|e| e.total_query_time),
-        Some(SortOrder::MaxTime) => entries.sort_unstable_by_key(|e| e.max_query_time),
-        Some(SortOrder::AvgTime) => entries.sort_unstable_by_key(|e| e.avg_query_time),
-        None => (),
-    };
+/// Computes the aggregation key for a query from its text alone, without parsing it
+fn textual_key(query: &str, options: &Opt) -> String {
+    let key = if options.normalize_numbers { normalize_literals(query) } else { query.to_string() };
+    if options.merge_case_insensitive_keywords {
+        fold_keyword_case(&key)
+    } else {
+        key
+    }
+}
+
+fn render_normalized(
+    out: &mut impl Write,
+    entries: Vec<LogEntry>,
+    options: &Opt,
+    logger: &mut Logger<impl Write>,
+    benchmark: &mut Benchmark,
+) -> Vec<AggregateLogEntry> {
+    let cache = NormalizeCache::new(options.normalize_options());
+    let normalized: Vec<NormalizedLogEntry> = benchmark.time(Phase::Normalization, || {
+        entries.into_par_iter().map(|entry| cache.normalize(entry)).collect()
+    });
+
+    if options.debug_parse || logger.enabled(3) {
+        for (query, error) in parse_errors(&normalized) {
+            logger.info(0, format!("Unparseable statement: {}\n  {}", query, error));
+        }
+    }
+    if options.dump_ast {
+        for entry in &normalized {
+            if let Some(ast) = &entry.ast {
+                logger.info(0, format!("Syntax tree of {}:\n{}", entry.entry.query, ast));
+            }
+        }
+    }
+
+    let aggregated = benchmark
+        .time(Phase::Aggregation, || aggregate_normalized(normalized, options.aggregate_options()));
+    if options.inventory {
+        print_inventory(out, aggregated, options);
+        Vec::new()
+    } else {
+        print_aggregated(out, aggregated, options)
+    }
+}
+
+/// Lists all records by count, or only the first ones if a limit is given explicitly
+fn print_inventory(
+    out: &mut impl Write,
+    entries: ahash::HashMap<String, AggregateLogEntry>,
+    options: &Opt,
+) {
+    let mut entries: Vec<AggregateLogEntry> = entries.into_values().collect();
+    sort_aggregated(&mut entries, Some(SortOrder::Count), None);
+    let top: Vec<&AggregateLogEntry> =
+        entries.iter().rev().take(options.limit.map_or(usize::MAX, |_| options.limit())).collect();
+    write_inventory(out, &top);
+}
+
+/// Lists regressions and improvements first, followed by new and disappeared queries
+fn print_comparisons(out: &mut impl Write, comparisons: Vec<Comparison>, options: &Opt) {
+    if options.format() == OutputFormat::Ndjson {
+        comparisons
+            .iter()
+            .filter(|c| c.change != Change::Unchanged)
+            .take(options.limit())
+            .for_each(|c| write_json(out, c, options.pretty));
+        return;
+    }
+
+    let sections = [
+        (Change::Regression, "Regressions"),
+        (Change::Improvement, "Improvements"),
+        (Change::New, "New queries"),
+        (Change::Disappeared, "Disappeared queries"),
+    ];
+    for (change, title) in sections {
+        let matching: Vec<&Comparison> =
+            comparisons.iter().filter(|c| c.change == change).take(options.limit()).collect();
+        writeln!(out, "{}: {}", title, matching.len()).unwrap();
+        for (i, comparison) in matching.iter().enumerate() {
+            writeln!(out, "#{}: {}", i + 1, comparison).unwrap();
+        }
+    }
+}
+
+fn render_index_suggestions(out: &mut impl Write, entries: Vec<LogEntry>, options: &Opt) {
+    let cache = NormalizeCache::new(options.normalize_options());
+    let normalized: Vec<NormalizedLogEntry> =
+        entries.into_par_iter().map(|entry| cache.normalize(entry)).collect();
+    let suggestions = suggest_indexes(normalized);
+
+    if options.format() == OutputFormat::Ndjson {
+        suggestions.iter().take(options.limit()).for_each(|s| write_json(out, s, options.pretty));
+        return;
+    }
+    writeln!(
+        out,
+        "Index candidates (heuristic, based on the WHERE clause only; verify with EXPLAIN):"
+    )
+    .unwrap();
+    suggestions.iter().enumerate().take(options.limit()).for_each(|(i, suggestion)| {
+        writeln!(out, "#{}: {}", i + 1, suggestion).unwrap();
+    });
+}
 
-    let mut stdout = io::stdout().lock();
+fn render_grouped(
+    out: &mut impl Write,
+    entries: Vec<LogEntry>,
+    group_by: &[GroupBy],
+    options: &Opt,
+    benchmark: &mut Benchmark,
+) -> Vec<AggregateLogEntry> {
+    let cache = NormalizeCache::new(options.normalize_options());
+    let aggregated = benchmark.time(Phase::Aggregation, || {
+        aggregate_grouped(entries, group_by, &cache, options.aggregate_options())
+    });
+    print_aggregated(out, aggregated, options)
+}
 
-    entries.iter().rev().enumerate().take(options.limit).for_each(|(i, entry)| {
+/// Returns the displayed records, highest ranked first
+fn print_aggregated(
+    out: &mut impl Write,
+    mut entries: ahash::HashMap<String, AggregateLogEntry>,
+    options: &Opt,
+) -> Vec<AggregateLogEntry> {
+    if let Some(path) = &options.merge_report {
+        let report = File::open(path).expect("Unable to read from report file");
+        let previous = read_report(report).unwrap_or_else(|error| panic!("{}", error));
+        merge_aggregated(&mut entries, previous);
+    }
+    if let Some(path) = &options.new_since {
+        let report = File::open(path).expect("Unable to read from baseline report");
+        let baseline = read_report(report).unwrap_or_else(|error| panic!("{}", error));
+        retain_new(&mut entries, &baseline);
+    }
+
+    let mut entries = entries.into_values().collect::<Vec<AggregateLogEntry>>();
+
+    let min_percentiles = [
+        (SortOrder::P50Time, options.min_p50),
+        (SortOrder::P90Time, options.min_p90),
+        (SortOrder::P95Time, options.min_p95),
+        (SortOrder::P99Time, options.min_p99),
+    ];
+    for (key, min_seconds) in min_percentiles {
+        if let Some(min_seconds) = min_seconds {
+            retain_min_time(&mut entries, key, min_seconds);
+        }
+    }
+
+    sort_aggregated_with(
+        &mut entries,
+        options.order(),
+        options.sort_secondary,
+        options.impact_weight,
+    );
+    let matching = entries.len();
+    entries.reverse();
+    entries.truncate(options.limit());
+    let top: Vec<&AggregateLogEntry> = entries.iter().collect();
+
+    if let Some(path) = &options.emit_repro {
+        let mut file = File::create(path).expect("Unable to write repro script");
+        write_repro_script(&mut file, &top);
+    }
+
+    match options.format() {
+        OutputFormat::Prometheus => write!(out, "{}", prometheus::exposition(&top)).unwrap(),
+        OutputFormat::Ndjson => top.iter().for_each(|e| write_json(out, e, options.pretty)),
+        OutputFormat::Influx => top.iter().for_each(|e| {
+            writeln!(out, "{}", influx::aggregate_line(e)).unwrap();
+        }),
+        OutputFormat::SqlComments => write_annotated_script(out, &top),
+        OutputFormat::Text => print_records(out, &top, matching, options),
+    }
+    entries
+}
+
+/// Writes the records in the human-readable text format
+fn print_records(
+    out: &mut impl Write,
+    entries: &[&AggregateLogEntry],
+    matching: usize,
+    options: &Opt,
+) {
+    if let Some(path) = &options.new_since {
+        writeln!(out, "Queries that newly appeared since {}: {}", path, matching).unwrap();
+    }
+    entries.iter().enumerate().for_each(|(i, entry)| {
         writeln!(
-            stdout,
-            "#{}: count {}, total: {:.3} s, avg {:.3} s, max {:.3} s",
+            out,
+            "#{}: count {}, total: {:.3} s, avg {:.3} s, max {:.3} s, stddev {:.3} s",
             i + 1,
-            entry.count,
+            format_number(entry.count, options),
             entry.total_query_time as f64 / 1_000_000.0,
             entry.avg_query_time as f64 / 1_000_000.0,
             entry.max_query_time as f64 / 1_000_000.0,
+            entry.stddev_query_time as f64 / 1_000_000.0,
         )
         .unwrap();
-        writeln!(stdout, "{}", entry.query).unwrap();
+        writeln!(
+            out,
+            "Percentiles: p50 {:.3} s, p90 {:.3} s, p95 {:.3} s, p99 {:.3} s",
+            entry.p50_query_time as f64 / 1_000_000.0,
+            entry.p90_query_time as f64 / 1_000_000.0,
+            entry.p95_query_time as f64 / 1_000_000.0,
+            entry.p99_query_time as f64 / 1_000_000.0,
+        )
+        .unwrap();
+        writeln!(out, "{}", displayed_query(&entry.query, options)).unwrap();
+        if !entry.sample_values.is_empty() {
+            writeln!(out, "Sample values: {}", format_sample_values(&entry.sample_values)).unwrap();
+        }
+        for (i, example) in entry.examples.iter().enumerate() {
+            let seconds = example.query_time as f64 / 1_000_000.0;
+            writeln!(out, "Example {} ({:.3} s): {}", i + 1, seconds, example.query).unwrap();
+        }
+        if let Some(max_rows_raw_query) = &entry.max_rows_raw_query {
+            writeln!(
+                out,
+                "Most rows examined: {}\n{}",
+                format_number(entry.max_rows_examined, options),
+                max_rows_raw_query
+            )
+            .unwrap();
+        }
     });
 }
 
-fn print_version() {
-    println!(
-        "{} v{} by {} - {}",
-        env!("CARGO_PKG_NAME"),
-        env!("CARGO_PKG_VERSION"),
-        env!("CARGO_PKG_AUTHORS"),
-        env!("CARGO_PKG_HOMEPAGE"),
-    );
+/// Shortens runs of placeholders in a normalized query for the text output if requested
+fn displayed_query<'a>(query: &'a str, options: &Opt) -> Cow<'a, str> {
+    if options.merge_placeholder_runs {
+        merge_placeholder_runs(query)
+    } else {
+        Cow::Borrowed(query)
+    }
+}
+
+/// Lists the values by the position of the placeholder they belong to, e.g. "[1] 'foo', [2] 42"
+fn format_sample_values(values: &[String]) -> String {
+    let positional: Vec<String> =
+        values.iter().enumerate().map(|(i, value)| format!("[{}] {}", i + 1, value)).collect();
+    positional.join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+    use indoc::indoc;
+    use myslowlog::aggregate::AggregateOptions;
+
+    use super::*;
+
+    fn write_temp_log(name: &str, contents: &str) -> String {
+        let path = std::env::temp_dir().join(format!("myslowlog-{}-{}", std::process::id(), name));
+        std::fs::write(&path, contents).unwrap();
+        path.to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn it_recomputes_the_aggregates_on_refresh() {
+        let entry = |second: u32, query_time: &str| {
+            format!(
+                "# Time: 2021-05-11T07:00:0{}.000000Z\n\
+                 # User@Host: foo[foo] @  [127.0.0.1]  Id: 1\n\
+                 # Query_time: {}  Lock_time: 0.000000 Rows_sent: 1  Rows_examined: 1\n\
+                 SELECT * FROM foo WHERE id = {};\n",
+                second, query_time, second
+            )
+        };
+        let mut contents = entry(0, "1.000000") + &entry(1, "2.000000");
+        let log = write_temp_log("watch.log", &contents);
+        let opt = Opt::parse_from(["test", "-i", log.as_str(), "--watch", "1s", "-n"]);
+        let mut logger = Logger::new(0, io::sink());
+
+        let report = String::from_utf8(refresh(&opt, &[], &mut logger)).unwrap();
+        assert!(report.starts_with("#1: count 2, total: 3.000 s"), "{}", report);
+
+        contents += &entry(2, "6.000000");
+        std::fs::write(&log, contents).unwrap();
+        let report = String::from_utf8(refresh(&opt, &[], &mut logger)).unwrap();
+        assert!(report.starts_with("#1: count 3, total: 9.000 s"), "{}", report);
+        std::fs::remove_file(log).unwrap();
+    }
+
+    #[test]
+    fn it_writes_a_separate_report_per_user() {
+        let entry = |user: &str, query: &str| LogEntry {
+            user: user.to_string(),
+            query: query.to_string(),
+            ..Default::default()
+        };
+        let entries = vec![
+            entry("alice", "SELECT * FROM orders;"),
+            entry("../bob", "SELECT * FROM invoices;"),
+            entry("alice", "SELECT * FROM customers;"),
+        ];
+        let outdir = std::env::temp_dir().join(format!("myslowlog-{}-split", std::process::id()));
+        let opt = Opt::parse_from(["test", "--split-by", "user", "--outdir", "unused"]);
+        let mut logger = Logger::new(0, io::sink());
+
+        write_split_reports(
+            &outdir,
+            entries,
+            SplitBy::User,
+            &opt,
+            &mut logger,
+            &mut Benchmark::new(),
+        )
+        .unwrap();
+
+        let mut files: Vec<String> = fs::read_dir(&outdir)
+            .unwrap()
+            .map(|file| file.unwrap().file_name().into_string().unwrap())
+            .collect();
+        files.sort();
+        assert_eq!(vec!["___bob.txt", "alice.txt"], files);
+        let alice = fs::read_to_string(outdir.join("alice.txt")).unwrap();
+        assert!(alice.contains("SELECT * FROM orders;"));
+        assert!(alice.contains("SELECT * FROM customers;"));
+        assert!(!alice.contains("invoices"));
+        let bob = fs::read_to_string(outdir.join("___bob.txt")).unwrap();
+        assert!(bob.contains("SELECT * FROM invoices;"));
+        assert!(!bob.contains("orders"));
+        fs::remove_dir_all(outdir).unwrap();
+    }
+
+    #[test]
+    fn it_writes_the_summary_to_a_sidecar_file() {
+        let entry = |query: &str, query_time_ms: i64| LogEntry {
+            query: query.to_string(),
+            query_time: time::Duration::milliseconds(query_time_ms),
+            ..Default::default()
+        };
+        let entries =
+            vec![entry("SELECT 1;", 1000), entry("SELECT 2;", 2500), entry("SELECT 1;", 500)];
+        let path =
+            std::env::temp_dir().join(format!("myslowlog-{}-summary.json", std::process::id()));
+
+        write_summary_json(&path, &summarize(&entries)).unwrap();
+
+        let sidecar: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(3, sidecar["total_queries"]);
+        assert_eq!(2, sidecar["unique_queries"]);
+        assert_eq!(4_000_000, sidecar["total_query_time"]);
+        assert_eq!(2_500_000, sidecar["max_query_time"]);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn it_reports_stats_depending_on_verbosity() {
+        let parsed = ParsedLog { entries: vec![LogEntry::default()], ..Default::default() };
+
+        let mut logger = Logger::new(0, Vec::new());
+        report_parse_results(&parsed, &mut logger);
+        assert!(logger.into_inner().is_empty());
+
+        let mut logger = Logger::new(1, Vec::new());
+        report_parse_results(&parsed, &mut logger);
+        let output = String::from_utf8(logger.into_inner()).unwrap();
+        assert_eq!("Parsed 1 entries, skipped 0 malformed entries\n", output);
+    }
+
+    #[test]
+    fn it_merges_input_files_chronologically() {
+        let first = write_temp_log(
+            "first.log",
+            indoc!(
+                "
+                # Time: 2021-05-11T07:00:00.000000Z
+                # User@Host: foo[foo] @  [127.0.0.1]  Id: 1
+                # Query_time: 1.000000  Lock_time: 0.000000 Rows_sent: 1  Rows_examined: 1
+                SELECT 1;
+                # Time: 2021-05-11T09:00:00.000000Z
+                # User@Host: foo[foo] @  [127.0.0.1]  Id: 1
+                # Query_time: 1.000000  Lock_time: 0.000000 Rows_sent: 1  Rows_examined: 1
+                SELECT 3;
+            "
+            ),
+        );
+        let second = write_temp_log(
+            "second.log",
+            indoc!(
+                "
+                # Time: 2021-05-11T08:00:00.000000Z
+                # User@Host: foo[foo] @  [127.0.0.1]  Id: 1
+                # Query_time: 1.000000  Lock_time: 0.000000 Rows_sent: 1  Rows_examined: 1
+                SELECT 2;
+                # Time: 2021-05-11T10:00:00.000000Z
+                # User@Host: foo[foo] @  [127.0.0.1]  Id: 1
+                # Query_time: 1.000000  Lock_time: 0.000000 Rows_sent: 1  Rows_examined: 1
+                SELECT 4;
+            "
+            ),
+        );
+
+        let opt = Opt::parse_from([
+            "test",
+            "-i",
+            first.as_str(),
+            "-i",
+            second.as_str(),
+            "--sort-input-by-time",
+        ]);
+        let queries: Vec<String> = read_logs(&opt, &mut Logger::new(0, io::sink()))
+            .entries
+            .into_iter()
+            .map(|e| e.query)
+            .collect();
+        assert_eq!(queries, vec!["SELECT 1;", "SELECT 2;", "SELECT 3;", "SELECT 4;"]);
+
+        let opt = Opt::parse_from(["test", "-i", first.as_str(), "-i", second.as_str()]);
+        let queries: Vec<String> = read_logs(&opt, &mut Logger::new(0, io::sink()))
+            .entries
+            .into_iter()
+            .map(|e| e.query)
+            .collect();
+        assert_eq!(queries, vec!["SELECT 1;", "SELECT 3;", "SELECT 2;", "SELECT 4;"]);
+        std::fs::remove_file(first).unwrap();
+        std::fs::remove_file(second).unwrap();
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn it_reads_the_last_entries_from_a_pipe() {
+        let path = std::env::temp_dir().join(format!("myslowlog-{}-fifo", std::process::id()));
+        let status = process::Command::new("mkfifo").arg(&path).status().unwrap();
+        assert!(status.success());
+        let writer = {
+            let path = path.clone();
+            thread::spawn(move || {
+                let entries = (1..=3).map(|i| {
+                    format!(
+                        "# Time: 2021-05-11T07:00:0{}.000000Z\n\
+                         # User@Host: foo[foo] @  [127.0.0.1]  Id: 1\n\
+                         # Query_time: 1.000000  Lock_time: 0.000000 Rows_sent: 1  Rows_examined: 1\n\
+                         SELECT {};\n",
+                        i, i
+                    )
+                });
+                fs::write(path, entries.collect::<String>()).unwrap();
+            })
+        };
+
+        let opt = Opt::parse_from(["test", "-i", path.to_str().unwrap(), "--last", "2"]);
+        let queries: Vec<String> = read_logs(&opt, &mut Logger::new(0, io::sink()))
+            .entries
+            .into_iter()
+            .map(|e| e.query)
+            .collect();
+        writer.join().unwrap();
+        assert_eq!(queries, vec!["SELECT 2;", "SELECT 3;"]);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn it_caps_the_number_of_entries_per_user() {
+        let entries: Vec<LogEntry> = ["foo", "foo", "bar", "foo", "baz", "bar", "bar", "foo"]
+            .into_iter()
+            .map(|user| LogEntry { user: user.to_string(), ..Default::default() })
+            .collect();
+
+        let users: Vec<&str> =
+            cap_per_user(entries.iter(), Some(2)).map(|e| e.user.as_str()).collect();
+        assert_eq!(vec!["foo", "foo", "bar", "baz", "bar"], users);
+        assert_eq!(entries.len(), cap_per_user(entries.iter(), None).count());
+    }
+
+    #[test]
+    fn it_displays_normalized_entries_without_aggregating_them() {
+        let entries: Vec<LogEntry> = [1, 2, 1]
+            .into_iter()
+            .map(|id| LogEntry {
+                query: format!("SELECT * FROM foo WHERE id = {};", id),
+                ..Default::default()
+            })
+            .collect();
+
+        let options = Opt::parse_from(["myslowlog", "--normalize", "--no-aggregate"]);
+        assert!(!options.aggregate());
+        let mut out = Vec::new();
+        render_individual(&mut out, entries, &options);
+        let output = String::from_utf8(out).unwrap();
+        let queries: Vec<&str> =
+            output.lines().filter(|line| !line.starts_with('#')).map(str::trim).collect();
+        assert_eq!(vec!["SELECT * FROM foo WHERE id = ?;"; 3], queries);
+    }
+
+    #[test]
+    fn it_shows_the_slowest_distinct_examples_of_each_record() {
+        let entries: Vec<LogEntry> = [(1, 100), (2, 500), (3, 300), (2, 200), (4, 400), (1, 50)]
+            .into_iter()
+            .map(|(id, query_time_ms)| LogEntry {
+                query: format!("SELECT * FROM foo WHERE id = {};", id),
+                query_time: time::Duration::milliseconds(query_time_ms),
+                ..Default::default()
+            })
+            .collect();
+
+        let options = Opt::parse_from(["myslowlog", "--normalize", "--examples", "3"]);
+        let cache = NormalizeCache::new(options.normalize_options());
+        let normalized = entries.into_iter().map(|entry| cache.normalize(entry)).collect();
+        let mut out = Vec::new();
+        print_aggregated(
+            &mut out,
+            aggregate_normalized(normalized, options.aggregate_options()),
+            &options,
+        );
+        let output = String::from_utf8(out).unwrap();
+        let examples: Vec<&str> =
+            output.lines().filter(|line| line.starts_with("Example ")).collect();
+        assert_eq!(
+            vec![
+                "Example 1 (0.500 s): SELECT * FROM foo WHERE id = 2;",
+                "Example 2 (0.400 s): SELECT * FROM foo WHERE id = 4;",
+                "Example 3 (0.300 s): SELECT * FROM foo WHERE id = 3;",
+            ],
+            examples
+        );
+    }
+
+    #[test]
+    fn it_redacts_literals_of_displayed_queries() {
+        let entries = vec![LogEntry {
+            query: "SELECT * FROM users WHERE email = 'jane@example.com' AND id > 5;".to_string(),
+            ..Default::default()
+        }];
+
+        let options = Opt::parse_from(["myslowlog", "--redact"]);
+        let mut out = Vec::new();
+        render_individual(&mut out, entries, &options);
+        let output = String::from_utf8(out).unwrap();
+        assert!(!output.contains("jane@example.com"));
+        assert!(output.contains("SELECT * FROM users WHERE email = ? AND id > ?;"));
+    }
+
+    #[test]
+    fn it_removes_comments_of_redacted_queries() {
+        let entries = vec![LogEntry {
+            query: "/* session for jane@example.com */ SELECT * FROM users WHERE id = 5; \
+                    -- token 3f9a1c"
+                .to_string(),
+            ..Default::default()
+        }];
+
+        let options = Opt::parse_from(["myslowlog", "--redact", "--format", "ndjson"]);
+        let mut out = Vec::new();
+        render_individual(&mut out, entries, &options);
+        let output = String::from_utf8(out).unwrap();
+        assert!(!output.contains("jane@example.com"));
+        assert!(!output.contains("3f9a1c"));
+        assert!(output.contains(r#""query":"SELECT * FROM users WHERE id = ?;""#));
+    }
+
+    #[test]
+    fn it_skips_unreadable_files_if_asked_to() {
+        let readable = write_temp_log(
+            "readable.log",
+            indoc!(
+                "
+                # Time: 2021-05-11T07:00:00.000000Z
+                # User@Host: foo[foo] @  [127.0.0.1]  Id: 1
+                # Query_time: 1.000000  Lock_time: 0.000000 Rows_sent: 1  Rows_examined: 1
+                SELECT 1;
+            "
+            ),
+        );
+        let missing = std::env::temp_dir()
+            .join(format!("myslowlog-{}-missing.log", std::process::id()))
+            .to_str()
+            .unwrap()
+            .to_string();
+
+        let opt = Opt::parse_from([
+            "test",
+            "-i",
+            missing.as_str(),
+            "-i",
+            readable.as_str(),
+            "--skip-unreadable",
+        ]);
+        let mut logger = Logger::new(0, Vec::new());
+        let parsed = read_logs(&opt, &mut logger);
+        assert_eq!(1, parsed.unreadable_files);
+        assert_eq!(
+            vec!["SELECT 1;"],
+            parsed.entries.iter().map(|e| e.query.as_str()).collect::<Vec<_>>()
+        );
+        let output = String::from_utf8(logger.into_inner()).unwrap();
+        assert!(output.starts_with("Warning: Skipping unreadable file"));
+        std::fs::remove_file(readable).unwrap();
+    }
+
+    #[test]
+    fn it_fails_on_malformed_entries_only_in_strict_mode() {
+        let log = write_temp_log(
+            "malformed.log",
+            indoc!(
+                "
+                # Time: 2021-05-11T07:00:00.000000Z
+                # User@Host: this line is broken
+                # Query_time: 1.000000  Lock_time: 0.000000 Rows_sent: 1  Rows_examined: 1
+                SELECT 1;
+                # Time: 2021-05-11T08:00:00.000000Z
+                # User@Host: foo[foo] @  [127.0.0.1]  Id: 1
+                # Query_time: 1.000000  Lock_time: 0.000000 Rows_sent: 1  Rows_examined: 1
+                SELECT 2;
+            "
+            ),
+        );
+
+        let opt = Opt::parse_from(["test", "-i", log.as_str()]);
+        let parsed = read_logs(&opt, &mut Logger::new(0, io::sink()));
+        assert_eq!(1, parsed.entries.len());
+        assert_eq!(0, exit_status(&parsed, &opt));
+
+        let opt = Opt::parse_from(["test", "-i", log.as_str(), "--strict"]);
+        let parsed = read_logs(&opt, &mut Logger::new(0, io::sink()));
+        assert!(parsed.entries.is_empty());
+        assert_eq!(1, exit_status(&parsed, &opt));
+        std::fs::remove_file(log).unwrap();
+    }
+
+    #[test]
+    fn it_ranks_hosts_by_total_time() {
+        let entry = |host: &str, query_time_ms: i64| LogEntry {
+            host: host.to_string(),
+            query_time: time::Duration::milliseconds(query_time_ms),
+            ..Default::default()
+        };
+        let entries = vec![
+            entry("10.0.0.1", 1000),
+            entry("10.0.0.2", 3000),
+            entry("10.0.0.1", 1500),
+            entry("10.0.0.1", 1000),
+        ];
+
+        let opt = Opt::parse_from(["test", "--group-by", "host"]);
+        let mut groups: Vec<AggregateLogEntry> = aggregate_grouped(
+            entries,
+            &[GroupBy::Host],
+            &NormalizeCache::default(),
+            AggregateOptions::default(),
+        )
+        .into_values()
+        .collect();
+        sort_aggregated(&mut groups, opt.order(), opt.sort_secondary);
+
+        let ranked: Vec<(&str, i64)> =
+            groups.iter().rev().map(|group| (group.query.as_str(), group.count)).collect();
+        assert_eq!(vec![("10.0.0.1", 3), ("10.0.0.2", 1)], ranked);
+    }
 }
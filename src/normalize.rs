@@ -1,19 +1,38 @@
+use std::borrow::Cow;
+use std::cell::RefCell;
 use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::RwLock;
 
+use ahash::{HashMap, HashSet, HashSetExt};
+
+use once_cell::sync::OnceCell;
+use regex::Regex;
+use serde::Serialize;
 use sqlparser::ast::{
-    Assignment, Distinct, Expr, GroupByExpr, Join, JoinConstraint, JoinOperator, Offset,
-    OrderByExpr, Query, Select, SelectItem, SetExpr, Statement, TableFactor, TableWithJoins, Value,
-    Values,
+    Assignment, Distinct, Expr, Function, FunctionArg, FunctionArgExpr, GroupByExpr, Ident, Join,
+    JoinConstraint, JoinOperator, Offset, OrderByExpr, Query, Select, SelectItem, SetExpr,
+    Statement, TableFactor, TableWithJoins, Value, Values,
 };
-use sqlparser::dialect::MySqlDialect;
-use sqlparser::parser::Parser;
+use sqlparser::keywords::ALL_KEYWORDS;
 
 use crate::log_parser::LogEntry;
+use crate::visit::{parse, try_parse};
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize)]
 pub struct NormalizedLogEntry {
+    #[serde(flatten)]
     pub entry: LogEntry,
     pub normalized_query: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parse_error: Option<String>,
+    /// The literal values replaced by placeholders, in order, if requested
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub sample_values: Vec<String>,
+    /// The debug representation of the parsed statements, if requested.
+    /// Only set when the query was actually parsed, not when it was taken from a cache.
+    #[serde(skip)]
+    pub ast: Option<String>,
 }
 
 impl fmt::Display for NormalizedLogEntry {
@@ -31,54 +50,519 @@ impl fmt::Display for NormalizedLogEntry {
     }
 }
 
-pub fn normalize(entry: LogEntry) -> NormalizedLogEntry {
-    let dialect = MySqlDialect {};
-    let parser_result = Parser::parse_sql(&dialect, &entry.query);
-    let normalized_query = match parser_result {
-        Ok(ast) => normalize_ast(&ast),
-        Err(err) => format!("Unparseable statement: {} ({})", &entry.query, &err),
+/// Controls which parts of a query are normalized
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct NormalizeOptions {
+    /// Reduce `IN` lists to a single placeholder
+    pub collapse_in_lists: bool,
+    /// Replace the `LIMIT` value with a placeholder
+    pub normalize_limit: bool,
+    /// Convert table and column names to lower case
+    pub fold_identifiers: bool,
+    /// Drop comments; if disabled, leading comments are kept in front of the normalized query
+    pub strip_comments: bool,
+    /// Remove index hints such as `USE INDEX (idx)` before parsing
+    pub strip_hints: bool,
+    /// Record the literal values that are replaced by placeholders
+    pub capture_sample_values: bool,
+    /// Keep the debug representation of the parsed statements
+    pub dump_ast: bool,
+}
+
+impl Default for NormalizeOptions {
+    fn default() -> Self {
+        NormalizeOptions {
+            collapse_in_lists: true,
+            normalize_limit: false,
+            fold_identifiers: false,
+            strip_comments: true,
+            strip_hints: false,
+            capture_sample_values: false,
+            dump_ast: false,
+        }
+    }
+}
+
+/// The state of normalizing a single query, which is passed to all of the normalization functions
+struct Normalizer<'a> {
+    options: &'a NormalizeOptions,
+    /// The literal values replaced so far, in order, if they are to be captured
+    sample_values: Option<RefCell<Vec<String>>>,
+}
+
+impl<'a> Normalizer<'a> {
+    fn new(options: &'a NormalizeOptions) -> Self {
+        let sample_values = options.capture_sample_values.then(RefCell::default);
+        Normalizer { options, sample_values }
+    }
+
+    /// Runs a normalization that yields a single placeholder for several values, such as a
+    /// collapsed IN list, and captures all of the values as a single comma-separated one
+    fn capture_as_one<T>(&self, normalize: impl FnOnce() -> T) -> T {
+        let Some(values) = &self.sample_values else {
+            return normalize();
+        };
+        let start = values.borrow().len();
+        let result = normalize();
+        let mut values = values.borrow_mut();
+        if values.len() > start {
+            let joined = values.split_off(start).join(", ");
+            values.push(joined);
+        }
+        result
+    }
+}
+
+pub fn normalize(entry: LogEntry, options: &NormalizeOptions) -> NormalizedLogEntry {
+    let normalizer = Normalizer::new(options);
+    let query = if options.strip_hints {
+        strip_index_hints(&entry.query)
+    } else {
+        Cow::Borrowed(entry.query.as_str())
+    };
+    let parser_result = try_parse(&query);
+    let ast = match &parser_result {
+        Ok(ast) if options.dump_ast => Some(format!("{:#?}", ast)),
+        _ => None,
+    };
+    let (normalized_query, parse_error) = match parser_result {
+        Ok(ast) if !options.strip_comments => {
+            (leading_comments(&entry.query).to_string() + &normalize_ast(&ast, &normalizer), None)
+        }
+        Ok(ast) => (normalize_ast(&ast, &normalizer), None),
+        Err(err) => (format!("Unparseable statement: {} ({})", &entry.query, &err), Some(err)),
     };
 
-    NormalizedLogEntry { entry, normalized_query: normalized_query.clone() }
+    let sample_values = normalizer.sample_values.map(RefCell::into_inner).unwrap_or_default();
+    NormalizedLogEntry { entry, normalized_query, parse_error, sample_values, ast }
+}
+
+/// A short stable identifier for a (normalized) query, computed with 64-bit FNV-1a
+pub fn fingerprint(query: &str) -> String {
+    let hash = query.bytes().fold(0xcbf29ce484222325_u64, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    });
+    format!("{:016x}", hash)
+}
+
+/// Removes MySQL index hints (`USE`, `FORCE` or `IGNORE` followed by `INDEX` or `KEY`) from a
+/// query. The parser does not support them, so they are stripped from the text before parsing,
+/// leaving string literals and comments alone. Optimizer hints need no special treatment: they
+/// are comments, which the parser drops anyway.
+fn strip_index_hints(query: &str) -> Cow<'_, str> {
+    static REGEX: OnceCell<Regex> = OnceCell::new();
+    let regex = REGEX.get_or_init(|| {
+        Regex::new(
+            r"(?i)\s+(USE|FORCE|IGNORE)\s+(INDEX|KEY)(\s+FOR\s+(JOIN|ORDER\s+BY|GROUP\s+BY))?\s*\([^)]*\)",
+        )
+        .unwrap()
+    });
+    if !regex.is_match(query) {
+        return Cow::Borrowed(query);
+    }
+    let stripped = split_query(query)
+        .into_iter()
+        .map(|segment| match segment {
+            Segment::Code(code) => regex.replace_all(code, ""),
+            Segment::Literal(text) | Segment::Comment(text) => Cow::Borrowed(text),
+        })
+        .collect();
+    Cow::Owned(stripped)
+}
+
+/// Shortens runs of three or more consecutive placeholders in a normalized query to `?, ...`,
+/// e.g. the rows of a batch insert. This is only meant for display; since it hides the number of
+/// placeholders, queries are still aggregated by their complete normalized text.
+pub fn merge_placeholder_runs(query: &str) -> Cow<'_, str> {
+    static REGEX: OnceCell<Regex> = OnceCell::new();
+    let regex = REGEX.get_or_init(|| Regex::new(r"\?(\s*,\s*\?){2,}").unwrap());
+    regex.replace_all(query, "?, ...")
+}
+
+/// Returns the block comments preceding the actual statement, if any
+fn leading_comments(query: &str) -> &str {
+    let mut rest = query.trim_start();
+    while rest.starts_with("/*") {
+        match rest.find("*/") {
+            Some(end) => rest = rest[end + 2..].trim_start(),
+            None => break,
+        }
+    }
+    query[..query.len() - rest.len()].trim()
+}
+
+/// Returns each distinct unparseable raw query together with the parser's error message,
+/// in the order of their first appearance
+pub fn parse_errors(entries: &[NormalizedLogEntry]) -> Vec<(&str, &str)> {
+    let mut seen = HashSet::new();
+    entries
+        .iter()
+        .filter_map(|e| e.parse_error.as_deref().map(|error| (e.entry.query.as_str(), error)))
+        .filter(|(query, _)| seen.insert(*query))
+        .collect()
+}
+
+#[derive(Clone)]
+struct CachedQuery {
+    normalized_query: String,
+    parse_error: Option<String>,
+    sample_values: Vec<String>,
+}
+
+/// The number of raw queries the cache holds at most. Logs with mostly distinct queries would
+/// otherwise keep every one of them in memory.
+const CACHE_CAPACITY: usize = 10_000;
+
+/// Caches normalized queries by their raw query string, so that each distinct raw query only
+/// needs to be parsed once. Two threads encountering the same uncached query at the same time may
+/// both parse it, which is harmless. When the cache is full, it is emptied and starts over, which
+/// keeps the queries that recur throughout the log cached most of the time.
+pub struct NormalizeCache {
+    queries: RwLock<HashMap<String, CachedQuery>>,
+    capacity: usize,
+    misses: AtomicUsize,
+    options: NormalizeOptions,
+}
+
+impl Default for NormalizeCache {
+    fn default() -> Self {
+        NormalizeCache {
+            queries: RwLock::default(),
+            capacity: CACHE_CAPACITY,
+            misses: AtomicUsize::default(),
+            options: NormalizeOptions::default(),
+        }
+    }
+}
+
+impl NormalizeCache {
+    pub fn new(options: NormalizeOptions) -> Self {
+        NormalizeCache { options, ..Default::default() }
+    }
+
+    pub fn normalize(&self, entry: LogEntry) -> NormalizedLogEntry {
+        let cached = self.queries.read().unwrap().get(&entry.query).cloned();
+        if let Some(CachedQuery { normalized_query, parse_error, sample_values }) = cached {
+            return NormalizedLogEntry {
+                entry,
+                normalized_query,
+                parse_error,
+                sample_values,
+                ast: None,
+            };
+        }
+
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        let normalized = normalize(entry, &self.options);
+        let mut queries = self.queries.write().unwrap();
+        if queries.len() >= self.capacity {
+            queries.clear();
+        }
+        queries.insert(
+            normalized.entry.query.clone(),
+            CachedQuery {
+                normalized_query: normalized.normalized_query.clone(),
+                parse_error: normalized.parse_error.clone(),
+                sample_values: normalized.sample_values.clone(),
+            },
+        );
+        normalized
+    }
+
+    /// The number of queries that had to be parsed
+    pub fn misses(&self) -> usize {
+        self.misses.load(Ordering::Relaxed)
+    }
+}
+
+pub const OTHER_SHAPE: &str = "<other>";
+
+/// Describes a query by its statement type and the first table it operates on,
+/// e.g. "SELECT on orders". Unparseable queries and other statement types are lumped
+/// together as `<other>`.
+pub fn query_shape(query: &str) -> String {
+    let shape = parse(query).and_then(|ast| ast.first().and_then(statement_shape));
+    shape.unwrap_or_else(|| OTHER_SHAPE.to_string())
+}
+
+fn statement_shape(stmt: &Statement) -> Option<String> {
+    let (kind, table) = match stmt {
+        Statement::Query(query) => ("SELECT", set_expr_table(&query.body)),
+        Statement::Insert { table_name, .. } => ("INSERT", Some(table_name.to_string())),
+        Statement::Update { table, .. } => ("UPDATE", table_factor_name(&table.relation)),
+        Statement::Delete { from, .. } => {
+            ("DELETE", from.first().and_then(|twj| table_factor_name(&twj.relation)))
+        }
+        _ => return None,
+    };
+    match table {
+        Some(table) => Some(format!("{} on {}", kind, table)),
+        None => Some(kind.to_string()),
+    }
+}
+
+fn set_expr_table(set_expr: &SetExpr) -> Option<String> {
+    match set_expr {
+        SetExpr::Select(select) => {
+            select.from.first().and_then(|twj| table_factor_name(&twj.relation))
+        }
+        SetExpr::Query(query) => set_expr_table(&query.body),
+        SetExpr::SetOperation { left, .. } => set_expr_table(left),
+        _ => None,
+    }
+}
+
+fn table_factor_name(tf: &TableFactor) -> Option<String> {
+    match tf {
+        TableFactor::Table { name, .. } => Some(name.to_string()),
+        TableFactor::Derived { subquery, .. } => set_expr_table(&subquery.body),
+        TableFactor::NestedJoin { table_with_joins, .. } => {
+            table_factor_name(&table_with_joins.relation)
+        }
+        _ => None,
+    }
+}
+
+/// Uppercases all SQL keywords in the query, leaving string literals, quoted identifiers,
+/// qualified names and variables alone. Unlike `normalize`, this works on unparseable queries.
+pub fn fold_keyword_case(query: &str) -> String {
+    let mut result = String::with_capacity(query.len());
+    let mut chars = query.chars().peekable();
+    let mut previous = None;
+
+    while let Some(c) = chars.next() {
+        if c == '\'' || c == '"' || c == '`' {
+            result.push(c);
+            while let Some(next) = chars.next() {
+                result.push(next);
+                if next == '\\' {
+                    if let Some(escaped) = chars.next() {
+                        result.push(escaped);
+                    }
+                } else if next == c {
+                    break;
+                }
+            }
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let mut word = c.to_string();
+            while let Some(&next) = chars.peek() {
+                if !next.is_ascii_alphanumeric() && next != '_' {
+                    break;
+                }
+                word.push(next);
+                chars.next();
+            }
+            let upper = word.to_ascii_uppercase();
+            let is_name = matches!(previous, Some('.') | Some('@'));
+            if !is_name && ALL_KEYWORDS.binary_search(&upper.as_str()).is_ok() {
+                result.push_str(&upper);
+            } else {
+                result.push_str(&word);
+            }
+        } else {
+            result.push(c);
+        }
+        previous = result.chars().last();
+    }
+
+    result
+}
+
+/// A part of a query as seen by the text-based functions that work without parsing it
+#[derive(Debug, PartialEq)]
+enum Segment<'a> {
+    /// Anything outside of string literals and comments, including quoted identifiers
+    Code(&'a str),
+    /// A single- or double-quoted string literal, including the quotes
+    Literal(&'a str),
+    /// A `/* ... */`, `-- ...` or `# ...` comment, excluding the line break after the latter two
+    Comment(&'a str),
+}
+
+/// Splits a query into string literals, comments and the code between them. Unterminated
+/// literals and comments extend to the end of the query.
+fn split_query(query: &str) -> Vec<Segment<'_>> {
+    // all delimiters are ASCII, so every index at which the query is split is a char boundary
+    let bytes = query.as_bytes();
+    let line_end = |start: usize| {
+        bytes[start..].iter().position(|&byte| byte == b'\n').map_or(bytes.len(), |end| start + end)
+    };
+    let mut segments = Vec::new();
+    let mut code_start = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        let (end, literal) = match bytes[i] {
+            quote @ (b'\'' | b'"') => (literal_end(bytes, i + 1, quote), true),
+            b'`' => {
+                i = bytes[i + 1..]
+                    .iter()
+                    .position(|&byte| byte == b'`')
+                    .map_or(bytes.len(), |end| i + end + 2);
+                continue;
+            }
+            b'#' => (line_end(i), false),
+            // MySQL only treats a double dash as a comment if it is followed by whitespace
+            b'-' if bytes.get(i + 1) == Some(&b'-')
+                && bytes.get(i + 2).is_none_or(u8::is_ascii_whitespace) =>
+            {
+                (line_end(i), false)
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'*') => {
+                let end = query[i + 2..].find("*/").map_or(bytes.len(), |end| i + end + 4);
+                (end, false)
+            }
+            _ => {
+                i += 1;
+                continue;
+            }
+        };
+        if code_start < i {
+            segments.push(Segment::Code(&query[code_start..i]));
+        }
+        let text = &query[i..end];
+        segments.push(if literal { Segment::Literal(text) } else { Segment::Comment(text) });
+        i = end;
+        code_start = end;
+    }
+    if code_start < bytes.len() {
+        segments.push(Segment::Code(&query[code_start..]));
+    }
+    segments
+}
+
+/// Returns the index after the quote that ends a string literal starting at `start`
+fn literal_end(bytes: &[u8], start: usize, quote: u8) -> usize {
+    let mut i = start;
+    while i < bytes.len() {
+        if bytes[i] == b'\\' {
+            i += 2;
+        } else if bytes[i] == quote {
+            // a doubled quote is an escaped quote, not the end of the literal
+            if bytes.get(i + 1) != Some(&quote) {
+                return i + 1;
+            }
+            i += 2;
+        } else {
+            i += 1;
+        }
+    }
+    bytes.len()
+}
+
+/// Replaces numeric and string literals in the query with placeholders by scanning its text,
+/// without parsing it. This is much faster than `normalize` and works on any query, but it
+/// neither collapses IN lists nor recognizes literals that are spelled differently (e.g. `TRUE`).
+/// Comments are kept as they are.
+pub fn normalize_literals(query: &str) -> String {
+    let mut result = String::with_capacity(query.len());
+    for segment in split_query(query) {
+        match segment {
+            Segment::Code(code) => replace_numbers(code, &mut result),
+            Segment::Literal(_) => result.push('?'),
+            Segment::Comment(comment) => result.push_str(comment),
+        }
+    }
+    result
+}
+
+/// Like `normalize_literals`, but also removes comments, since they can contain data as well
+pub fn redact_literals(query: &str) -> String {
+    let mut result = String::with_capacity(query.len());
+    for segment in split_query(query) {
+        match segment {
+            Segment::Code(code) => replace_numbers(code, &mut result),
+            Segment::Literal(_) => result.push('?'),
+            // keep the tokens around a block comment apart, e.g. in `SELECT/* x */1`
+            Segment::Comment(_) if !result.is_empty() && !result.ends_with(char::is_whitespace) => {
+                result.push(' ')
+            }
+            Segment::Comment(_) => (),
+        }
+    }
+    result.trim().to_string()
+}
+
+/// Appends code outside of string literals and comments to the result, with its numeric
+/// literals replaced by placeholders
+fn replace_numbers(code: &str, result: &mut String) {
+    let mut chars = code.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '`' {
+            result.push(c);
+            for next in chars.by_ref() {
+                result.push(next);
+                if next == '`' {
+                    break;
+                }
+            }
+        } else if c.is_alphabetic() || c == '_' || c == '$' || c == '@' {
+            // identifiers and keywords may contain digits that are not literals
+            result.push(c);
+            while let Some(&next) = chars.peek() {
+                if !next.is_alphanumeric() && next != '_' && next != '$' {
+                    break;
+                }
+                result.push(next);
+                chars.next();
+            }
+        } else if c.is_ascii_digit() || (c == '.' && chars.peek().is_some_and(char::is_ascii_digit))
+        {
+            // also consumes hexadecimal literals and exponents, e.g. 0xFF or 1.5e-3
+            let mut previous = c;
+            while let Some(&next) = chars.peek() {
+                let is_sign = matches!(next, '+' | '-') && matches!(previous, 'e' | 'E');
+                if !next.is_ascii_alphanumeric() && next != '.' && !is_sign {
+                    break;
+                }
+                previous = next;
+                chars.next();
+            }
+            result.push('?');
+        } else {
+            result.push(c);
+        }
+    }
 }
 
-fn normalize_ast(ast: &[Statement]) -> String {
+fn normalize_ast(ast: &[Statement], normalizer: &Normalizer) -> String {
     ast.iter()
-        .map(normalize_stmt)
+        .map(|item| normalize_stmt(item, normalizer))
         .map(|stmt| format!("{};", stmt))
         .fold(String::new(), |acc, item| acc + " " + &item)
 }
 
-fn normalize_stmt(stmt: &Statement) -> Statement {
+fn normalize_stmt(stmt: &Statement, normalizer: &Normalizer) -> Statement {
     match stmt {
-        Statement::Query(query) => Statement::Query(Box::new(normalize_query(query))),
-        Statement::Insert { .. } => normalize_insert(stmt),
-        Statement::Update { .. } => normalize_update(stmt),
-        Statement::Delete { .. } => normalize_delete(stmt),
+        Statement::Query(query) => Statement::Query(Box::new(normalize_query(query, normalizer))),
+        Statement::Insert { .. } => normalize_insert(stmt, normalizer),
+        Statement::Update { .. } => normalize_update(stmt, normalizer),
+        Statement::Delete { .. } => normalize_delete(stmt, normalizer),
         default => default.clone(),
     }
 }
 
-fn normalize_query(query: &Query) -> Query {
-    let order_by = query.order_by.iter().map(normalize_order_by).collect();
+fn normalize_query(query: &Query, normalizer: &Normalizer) -> Query {
+    // the body comes first so that captured sample values are in the order of the placeholders
+    let body = Box::new(normalize_set_expr(&query.body, normalizer));
+    let order_by = query.order_by.iter().map(|item| normalize_order_by(item, normalizer)).collect();
     Query {
         with: query.with.clone(),
-        body: Box::new(normalize_set_expr(&query.body)),
+        body,
         order_by,
-        limit: query.limit.clone(),
-        offset: query.offset.as_ref().map(normalize_offset),
+        limit: normalize_limit(&query.limit, normalizer),
+        offset: query.offset.as_ref().map(|item| normalize_offset(item, normalizer)),
         fetch: query.fetch.clone(),
         locks: query.locks.clone(),
     }
 }
 
-fn normalize_insert(stmt: &Statement) -> Statement {
+fn normalize_insert(stmt: &Statement, normalizer: &Normalizer) -> Statement {
     match stmt {
         Statement::Insert { into, table_name, columns, source, on, .. } => Statement::Insert {
             into: *into,
             table_name: table_name.to_owned(),
             columns: columns.clone(),
-            source: Box::new(normalize_query(source)),
+            source: Box::new(normalize_query(source, normalizer)),
             on: on.clone(),
             returning: None,
             partitioned: None,
@@ -91,195 +575,226 @@ fn normalize_insert(stmt: &Statement) -> Statement {
     }
 }
 
-fn normalize_update(stmt: &Statement) -> Statement {
+fn normalize_update(stmt: &Statement, normalizer: &Normalizer) -> Statement {
     match stmt {
         Statement::Update { table, assignments, from, selection, returning } => Statement::Update {
-            table: normalize_table_with_joins(table),
-            assignments: assignments.iter().map(normalize_assignment).collect(),
-            from: from.as_ref().map(normalize_table_with_joins),
-            selection: selection.as_ref().map(normalize_expr),
+            table: normalize_table_with_joins(table, normalizer),
+            assignments: assignments
+                .iter()
+                .map(|item| normalize_assignment(item, normalizer))
+                .collect(),
+            from: from.as_ref().map(|item| normalize_table_with_joins(item, normalizer)),
+            selection: selection.as_ref().map(|item| normalize_expr(item, normalizer)),
             returning: returning.clone(),
         },
         _ => panic!("A glitch in the matrix has occurred"),
     }
 }
 
-fn normalize_assignment(assignment: &Assignment) -> Assignment {
-    Assignment { id: assignment.id.clone(), value: normalize_expr(&assignment.value) }
+fn normalize_assignment(assignment: &Assignment, normalizer: &Normalizer) -> Assignment {
+    Assignment { id: assignment.id.clone(), value: normalize_expr(&assignment.value, normalizer) }
 }
 
-fn normalize_delete(stmt: &Statement) -> Statement {
+fn normalize_delete(stmt: &Statement, normalizer: &Normalizer) -> Statement {
     match stmt {
         Statement::Delete { tables, from, using, selection, returning, order_by, limit } => {
             Statement::Delete {
                 tables: tables.clone(),
                 from: from.clone(),
                 using: using.clone(),
-                selection: selection.as_ref().map(normalize_expr),
+                selection: selection.as_ref().map(|item| normalize_expr(item, normalizer)),
                 returning: returning.clone(),
-                order_by: order_by.iter().map(normalize_order_by).collect(),
-                limit: limit.clone(),
+                order_by: order_by
+                    .iter()
+                    .map(|item| normalize_order_by(item, normalizer))
+                    .collect(),
+                limit: normalize_limit(limit, normalizer),
             }
         }
         _ => panic!("A glitch in the matrix has occurred"),
     }
 }
 
-fn normalize_offset(offset: &Offset) -> Offset {
-    Offset { value: normalize_expr(&offset.value), rows: offset.rows }
+fn normalize_offset(offset: &Offset, normalizer: &Normalizer) -> Offset {
+    Offset { value: normalize_expr(&offset.value, normalizer), rows: offset.rows }
 }
 
-fn normalize_set_expr(set_expr: &SetExpr) -> SetExpr {
+fn normalize_set_expr(set_expr: &SetExpr, normalizer: &Normalizer) -> SetExpr {
     match set_expr {
-        SetExpr::Select(select) => SetExpr::Select(Box::new(normalize_select(select))),
-        SetExpr::Query(query) => SetExpr::Query(Box::new(normalize_query(query))),
+        SetExpr::Select(select) => SetExpr::Select(Box::new(normalize_select(select, normalizer))),
+        SetExpr::Query(query) => SetExpr::Query(Box::new(normalize_query(query, normalizer))),
         SetExpr::SetOperation { op, set_quantifier, left, right } => SetExpr::SetOperation {
             op: *op,
             set_quantifier: *set_quantifier,
-            left: Box::new(normalize_set_expr(left)),
-            right: Box::new(normalize_set_expr(right)),
+            left: Box::new(normalize_set_expr(left, normalizer)),
+            right: Box::new(normalize_set_expr(right, normalizer)),
         },
-        SetExpr::Values(values) => SetExpr::Values(normalize_values(values)),
-        SetExpr::Insert(stmt) => SetExpr::Insert(normalize_stmt(stmt)),
-        SetExpr::Update(stmt) => SetExpr::Update(normalize_stmt(stmt)),
+        SetExpr::Values(values) => SetExpr::Values(normalize_values(values, normalizer)),
+        SetExpr::Insert(stmt) => SetExpr::Insert(normalize_stmt(stmt, normalizer)),
+        SetExpr::Update(stmt) => SetExpr::Update(normalize_stmt(stmt, normalizer)),
         SetExpr::Table(table) => SetExpr::Table(table.clone()),
     }
 }
 
-fn normalize_select(select: &Select) -> Select {
-    let projection = select.projection.iter().map(normalize_select_item).collect();
-    let from = select.from.iter().map(normalize_table_with_joins).collect();
+fn normalize_select(select: &Select, normalizer: &Normalizer) -> Select {
+    let projection =
+        select.projection.iter().map(|item| normalize_select_item(item, normalizer)).collect();
+    let from =
+        select.from.iter().map(|item| normalize_table_with_joins(item, normalizer)).collect();
 
     Select {
-        distinct: select.distinct.as_ref().map(normalize_distinct),
+        distinct: select.distinct.as_ref().map(|item| normalize_distinct(item, normalizer)),
         top: select.top.clone(),
         projection,
         into: select.into.clone(),
         from,
         lateral_views: select.lateral_views.clone(),
-        selection: select.selection.as_ref().map(normalize_expr),
-        group_by: normalize_group_by(&select.group_by),
+        selection: select.selection.as_ref().map(|item| normalize_expr(item, normalizer)),
+        group_by: normalize_group_by(&select.group_by, normalizer),
         cluster_by: select.cluster_by.clone(),
         distribute_by: select.distribute_by.clone(),
         sort_by: select.sort_by.clone(),
-        having: select.having.as_ref().map(normalize_expr),
-        qualify: select.qualify.as_ref().map(normalize_expr),
+        having: select.having.as_ref().map(|item| normalize_expr(item, normalizer)),
+        qualify: select.qualify.as_ref().map(|item| normalize_expr(item, normalizer)),
         named_window: select.named_window.clone(),
     }
 }
 
-fn normalize_distinct(distinct: &Distinct) -> Distinct {
+fn normalize_distinct(distinct: &Distinct, normalizer: &Normalizer) -> Distinct {
     match distinct {
         Distinct::Distinct => Distinct::Distinct,
         Distinct::On(exprs) => {
-            let normalized_exprs = exprs.iter().map(normalize_expr).collect();
+            let normalized_exprs =
+                exprs.iter().map(|item| normalize_expr(item, normalizer)).collect();
             Distinct::On(normalized_exprs)
         }
     }
 }
 
-fn normalize_select_item(item: &SelectItem) -> SelectItem {
+fn normalize_select_item(item: &SelectItem, normalizer: &Normalizer) -> SelectItem {
     match item {
-        SelectItem::UnnamedExpr(expr) => SelectItem::UnnamedExpr(normalize_expr(expr)),
-        SelectItem::ExprWithAlias { expr, alias } => {
-            SelectItem::ExprWithAlias { expr: normalize_expr(expr), alias: alias.clone() }
-        }
+        SelectItem::UnnamedExpr(expr) => SelectItem::UnnamedExpr(normalize_expr(expr, normalizer)),
+        SelectItem::ExprWithAlias { expr, alias } => SelectItem::ExprWithAlias {
+            expr: normalize_expr(expr, normalizer),
+            alias: alias.clone(),
+        },
         qw @ SelectItem::QualifiedWildcard(_, _) => qw.clone(),
         w @ SelectItem::Wildcard(_) => w.clone(),
     }
 }
 
-fn normalize_table_with_joins(twj: &TableWithJoins) -> TableWithJoins {
-    let joins = twj.joins.iter().map(normalize_join).collect();
-    TableWithJoins { relation: normalize_table_factor(&twj.relation), joins }
+fn normalize_table_with_joins(twj: &TableWithJoins, normalizer: &Normalizer) -> TableWithJoins {
+    let joins = twj.joins.iter().map(|item| normalize_join(item, normalizer)).collect();
+    TableWithJoins { relation: normalize_table_factor(&twj.relation, normalizer), joins }
 }
 
-fn normalize_join(join: &Join) -> Join {
+fn normalize_join(join: &Join, normalizer: &Normalizer) -> Join {
     Join {
-        relation: normalize_table_factor(&join.relation),
-        join_operator: normalize_join_operator(&join.join_operator),
+        relation: normalize_table_factor(&join.relation, normalizer),
+        join_operator: normalize_join_operator(&join.join_operator, normalizer),
     }
 }
 
-fn normalize_table_factor(tf: &TableFactor) -> TableFactor {
+fn normalize_table_factor(tf: &TableFactor, normalizer: &Normalizer) -> TableFactor {
     match tf {
         TableFactor::NestedJoin { table_with_joins, alias } => TableFactor::NestedJoin {
-            table_with_joins: Box::new(normalize_table_with_joins(table_with_joins)),
+            table_with_joins: Box::new(normalize_table_with_joins(table_with_joins, normalizer)),
             alias: alias.clone(),
         },
         TableFactor::Derived { lateral, subquery, alias } => TableFactor::Derived {
             lateral: *lateral,
-            subquery: Box::new(normalize_query(subquery)),
+            subquery: Box::new(normalize_query(subquery, normalizer)),
             alias: alias.clone(),
         },
+        TableFactor::Table { .. } if normalizer.options.fold_identifiers => {
+            let mut table = tf.clone();
+            if let TableFactor::Table { name, .. } = &mut table {
+                name.0 = name.0.iter().map(|ident| normalize_ident(ident, normalizer)).collect();
+            }
+            table
+        }
         default => default.clone(),
     }
 }
 
-fn normalize_join_operator(operator: &JoinOperator) -> JoinOperator {
+fn normalize_join_operator(operator: &JoinOperator, normalizer: &Normalizer) -> JoinOperator {
     match operator {
         JoinOperator::Inner(constraint) => {
-            JoinOperator::Inner(normalize_join_constraint(constraint))
+            JoinOperator::Inner(normalize_join_constraint(constraint, normalizer))
         }
         JoinOperator::LeftOuter(constraint) => {
-            JoinOperator::LeftOuter(normalize_join_constraint(constraint))
+            JoinOperator::LeftOuter(normalize_join_constraint(constraint, normalizer))
         }
         JoinOperator::RightOuter(constraint) => {
-            JoinOperator::RightOuter(normalize_join_constraint(constraint))
+            JoinOperator::RightOuter(normalize_join_constraint(constraint, normalizer))
         }
         JoinOperator::FullOuter(constraint) => {
-            JoinOperator::FullOuter(normalize_join_constraint(constraint))
+            JoinOperator::FullOuter(normalize_join_constraint(constraint, normalizer))
         }
         default => default.clone(),
     }
 }
 
-fn normalize_join_constraint(constraint: &JoinConstraint) -> JoinConstraint {
+fn normalize_join_constraint(
+    constraint: &JoinConstraint,
+    normalizer: &Normalizer,
+) -> JoinConstraint {
     match constraint {
-        JoinConstraint::On(expr) => JoinConstraint::On(normalize_expr(expr)),
+        JoinConstraint::On(expr) => JoinConstraint::On(normalize_expr(expr, normalizer)),
         default => default.clone(),
     }
 }
 
-fn normalize_values(values: &Values) -> Values {
-    let rows = values.rows.iter().map(|vec| vec.iter().map(normalize_expr).collect()).collect();
+fn normalize_values(values: &Values, normalizer: &Normalizer) -> Values {
+    let rows = values
+        .rows
+        .iter()
+        .map(|vec| vec.iter().map(|item| normalize_expr(item, normalizer)).collect())
+        .collect();
     Values { explicit_row: values.explicit_row, rows }
 }
 
-fn normalize_order_by(order_by: &OrderByExpr) -> OrderByExpr {
+fn normalize_order_by(order_by: &OrderByExpr, normalizer: &Normalizer) -> OrderByExpr {
     OrderByExpr {
-        expr: normalize_expr(&order_by.expr),
+        expr: normalize_expr(&order_by.expr, normalizer),
         asc: order_by.asc,
         nulls_first: order_by.nulls_first,
     }
 }
 
-fn normalize_group_by(group_by: &GroupByExpr) -> GroupByExpr {
+fn normalize_group_by(group_by: &GroupByExpr, normalizer: &Normalizer) -> GroupByExpr {
     match group_by {
         GroupByExpr::All => GroupByExpr::All,
         GroupByExpr::Expressions(exprs) => {
-            let normalized_exprs = exprs.iter().map(normalize_expr).collect();
+            let normalized_exprs =
+                exprs.iter().map(|item| normalize_expr(item, normalizer)).collect();
             GroupByExpr::Expressions(normalized_exprs)
         }
     }
 }
 
-fn normalize_expr(expr: &Expr) -> Expr {
-    let map_exprs = |exprs: &Vec<Expr>| exprs.iter().map(normalize_expr).collect();
-    let map_boxed_expr = |boxed: &Expr| Box::new(normalize_expr(boxed));
-    let map_boxed_query = |boxed: &Query| Box::new(normalize_query(boxed));
+fn normalize_expr(expr: &Expr, normalizer: &Normalizer) -> Expr {
+    let map_exprs =
+        |exprs: &Vec<Expr>| exprs.iter().map(|item| normalize_expr(item, normalizer)).collect();
+    let map_boxed_expr = |boxed: &Expr| Box::new(normalize_expr(boxed, normalizer));
+    let map_boxed_query = |boxed: &Query| Box::new(normalize_query(boxed, normalizer));
     match expr {
         Expr::IsNull(e) => Expr::IsNull(map_boxed_expr(e)),
         Expr::IsNotNull(e) => Expr::IsNotNull(map_boxed_expr(e)),
-        // reduce all lists down to 1 element
-        Expr::InList { expr, list, negated } => Expr::InList {
-            expr: map_boxed_expr(expr),
-            list: match list.first() {
-                Some(expr) => vec![normalize_expr(expr)],
-                None => Vec::new(),
-            },
-            negated: *negated,
-        },
+        // reduce all lists down to 1 element, unless asked to keep them
+        Expr::InList { expr, list, negated } if normalizer.options.collapse_in_lists => {
+            Expr::InList {
+                expr: map_boxed_expr(expr),
+                list: normalizer.capture_as_one(|| {
+                    let list: Vec<Expr> = map_exprs(list);
+                    list.into_iter().take(1).collect()
+                }),
+                negated: *negated,
+            }
+        }
+        Expr::InList { expr, list, negated } => {
+            Expr::InList { expr: map_boxed_expr(expr), list: map_exprs(list), negated: *negated }
+        }
         Expr::InSubquery { expr, subquery, negated } => Expr::InSubquery {
             expr: map_boxed_expr(expr),
             subquery: map_boxed_query(subquery),
@@ -329,11 +844,349 @@ fn normalize_expr(expr: &Expr) -> Expr {
             pattern: map_boxed_expr(pattern),
         },
         Expr::Subquery(query) => Expr::Subquery(map_boxed_query(query)),
-        Expr::Value(v) => Expr::Value(normalize_value(v)),
+        Expr::Function(function) => Expr::Function(Function {
+            args: function
+                .args
+                .iter()
+                .map(|item| normalize_function_arg(item, normalizer))
+                .collect(),
+            ..function.clone()
+        }),
+        Expr::Value(v) => Expr::Value(normalize_value(v, normalizer)),
+        Expr::Identifier(ident) => Expr::Identifier(normalize_ident(ident, normalizer)),
+        Expr::CompoundIdentifier(idents) => Expr::CompoundIdentifier(
+            idents.iter().map(|ident| normalize_ident(ident, normalizer)).collect(),
+        ),
         default => default.clone(),
     }
 }
 
-fn normalize_value(_value: &Value) -> Value {
+fn normalize_function_arg(arg: &FunctionArg, normalizer: &Normalizer) -> FunctionArg {
+    let mut arg = arg.clone();
+    match &mut arg {
+        FunctionArg::Named { arg: FunctionArgExpr::Expr(expr), .. }
+        | FunctionArg::Unnamed(FunctionArgExpr::Expr(expr)) => {
+            *expr = normalize_expr(expr, normalizer)
+        }
+        _ => (),
+    }
+    arg
+}
+
+fn normalize_limit(limit: &Option<Expr>, normalizer: &Normalizer) -> Option<Expr> {
+    match limit {
+        Some(expr) if normalizer.options.normalize_limit => Some(normalize_expr(expr, normalizer)),
+        _ => limit.clone(),
+    }
+}
+
+fn normalize_ident(ident: &Ident, normalizer: &Normalizer) -> Ident {
+    if normalizer.options.fold_identifiers {
+        Ident { value: ident.value.to_lowercase(), quote_style: ident.quote_style }
+    } else {
+        ident.clone()
+    }
+}
+
+fn normalize_value(value: &Value, normalizer: &Normalizer) -> Value {
+    if let Some(values) = &normalizer.sample_values {
+        values.borrow_mut().push(value.to_string());
+    }
     Value::Placeholder("?".to_owned())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aggregate::{aggregate_by_key, AggregateOptions};
+
+    fn entry(query: &str) -> LogEntry {
+        LogEntry { query: query.to_string(), ..Default::default() }
+    }
+
+    #[test]
+    fn it_parses_duplicate_queries_only_once() {
+        let cache = NormalizeCache::default();
+        let first = cache.normalize(entry("SELECT * FROM foo WHERE id = 1;"));
+        let second = cache.normalize(entry("SELECT * FROM foo WHERE id = 1;"));
+        assert_eq!(1, cache.misses());
+        assert_eq!(first.normalized_query, second.normalized_query);
+
+        let third = cache.normalize(entry("SELECT * FROM foo WHERE id = 2;"));
+        assert_eq!(2, cache.misses());
+        assert_eq!(first.normalized_query, third.normalized_query);
+    }
+
+    #[test]
+    fn it_empties_the_cache_when_it_is_full() {
+        let cache = NormalizeCache { capacity: 2, ..Default::default() };
+        for query in ["SELECT 1;", "SELECT 2;", "SELECT 1;", "SELECT 3;", "SELECT 1;"] {
+            cache.normalize(entry(query));
+        }
+        assert_eq!(4, cache.misses());
+        assert_eq!(2, cache.queries.read().unwrap().len());
+    }
+
+    #[test]
+    fn it_reports_each_parse_error_once() {
+        let cache = NormalizeCache::default();
+        let entries: Vec<NormalizedLogEntry> =
+            ["THIS IS NOT SQL;", "SELECT * FROM foo WHERE id = 1;", "THIS IS NOT SQL;"]
+                .into_iter()
+                .map(|query| cache.normalize(entry(query)))
+                .collect();
+
+        let errors = parse_errors(&entries);
+        assert_eq!(1, errors.len());
+        assert_eq!("THIS IS NOT SQL;", errors[0].0);
+        assert!(errors[0].1.contains("THIS"));
+    }
+
+    #[test]
+    fn it_normalizes_function_arguments() {
+        let defaults = NormalizeOptions::default();
+        let first = normalize(
+            entry("SELECT * FROM foo WHERE created < DATE_SUB('2021-05-11', 1);"),
+            &defaults,
+        );
+        let second = normalize(
+            entry("SELECT * FROM foo WHERE created < DATE_SUB('2021-06-01', 2);"),
+            &defaults,
+        );
+        assert_eq!(first.normalized_query, second.normalized_query);
+        assert!(first.normalized_query.contains("DATE_SUB(?, ?)"));
+    }
+
+    #[test]
+    fn it_computes_stable_fingerprints() {
+        assert_eq!("cbf29ce484222325", fingerprint(""));
+        assert_eq!(fingerprint("SELECT ?;"), fingerprint("SELECT ?;"));
+        assert_ne!(fingerprint("SELECT ?;"), fingerprint("SELECT ?, ?;"));
+    }
+
+    #[test]
+    fn it_determines_query_shapes() {
+        let select_shapes = [
+            "SELECT * FROM orders WHERE id = 1;",
+            "SELECT id FROM orders WHERE customer = 'foo' ORDER BY id LIMIT 5;",
+            "SELECT o.id FROM orders o JOIN customers c ON c.id = o.customer;",
+        ]
+        .map(query_shape);
+        assert!(select_shapes.iter().all(|shape| shape == "SELECT on orders"));
+
+        assert_eq!("UPDATE on users", query_shape("UPDATE users SET name = 'foo' WHERE id = 1;"));
+        assert_eq!("INSERT on users", query_shape("INSERT INTO users (name) VALUES ('foo');"));
+        assert_eq!("DELETE on users", query_shape("DELETE FROM users WHERE id = 1;"));
+        assert_eq!("SELECT", query_shape("SELECT 1;"));
+        assert_eq!(OTHER_SHAPE, query_shape("THIS IS NOT SQL;"));
+    }
+
+    #[test]
+    fn it_folds_keyword_case() {
+        assert_eq!(
+            "SELECT foo, `select` FROM bar b WHERE b.order = 'select' AND @limit > 1;",
+            fold_keyword_case(
+                "select foo, `select` from bar b where b.order = 'select' and @limit > 1;"
+            )
+        );
+    }
+
+    #[test]
+    fn it_merges_queries_differing_in_keyword_case() {
+        let entries = ["select 1;", "SELECT 1;", "Select 1;", "SELECT 2;"]
+            .into_iter()
+            .map(|query| (fold_keyword_case(query), entry(query)))
+            .collect();
+
+        let aggregated = aggregate_by_key(entries, AggregateOptions::default());
+        assert_eq!(2, aggregated.len());
+        assert_eq!(3, aggregated.get("SELECT 1;").expect("we know this exists").count);
+    }
+
+    #[test]
+    fn it_replaces_numeric_literals_without_parsing() {
+        assert_eq!(
+            "SELECT * FROM t1 WHERE id = ? AND price > ? AND flags & ? LIMIT ?, ?;",
+            normalize_literals(
+                "SELECT * FROM t1 WHERE id = 42 AND price > 1.5e-3 AND flags & 0xFF LIMIT 10, 20;"
+            )
+        );
+    }
+
+    #[test]
+    fn it_replaces_quoted_literals_without_parsing() {
+        assert_eq!(
+            "SELECT `col 1` FROM foo WHERE name = ? OR name = ?;",
+            normalize_literals("SELECT `col 1` FROM foo WHERE name = 'bar' OR name = \"baz 2\";")
+        );
+    }
+
+    #[test]
+    fn it_handles_escaped_quotes_without_parsing() {
+        assert_eq!(
+            "SELECT * FROM foo WHERE a = ? AND b = ? AND c = ?;",
+            normalize_literals(
+                r"SELECT * FROM foo WHERE a = 'it\'s' AND b = 'it''s 1' AND c = 'back\\';"
+            )
+        );
+    }
+
+    #[test]
+    fn it_skips_comments_without_parsing() {
+        assert_eq!(
+            "SELECT * FROM foo -- don't look\nWHERE id = ? # it's 42\nAND /* 'x */ a = ?;",
+            normalize_literals(
+                "SELECT * FROM foo -- don't look\nWHERE id = 42 # it's 42\nAND /* 'x */ a = 'y';"
+            )
+        );
+        assert_eq!("SELECT ?--?;", normalize_literals("SELECT 1--1;"));
+        assert_eq!(
+            vec![
+                Segment::Code("SELECT `a#b`, "),
+                Segment::Literal("'--'"),
+                Segment::Code(" "),
+                Segment::Comment("/* unterminated"),
+            ],
+            split_query("SELECT `a#b`, '--' /* unterminated")
+        );
+    }
+
+    #[test]
+    fn it_removes_comments_when_redacting() {
+        assert_eq!(
+            "SELECT * FROM users WHERE email = ?;",
+            redact_literals(
+                "/* user: jane@example.com */ SELECT * FROM users WHERE email = 'jane@example.com'; \
+                 -- token abc123"
+            )
+        );
+        assert_eq!("SELECT ?;", redact_literals("SELECT/* jane */1; -- token abc"));
+    }
+
+    #[test]
+    fn it_dumps_the_syntax_tree_of_parsed_queries_once() {
+        let options = NormalizeOptions { dump_ast: true, ..Default::default() };
+        let cache = NormalizeCache::new(options);
+
+        let ast = cache.normalize(entry("SELECT * FROM foo WHERE id = 1;")).ast;
+        let ast = ast.expect("the query was parsed");
+        assert!(ast.contains("Query("));
+        assert!(ast.contains("value: \"foo\""));
+
+        assert_eq!(None, cache.normalize(entry("SELECT * FROM foo WHERE id = 1;")).ast);
+        assert_eq!(None, normalize(entry("SELECT * FROM foo;"), &Default::default()).ast);
+    }
+
+    fn normalize_with(query: &str, options: NormalizeOptions) -> String {
+        normalize(entry(query), &options).normalized_query.trim().to_string()
+    }
+
+    #[test]
+    fn it_keeps_in_lists_if_asked_to() {
+        let query = "SELECT * FROM foo WHERE id IN (1, 2, 3);";
+        assert_eq!(
+            "SELECT * FROM foo WHERE id IN (?);",
+            normalize_with(query, NormalizeOptions::default())
+        );
+        let options = NormalizeOptions { collapse_in_lists: false, ..Default::default() };
+        assert_eq!("SELECT * FROM foo WHERE id IN (?, ?, ?);", normalize_with(query, options));
+    }
+
+    #[test]
+    fn it_captures_all_values_of_a_collapsed_in_list_as_one() {
+        let options = NormalizeOptions { capture_sample_values: true, ..Default::default() };
+        let normalized =
+            normalize(entry("SELECT * FROM foo WHERE id IN (1, 2, 3) AND name = 'a';"), &options);
+        assert_eq!(
+            "SELECT * FROM foo WHERE id IN (?) AND name = ?;",
+            normalized.normalized_query.trim()
+        );
+        assert_eq!(vec!["1, 2, 3", "'a'"], normalized.sample_values);
+    }
+
+    #[test]
+    fn it_merges_runs_of_placeholders_for_display() {
+        let values: Vec<String> = (1..=10).map(|i| i.to_string()).collect();
+        let query = format!("INSERT INTO foo VALUES ({});", values.join(", "));
+        let normalized = normalize_with(&query, NormalizeOptions::default());
+        assert_eq!(10, normalized.matches('?').count());
+        assert_eq!("INSERT INTO foo VALUES (?, ...);", merge_placeholder_runs(&normalized));
+        assert_eq!(
+            "SELECT * FROM foo WHERE a = ? AND b IN (?, ?);",
+            merge_placeholder_runs("SELECT * FROM foo WHERE a = ? AND b IN (?, ?);")
+        );
+    }
+
+    #[test]
+    fn it_normalizes_limits_if_asked_to() {
+        let query = "SELECT * FROM foo LIMIT 10;";
+        assert_eq!(
+            "SELECT * FROM foo LIMIT 10;",
+            normalize_with(query, NormalizeOptions::default())
+        );
+        let options = NormalizeOptions { normalize_limit: true, ..Default::default() };
+        assert_eq!("SELECT * FROM foo LIMIT ?;", normalize_with(query, options));
+    }
+
+    #[test]
+    fn it_folds_identifiers_if_asked_to() {
+        let query = "SELECT Name FROM Foo WHERE Foo.Id = 1;";
+        assert_eq!(
+            "SELECT Name FROM Foo WHERE Foo.Id = ?;",
+            normalize_with(query, NormalizeOptions::default())
+        );
+        let options = NormalizeOptions { fold_identifiers: true, ..Default::default() };
+        assert_eq!("SELECT name FROM foo WHERE foo.id = ?;", normalize_with(query, options));
+    }
+
+    #[test]
+    fn it_strips_hints_if_asked_to() {
+        let options = NormalizeOptions { strip_hints: true, ..Default::default() };
+        let expected = "SELECT * FROM foo JOIN bar ON foo.id = bar.foo_id WHERE foo.a = ?;";
+        assert_eq!(
+            expected,
+            normalize_with(
+                "SELECT * FROM foo USE INDEX (idx_a) JOIN bar FORCE KEY FOR JOIN (PRIMARY, \
+                 idx_foo) ON foo.id = bar.foo_id WHERE foo.a = 1;",
+                options
+            )
+        );
+        assert_eq!(
+            expected,
+            normalize_with(
+                "SELECT /*+ INDEX(foo idx_a) */ * FROM foo JOIN bar ON foo.id = bar.foo_id \
+                 WHERE foo.a = 2;",
+                options
+            )
+        );
+        assert_eq!(
+            "SELECT * FROM foo WHERE note = ? AND b = ?;",
+            normalize_with(
+                "SELECT * FROM foo IGNORE INDEX (idx_b) WHERE note = 'please use index (idx_a)' \
+                 AND b = 1;",
+                options
+            )
+        );
+        assert_eq!(
+            "SELECT * FROM foo WHERE note = 'please use index (idx_a)' AND b = 1 -- force key (x)",
+            strip_index_hints(
+                "SELECT * FROM foo IGNORE INDEX (idx_b) WHERE note = 'please use index (idx_a)' \
+                 AND b = 1 -- force key (x)"
+            )
+        );
+    }
+
+    #[test]
+    fn it_keeps_leading_comments_if_asked_to() {
+        let query = "/* controller:orders */ SELECT * FROM foo WHERE id = 1;";
+        assert_eq!(
+            "SELECT * FROM foo WHERE id = ?;",
+            normalize_with(query, NormalizeOptions::default())
+        );
+        let options = NormalizeOptions { strip_comments: false, ..Default::default() };
+        assert_eq!(
+            "/* controller:orders */ SELECT * FROM foo WHERE id = ?;",
+            normalize_with(query, options)
+        );
+    }
+}
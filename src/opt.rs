@@ -1,59 +1,572 @@
-use clap::{Parser, ValueEnum};
+use clap::{ArgAction, Parser, ValueEnum};
 use once_cell::sync::OnceCell;
 use regex::{Captures, Regex};
+use serde::Deserialize;
+use time::format_description::well_known::{Iso8601, Rfc3339};
+use time::OffsetDateTime;
 
-use crate::filters::{
-    Filter, Not, QueryMatches, QueryTimeGreaterThan, QueryTimeLessThan, UserEquals, UserMatches,
-};
+use crate::aggregate::{AggregateOptions, DEFAULT_IMPACT_WEIGHT};
+use crate::config::Config;
+use crate::filters::{CostWeights, Filter, FilterOptions, FilterRegistry};
+use crate::format::TimeFormat;
+use crate::histogram;
+use crate::log_parser::{InputFormat, OversizedQueries, ParseOptions};
+use crate::normalize::NormalizeOptions;
+use crate::timeseries;
 
-#[derive(Copy, Clone, Debug, ValueEnum)]
+#[derive(Copy, Clone, Debug, Deserialize, PartialEq, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
 pub enum SortOrder {
     Count,
     AvgTime,
     MaxTime,
     TotalTime,
+    StddevTime,
+    P50Time,
+    P90Time,
+    P95Time,
+    P99Time,
+    /// Total time, weighted by the logarithm of the number of executions
+    Impact,
+}
+
+#[derive(Copy, Clone, Debug, Default, Deserialize, PartialEq, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum OutputFormat {
+    /// Human-readable text
+    #[default]
+    Text,
+    /// One JSON object per line
+    Ndjson,
+    /// InfluxDB line protocol
+    Influx,
+    /// A SQL script with the slowest query of each aggregated record, annotated with its statistics
+    SqlComments,
+    /// Prometheus text exposition format
+    Prometheus,
+}
+
+/// A dimension of the key by which `--group-by` combines queries
+#[derive(Copy, Clone, Debug, PartialEq, ValueEnum)]
+pub enum GroupBy {
+    /// Normalized query
+    Fingerprint,
+    /// Statement type and first table
+    Shape,
+    /// Error code, or whether the query was killed
+    Error,
+    /// Client host name or IP address
+    Host,
+    /// User who ran the query
+    User,
+    /// Database selected by a `use` statement; requires --keep-use
+    Schema,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, ValueEnum)]
+pub enum SplitBy {
+    /// The user who ran the query
+    User,
+    /// The database selected by a `use` statement; requires --keep-use
+    Schema,
+}
+
+/// The time window of `--incident`, including both ends
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TimeRange {
+    pub start: OffsetDateTime,
+    pub end: OffsetDateTime,
 }
 
 #[derive(Parser)]
+#[command(version)]
 pub struct Opt {
-    #[arg(short = 'i', long = "infile")]
-    /// The path to the logfile. If not given, will try reading from stdin
-    pub filename: Option<String>,
+    #[arg(short = 'i', long = "infile", number_of_values = 1)]
+    /// The path to the logfile. May be given multiple times. If not given, will try reading from
+    /// stdin
+    pub filenames: Vec<String>,
+    #[cfg(feature = "journald")]
+    #[arg(long, value_name = "UNIT", conflicts_with = "filenames")]
+    /// Read the log from the systemd journal of this unit, e.g. mysqld.service
+    pub journald: Option<String>,
+    #[arg(long)]
+    /// Sort the entries of all logfiles chronologically before processing them
+    pub sort_input_by_time: bool,
+    #[arg(long, value_name = "N")]
+    /// Only process the last N entries, reading uncompressed logfiles backwards from the end
+    pub last: Option<usize>,
+    #[arg(long)]
+    /// Warn about input files that cannot be read and continue with the others
+    pub skip_unreadable: bool,
+    #[arg(long)]
+    /// Treat SET statements preceding a query as queries, except for SET timestamp
+    pub keep_set_statements: bool,
+    #[arg(long)]
+    /// Prepend the database selected by a `use` statement to the query of each entry
+    pub keep_use: bool,
+    #[arg(long)]
+    /// Read Query_time and Lock_time as integer microseconds instead of fractional seconds
+    pub time_in_micros: bool,
+    #[arg(long, value_enum, default_value_t = InputFormat::Mysql)]
+    /// The format of the logfiles
+    pub input_format: InputFormat,
+    #[arg(short, long, value_enum)]
+    /// The output format [default: text]
+    pub format: Option<OutputFormat>,
+    #[arg(long)]
+    /// Separate thousands in row counts and query counts of the text output with commas
+    pub human_numbers: bool,
+    #[arg(long)]
+    /// Stop writing output before the first line that would exceed this many bytes
+    pub max_output_bytes: Option<usize>,
+    #[arg(long)]
+    /// Indent JSON output instead of writing one object per line
+    pub pretty: bool,
     #[arg(short = 'F', long = "filter", number_of_values = 1)]
     pub filters: Vec<String>,
+    #[arg(long, value_name = "START..END", value_parser = parse_time_range)]
+    /// Only consider entries logged between these two ISO 8601 timestamps
+    pub incident: Option<TimeRange>,
+    #[arg(long, value_name = "SECONDS", requires = "incident")]
+    /// With --incident, only consider entries that took at least this many seconds
+    pub min_time: Option<f64>,
+    #[arg(long)]
+    /// Compare user names in filters after Unicode normalization (NFC)
+    pub unicode_normalize: bool,
+    #[arg(long, value_name = "WEIGHTS", default_value = "1,0.000001,1")]
+    /// Weights of query time, rows examined and lock time in the score of the cost filter
+    pub cost_weights: CostWeights,
+    #[arg(long)]
+    /// Print a line describing the active filters to stderr
+    pub filter_summary: bool,
     #[arg(short, long)]
     pub order: Option<SortOrder>,
+    #[arg(long)]
+    /// Break ties in the sort order by this criterion
+    pub sort_secondary: Option<SortOrder>,
+    #[arg(long, value_name = "W", default_value_t = DEFAULT_IMPACT_WEIGHT)]
+    /// The weight of the number of executions in the impact sort order
+    pub impact_weight: f64,
     #[arg(short, long)]
     /// Combine identical queries
     pub aggregate: bool,
+    #[arg(long, requires = "normalize", conflicts_with = "aggregate")]
+    /// With -n, display each entry with its normalized query instead of aggregating them
+    pub no_aggregate: bool,
+    #[arg(long)]
+    /// When aggregating, treat queries that only differ in the case of SQL keywords as identical
+    pub merge_case_insensitive_keywords: bool,
+    #[arg(long)]
+    /// When aggregating, collect all queries beyond this many distinct ones in an <other> record
+    pub max_groups: Option<usize>,
+    #[arg(long)]
+    /// When aggregating, also show the execution of each record that examined the most rows
+    pub extended: bool,
+    #[arg(long)]
+    /// With --format ndjson, list the individual executions of each aggregated record
+    pub include_executions: bool,
+    #[arg(long, value_name = "N", requires = "include_executions")]
+    /// List at most this many executions per aggregated record [default: 100]
+    pub max_executions_per_group: Option<usize>,
+    #[arg(long, value_name = "K")]
+    /// When aggregating, show the K slowest distinct raw queries of each record
+    pub examples: Option<usize>,
+    #[arg(short, long, value_name = "KEYS", value_delimiter = ',')]
+    /// Combine queries by a comma-separated list of keys instead of their (normalized) text
+    pub group_by: Vec<GroupBy>,
+    #[arg(long, conflicts_with = "group_by")]
+    /// Combine queries by the error they ended with; shorthand for `--group-by error`
+    pub aggregate_by_error: bool,
+    #[arg(long, value_name = "FILE")]
+    /// With -n, write the slowest raw query of each displayed record to this file as an SQL script
+    pub emit_repro: Option<String>,
+    #[cfg(feature = "push")]
+    #[arg(long, value_name = "URL", conflicts_with_all = ["split_by", "watch"])]
+    /// Push the metrics of the displayed records to this Prometheus Pushgateway
+    pub pushgateway: Option<String>,
+    #[cfg(feature = "push")]
+    #[arg(long, default_value = "myslowlog", requires = "pushgateway")]
+    /// The job name under which to push the metrics to the Pushgateway
+    pub job: String,
+    #[arg(
+        long,
+        value_name = "FILE",
+        conflicts_with_all = ["group_by", "aggregate_by_error", "report_duplicates"]
+    )]
+    /// Combine aggregated records with those from a report written with --format ndjson --limit 0
+    pub merge_report: Option<String>,
+    #[arg(long, value_name = "FILE")]
+    /// Compare the normalized queries of the input with those of this earlier log
+    pub baseline: Option<String>,
+    #[arg(long, value_name = "PERCENT", requires = "baseline")]
+    /// Exit with status 2 if the total time of a query grew by more than this since the baseline
+    pub fail_if_regression: Option<f64>,
+    #[arg(long, value_name = "FILE")]
+    /// Only show aggregated records whose query does not occur in this report, written with
+    /// --format ndjson --limit 0
+    pub new_since: Option<String>,
+    #[arg(long, value_name = "SECONDS")]
+    /// Only show aggregated records whose median query time is at least this value
+    pub min_p50: Option<f64>,
+    #[arg(long, value_name = "SECONDS")]
+    /// Only show aggregated records whose 90th percentile query time is at least this value
+    pub min_p90: Option<f64>,
+    #[arg(long, value_name = "SECONDS")]
+    /// Only show aggregated records whose 95th percentile query time is at least this value
+    pub min_p95: Option<f64>,
+    #[arg(long, value_name = "SECONDS")]
+    /// Only show aggregated records whose 99th percentile query time is at least this value
+    pub min_p99: Option<f64>,
     #[arg(short, long)]
     /// Replace values with placeholders
     pub normalize: bool,
-    #[arg(short, long, default_value = "10")]
-    pub limit: usize,
+    #[arg(long, conflicts_with = "normalize")]
+    /// Replace numeric and string literals with placeholders without parsing. Implies -a
+    pub normalize_numbers: bool,
+    #[arg(long, conflicts_with = "normalize")]
+    /// Replace string and numeric literals with ? and drop comments in displayed queries
+    pub redact: bool,
+    #[arg(long)]
+    /// When normalizing, keep all elements of IN lists instead of collapsing them into one
+    pub keep_in_lists: bool,
+    #[arg(long)]
+    /// When normalizing, replace LIMIT values with placeholders as well
+    pub normalize_limit: bool,
+    #[arg(long)]
+    /// When normalizing, convert table and column names to lower case
+    pub fold_identifiers: bool,
+    #[arg(long)]
+    /// When normalizing, keep comments in front of the query
+    pub keep_comments: bool,
+    #[arg(long)]
+    /// When normalizing, remove index hints such as USE INDEX (...)
+    pub strip_hints: bool,
+    #[arg(long)]
+    /// When normalizing, display runs of three or more placeholders as `?, ...`
+    pub merge_placeholder_runs: bool,
+    #[arg(long)]
+    /// When normalizing, show the literal values of the slowest query of each record
+    pub sample_values: bool,
+    #[arg(long, value_name = "MIN_COUNT", num_args = 0..=1, default_missing_value = "2")]
+    /// Only show raw queries that occur at least this often [default: 2]
+    pub report_duplicates: Option<i64>,
+    #[arg(long)]
+    /// Print the parser error for each distinct unparseable query to stderr
+    pub debug_parse: bool,
+    #[arg(long)]
+    /// List columns that might benefit from an index, guessed from queries that scan many rows
+    pub suggest_indexes: bool,
+    #[arg(long, requires = "normalize", conflicts_with = "no_aggregate")]
+    /// Only list the fingerprint, count and normalized query of all distinct queries
+    pub inventory: bool,
+    #[arg(long, requires = "normalize")]
+    /// Print the syntax tree of each distinct parsed query to stderr
+    pub dump_ast: bool,
     #[arg(short, long)]
-    /// Prints the program's version number
-    pub version: bool,
+    /// The maximum number of entries to display, or 0 to display all of them [default: 10]
+    pub limit: Option<usize>,
+    #[arg(long, value_name = "N")]
+    /// Display at most this many individual entries per user
+    pub limit_per_user: Option<usize>,
+    #[arg(long)]
+    /// Print summary statistics over all matching entries before the report
+    pub summary: bool,
+    #[arg(long, value_name = "PATH")]
+    /// Also write the summary statistics as JSON to this file, whatever the output format
+    pub summary_json: Option<String>,
+    // the qualified Vec path keeps clap from treating each edge as a separate argument value
+    #[arg(
+        long,
+        value_name = "EDGES",
+        num_args = 0..=1,
+        default_missing_value = histogram::DEFAULT_EDGES,
+        value_parser = histogram::parse_edges
+    )]
+    /// Count entries per query time range, delimited by these edges in seconds [default: 1,5,30]
+    pub latency_histogram: Option<::std::vec::Vec<f64>>,
+    #[arg(long, value_parser = timeseries::parse_interval, conflicts_with = "latency_histogram")]
+    /// Show a time series per normalized query with intervals of this length, e.g. 15m or 1h
+    pub interval: Option<time::Duration>,
+    #[arg(
+        long,
+        value_name = "INTERVAL",
+        value_parser = timeseries::parse_interval,
+        requires = "filenames",
+        conflicts_with_all = [
+            "no_aggregate",
+            "baseline",
+            "interval",
+            "latency_histogram",
+            "report_duplicates",
+            "suggest_indexes",
+            "summary",
+            "summary_json"
+        ]
+    )]
+    /// Re-read the logfiles at this interval, e.g. 10s, and refresh the aggregated report
+    pub watch: Option<time::Duration>,
+    #[arg(
+        long,
+        requires = "outdir",
+        requires_if("schema", "keep_use"),
+        conflicts_with_all = [
+            "baseline",
+            "latency_histogram",
+            "interval",
+            "report_duplicates",
+            "suggest_indexes",
+            "watch"
+        ]
+    )]
+    /// Write a separate report per user or schema to the directory given by --outdir
+    pub split_by: Option<SplitBy>,
+    #[arg(long, value_name = "DIR", requires = "split_by")]
+    /// The directory to write the reports of --split-by to; it is created if necessary
+    pub outdir: Option<String>,
+    #[arg(long)]
+    /// Display the age of each entry instead of its absolute timestamp
+    pub relative_time: bool,
+    #[arg(long, value_parser = TimeFormat::parse, conflicts_with = "relative_time")]
+    /// Format for timestamps: rfc3339, date, datetime or a custom format description
+    pub time_format: Option<TimeFormat>,
+    #[arg(long)]
+    /// Skip or truncate queries longer than this many bytes
+    pub max_query_bytes: Option<usize>,
+    #[arg(long, value_enum, default_value_t, requires = "max_query_bytes")]
+    /// What to do with queries exceeding --max-query-bytes
+    pub oversized_queries: OversizedQueries,
+    #[arg(long)]
+    /// Don't print a warning for each malformed log entry
+    pub quiet_errors: bool,
+    #[arg(long)]
+    /// Fail on the first malformed entry or unreadable file instead of skipping it
+    pub strict: bool,
+    #[arg(long)]
+    /// Print statistics about the parsed log to stderr (same as -v)
+    pub stats: bool,
+    #[arg(short, long, action = ArgAction::Count)]
+    /// Print diagnostics to stderr. Can be repeated up to three times for more detail
+    pub verbose: u8,
+    #[arg(long)]
+    /// Print the time spent parsing, filtering, normalizing and aggregating to stderr
+    pub benchmark: bool,
+    #[arg(long)]
+    /// Print the peak resident memory usage to stderr at the end
+    pub report_memory: bool,
+    #[arg(long)]
+    /// Read default options from this TOML file instead of ~/.config/myslowlog/config.toml
+    pub config: Option<String>,
+}
+
+const DEFAULT_LIMIT: usize = 10;
+const DEFAULT_MAX_EXECUTIONS: usize = 100;
+
+impl Opt {
+    pub fn verbosity(&self) -> u8 {
+        self.verbose.max(self.stats as u8)
+    }
+
+    /// Normalization implies aggregation unless it is explicitly disabled
+    pub fn aggregate(&self) -> bool {
+        (self.aggregate || self.normalize || self.normalize_numbers) && !self.no_aggregate
+    }
+
+    pub fn format(&self) -> OutputFormat {
+        self.format.unwrap_or_default()
+    }
+
+    pub fn group_by(&self) -> Option<Vec<GroupBy>> {
+        if self.group_by.is_empty() {
+            self.aggregate_by_error.then(|| vec![GroupBy::Error])
+        } else {
+            Some(self.group_by.clone())
+        }
+    }
+
+    pub fn order(&self) -> Option<SortOrder> {
+        let default = match (self.report_duplicates, self.group_by()) {
+            (Some(_), _) => Some(SortOrder::Count),
+            (_, Some(keys)) if keys == [GroupBy::Host] => Some(SortOrder::TotalTime),
+            _ => None,
+        };
+        self.order.or(default)
+    }
+
+    /// The maximum number of entries or records to display, where 0 stands for no limit
+    pub fn limit(&self) -> usize {
+        match self.limit.unwrap_or(DEFAULT_LIMIT) {
+            0 => usize::MAX,
+            limit => limit,
+        }
+    }
+
+    pub fn normalize_options(&self) -> NormalizeOptions {
+        NormalizeOptions {
+            collapse_in_lists: !self.keep_in_lists,
+            normalize_limit: self.normalize_limit,
+            fold_identifiers: self.fold_identifiers,
+            strip_comments: !self.keep_comments,
+            strip_hints: self.strip_hints,
+            capture_sample_values: self.sample_values,
+            dump_ast: self.dump_ast,
+        }
+    }
+
+    pub fn aggregate_options(&self) -> AggregateOptions {
+        let max_executions = if self.include_executions {
+            self.max_executions_per_group.unwrap_or(DEFAULT_MAX_EXECUTIONS)
+        } else {
+            0
+        };
+        AggregateOptions {
+            max_groups: self.max_groups,
+            max_executions,
+            max_examples: self.examples.unwrap_or(0),
+            keep_max_rows_query: self.extended,
+        }
+    }
+
+    pub fn filter_options(&self) -> FilterOptions {
+        FilterOptions { unicode_normalize: self.unicode_normalize, cost_weights: self.cost_weights }
+    }
+
+    /// The definitions of the filters given with `-F`, followed by the timestamp and query time
+    /// filters that `--incident` and `--min-time` are shorthands for
+    pub fn filter_definitions(&self) -> Vec<String> {
+        let mut definitions = self.filters.clone();
+        if let Some(range) = self.incident {
+            let format = |timestamp: OffsetDateTime| timestamp.format(&Rfc3339).unwrap();
+            definitions.push(format!("timestamp>={}", format(range.start)));
+            definitions.push(format!("timestamp<={}", format(range.end)));
+        }
+        if let Some(min_time) = self.min_time {
+            definitions.push(format!("query_time>={}", min_time));
+        }
+        definitions
+    }
+
+    pub fn parse_options(&self) -> ParseOptions {
+        ParseOptions {
+            input_format: self.input_format,
+            quiet_errors: self.quiet_errors,
+            keep_set_statements: self.keep_set_statements,
+            strict: self.strict,
+            keep_use: self.keep_use,
+            max_query_bytes: self.max_query_bytes,
+            oversized_queries: self.oversized_queries,
+            time_in_micros: self.time_in_micros,
+        }
+    }
 }
 
 pub fn parse_opts() -> (Opt, Vec<Box<dyn Filter>>) {
-    let opt = Opt::parse();
-    let mut filters = Vec::with_capacity(opt.filters.len());
-    for filter_def in &opt.filters {
-        let filter = parse_filter(filter_def).unwrap_or_else(|error| panic!("{}", error));
+    let mut opt = Opt::parse();
+    let config = Config::load(opt.config.as_deref()).unwrap_or_else(|error| panic!("{}", error));
+    config.apply_to(&mut opt);
+    validate_sort_orders(&opt).unwrap_or_else(|error| panic!("{}", error));
+    validate_format(&opt).unwrap_or_else(|error| panic!("{}", error));
+    validate_group_by(&opt).unwrap_or_else(|error| panic!("{}", error));
+    validate_emit_repro(&opt).unwrap_or_else(|error| panic!("{}", error));
+
+    let registry = FilterRegistry::builtin(&opt.filter_options());
+    let definitions = opt.filter_definitions();
+    let mut filters = Vec::with_capacity(definitions.len());
+    for filter_def in &definitions {
+        let filter =
+            parse_filter(filter_def, &registry).unwrap_or_else(|error| panic!("{}", error));
         filters.push(filter);
     }
     (opt, filters)
 }
 
-fn parse_filter(arg: &str) -> Result<Box<dyn Filter>, String> {
+/// Parses two ISO 8601 timestamps separated by `..`, e.g. `2021-05-11T07:00:00Z..2021-05-11T08:00:00Z`
+fn parse_time_range(arg: &str) -> Result<TimeRange, String> {
+    let (start, end) = arg
+        .split_once("..")
+        .ok_or_else(|| format!("Expected a time range such as START..END, found '{}'", arg))?;
+    let parse = |value: &str| {
+        OffsetDateTime::parse(value.trim(), &Iso8601::DEFAULT)
+            .map_err(|_err| format!("Expected an ISO 8601 timestamp, found '{}'", value))
+    };
+    let range = TimeRange { start: parse(start)?, end: parse(end)? };
+    if range.start > range.end {
+        return Err(format!("The time range '{}' ends before it starts", arg));
+    }
+    Ok(range)
+}
+
+fn validate_sort_orders(opt: &Opt) -> Result<(), String> {
+    match (opt.order, opt.sort_secondary) {
+        (None, Some(_)) => Err("--sort-secondary requires --order".to_string()),
+        (Some(primary), Some(secondary)) if primary == secondary => {
+            Err("--sort-secondary must differ from --order".to_string())
+        }
+        _ => Ok(()),
+    }
+}
+
+fn validate_format(opt: &Opt) -> Result<(), String> {
+    let aggregated = opt.aggregate() || opt.group_by().is_some() || opt.report_duplicates.is_some();
+    // the script consists of the queries of the records, which only plain aggregation keeps
+    let queries = opt.aggregate() && opt.group_by().is_none() && opt.report_duplicates.is_none();
+    if opt.format() == OutputFormat::SqlComments && !queries {
+        return Err(
+            "--format sql-comments requires aggregation, e.g. with --normalize, and cannot be \
+             combined with --group-by or --report-duplicates"
+                .to_string(),
+        );
+    }
+    let other_report = opt.baseline.is_some()
+        || opt.latency_histogram.is_some()
+        || opt.interval.is_some()
+        || opt.suggest_indexes
+        || opt.inventory;
+    if opt.format() == OutputFormat::SqlComments && other_report {
+        return Err("--format sql-comments cannot be combined with --baseline, \
+             --latency-histogram, --interval, --suggest-indexes or --inventory"
+            .to_string());
+    }
+    if opt.format() == OutputFormat::Prometheus && !aggregated {
+        return Err("--format prometheus requires aggregation, e.g. with --normalize".to_string());
+    }
+    Ok(())
+}
+
+fn validate_group_by(opt: &Opt) -> Result<(), String> {
+    for (i, key) in opt.group_by.iter().enumerate() {
+        if opt.group_by[..i].contains(key) {
+            let name = key.to_possible_value().expect("no key is skipped");
+            return Err(format!("--group-by lists the key '{}' more than once", name.get_name()));
+        }
+    }
+    if opt.group_by.contains(&GroupBy::Schema) && !opt.keep_use {
+        return Err("--group-by schema requires --keep-use".to_string());
+    }
+    Ok(())
+}
+
+/// Only records of normalized queries keep the raw text of their slowest query. The keys of all
+/// other records are labels or contain placeholders, neither of which can be run.
+fn validate_emit_repro(opt: &Opt) -> Result<(), String> {
+    let normalized = opt.normalize
+        && opt.aggregate()
+        && opt.group_by().is_none()
+        && opt.report_duplicates.is_none();
+    if opt.emit_repro.is_some() && !normalized {
+        return Err("--emit-repro requires aggregation by --normalize".to_string());
+    }
+    Ok(())
+}
+
+fn parse_filter(arg: &str, registry: &FilterRegistry) -> Result<Box<dyn Filter>, String> {
     static REGEX: OnceCell<Regex> = OnceCell::new();
-    let regex = REGEX
-        .get_or_init(|| Regex::new(r"^(?P<name>\w+)\s*(?P<op>[=<>!~]+)\s*(?P<value>.+)$").unwrap());
+    let regex = REGEX.get_or_init(|| {
+        Regex::new(r"^(?P<name>\w+)\s*(?P<op>[=<>!~]+|\bin\b)\s*(?P<value>.+)$").unwrap()
+    });
 
     regex.captures(arg).ok_or(format!("Invalid filter format: '{}'", arg)).and_then(
         |caps: Captures| {
-            create_filter(
+            registry.create(
                 caps.name("name").unwrap().as_str(),
                 caps.name("op").unwrap().as_str(),
                 caps.name("value").unwrap().as_str(),
@@ -62,48 +575,176 @@ fn parse_filter(arg: &str) -> Result<Box<dyn Filter>, String> {
     )
 }
 
-fn create_filter(name: &str, op: &str, value: &str) -> Result<Box<dyn Filter>, String> {
-    match name {
-        "user" => match op {
-            "=" => Ok(Box::new(UserEquals::new(value.to_string()))),
-            "!=" => {
-                let equals = Box::new(UserEquals::new(value.to_string()));
-                Ok(Box::new(Not::new(equals)))
-            }
-            "~=" => Ok(Box::new(UserMatches::new(value.to_string())?)),
-            _ => Err(format!("User filter expects one of '=', '!=' or '~=', found '{}'", op)),
-        },
-        "query" => match op {
-            "~=" => Ok(Box::new(QueryMatches::new(value.to_string())?)),
-            _ => Err(format!("Query filter only supports '~=', found '{}'", op)),
-        },
-        "query_time" => {
-            let time: f64 = value.parse().expect("Query time filter requires a numeric argument");
-            let msec = (1000.0 * time) as i64;
-            match op {
-                "<" | "<=" => Ok(Box::new(QueryTimeLessThan::new(msec))),
-                ">" | ">=" => Ok(Box::new(QueryTimeGreaterThan::new(msec))),
-                _ => Err(format!(
-                    "Query time filter expects one of '<', '<=', '>' or '>=', found '{}'",
-                    op
-                )),
-            }
-        }
-        _ => Err(format!("Unknown filter name: '{}'", name)),
-    }
-}
-
 #[cfg(test)]
 mod tests {
+    use time::macros::datetime;
+
     use super::*;
+    use crate::log_parser::LogEntry;
 
     #[test]
     fn it_parses_filters() {
-        let opt: Opt = Opt::parse_from(&["test", "-Fuser!=foo", "--filter", "query~=SELECT foo"]);
+        let opt: Opt = Opt::parse_from(["test", "-Fuser!=foo", "--filter", "query~=SELECT foo"]);
         assert_eq!(opt.filters.len(), 2);
         let first = opt.filters.first().unwrap();
         assert_eq!(first, "user!=foo");
         let second = opt.filters.last().unwrap();
         assert_eq!(second, "query~=SELECT foo");
     }
+
+    #[test]
+    fn it_parses_the_in_operator() {
+        let registry = FilterRegistry::builtin(&FilterOptions::default());
+        assert!(parse_filter("user in foo,bar, baz", &registry).is_ok());
+        assert!(parse_filter("user in foo", &registry).is_ok());
+        assert!(parse_filter("query in foo", &registry).is_err());
+    }
+
+    #[test]
+    fn it_validates_the_secondary_sort_order() {
+        let opt = Opt::parse_from(["test", "-o", "total-time", "--sort-secondary", "count"]);
+        assert!(validate_sort_orders(&opt).is_ok());
+        let opt = Opt::parse_from(["test", "-o", "count", "--sort-secondary", "count"]);
+        assert!(validate_sort_orders(&opt).is_err());
+        let opt = Opt::parse_from(["test", "--sort-secondary", "count"]);
+        assert!(validate_sort_orders(&opt).is_err());
+    }
+
+    #[test]
+    fn it_parses_composite_group_by_keys() {
+        let opt = Opt::parse_from(["test", "--group-by", "user,schema", "--keep-use"]);
+        assert_eq!(Some(vec![GroupBy::User, GroupBy::Schema]), opt.group_by());
+        assert!(validate_group_by(&opt).is_ok());
+        let opt = Opt::parse_from(["test", "--group-by", "user,schema"]);
+        assert_eq!(
+            Err("--group-by schema requires --keep-use".to_string()),
+            validate_group_by(&opt)
+        );
+        let opt = Opt::parse_from(["test", "--group-by", "user,host,user"]);
+        assert_eq!(
+            Err("--group-by lists the key 'user' more than once".to_string()),
+            validate_group_by(&opt)
+        );
+        assert!(Opt::try_parse_from(["test", "--group-by", "user,color"]).is_err());
+        let opt = Opt::parse_from(["test", "--aggregate-by-error"]);
+        assert_eq!(Some(vec![GroupBy::Error]), opt.group_by());
+    }
+
+    #[test]
+    fn it_expands_incidents_to_timestamp_and_query_time_filters() {
+        let opt = Opt::parse_from([
+            "test",
+            "--incident",
+            "2021-05-11T07:00:00Z..2021-05-11T08:00:00Z",
+            "--min-time",
+            "2.5",
+        ]);
+        let registry = FilterRegistry::builtin(&FilterOptions::default());
+        let filters: Vec<Box<dyn Filter>> = opt
+            .filter_definitions()
+            .iter()
+            .map(|definition| parse_filter(definition, &registry).unwrap())
+            .collect();
+        assert_eq!(3, filters.len());
+
+        let entry = |timestamp: OffsetDateTime, seconds: i64| LogEntry {
+            timestamp,
+            query_time: time::Duration::seconds(seconds),
+            ..Default::default()
+        };
+        let selected = |entry: LogEntry| filters.iter().all(|filter| filter.matches(&entry));
+        assert!(selected(entry(datetime!(2021-05-11 07:30:00 UTC), 3)));
+        assert!(selected(entry(datetime!(2021-05-11 08:00:00 UTC), 5)));
+        assert!(!selected(entry(datetime!(2021-05-11 07:30:00 UTC), 2)));
+        assert!(!selected(entry(datetime!(2021-05-11 06:59:59 UTC), 10)));
+        assert!(!selected(entry(datetime!(2021-05-11 08:00:01 UTC), 10)));
+
+        assert!(parse_time_range("2021-05-11T08:00:00Z..2021-05-11T07:00:00Z").is_err());
+        assert!(parse_time_range("2021-05-11T07:00:00Z").is_err());
+    }
+
+    #[test]
+    fn it_only_writes_sql_comments_for_aggregated_queries() {
+        let opt = Opt::parse_from(["test", "-n", "--format", "sql-comments"]);
+        assert!(validate_format(&opt).is_ok());
+        for mode in [
+            &["--group-by", "user"][..],
+            &["-n", "--aggregate-by-error"],
+            &["--report-duplicates"],
+            &["-n", "--baseline", "old.log"],
+            &["-n", "--latency-histogram"],
+            &["-n", "--interval", "1h"],
+            &["-n", "--suggest-indexes"],
+            &["-n", "--inventory"],
+        ] {
+            let args = ["test", "--format", "sql-comments"].iter().chain(mode);
+            assert!(validate_format(&Opt::parse_from(args)).is_err());
+        }
+    }
+
+    #[test]
+    fn it_only_emits_repro_scripts_for_normalized_queries() {
+        let opt = Opt::parse_from(["test", "-n", "--emit-repro", "repro.sql"]);
+        assert!(validate_emit_repro(&opt).is_ok());
+        for mode in [
+            &["-a"][..],
+            &["-n", "--group-by", "host"],
+            &["-n", "--aggregate-by-error"],
+            &["--report-duplicates"],
+            &["--normalize-numbers"],
+        ] {
+            let args = ["test", "--emit-repro", "repro.sql"].iter().chain(mode);
+            assert!(validate_emit_repro(&Opt::parse_from(args)).is_err());
+        }
+    }
+
+    #[test]
+    fn it_only_merges_reports_of_queries() {
+        assert!(Opt::try_parse_from(["test", "-n", "--merge-report", "old.ndjson"]).is_ok());
+        for mode in
+            [&["--group-by", "user"][..], &["--aggregate-by-error"], &["--report-duplicates"]]
+        {
+            let args = ["test", "--merge-report", "old.ndjson"].iter().chain(mode);
+            assert!(Opt::try_parse_from(args).is_err());
+        }
+    }
+
+    #[test]
+    fn it_only_watches_reports_of_queries() {
+        assert!(Opt::try_parse_from(["test", "-i", "slow.log", "--watch", "10s"]).is_ok());
+        for mode in [
+            &["--no-aggregate"][..],
+            &["--baseline", "old.log"],
+            &["--interval", "1h"],
+            &["--latency-histogram"],
+            &["--report-duplicates"],
+            &["--suggest-indexes"],
+            &["--summary"],
+            &["--summary-json", "summary.json"],
+        ] {
+            let args = ["test", "-i", "slow.log", "--watch", "10s"].iter().chain(mode);
+            assert!(Opt::try_parse_from(args).is_err());
+        }
+    }
+
+    #[test]
+    fn it_treats_a_limit_of_zero_as_unlimited() {
+        assert_eq!(DEFAULT_LIMIT, Opt::parse_from(["test"]).limit());
+        assert_eq!(5, Opt::parse_from(["test", "--limit", "5"]).limit());
+        assert_eq!(usize::MAX, Opt::parse_from(["test", "--limit", "0"]).limit());
+    }
+
+    #[test]
+    fn it_prints_the_version() {
+        let error = Opt::try_parse_from(["test", "--version"]).err().expect("should not parse");
+        assert_eq!(clap::error::ErrorKind::DisplayVersion, error.kind());
+        let output = error.to_string();
+        assert!(output.ends_with(&format!(" {}\n", env!("CARGO_PKG_VERSION"))));
+    }
+
+    #[test]
+    fn it_accepts_multiple_input_files() {
+        let opt: Opt = Opt::parse_from(["test", "-i", "foo.log", "--infile", "bar.log"]);
+        assert_eq!(opt.filenames, vec!["foo.log", "bar.log"]);
+    }
 }
@@ -0,0 +1,196 @@
+use std::io::{BufRead, BufReader, Read};
+
+use regex::Regex;
+use time::format_description::FormatItem;
+use time::macros::format_description;
+use time::{Duration, PrimitiveDateTime, UtcOffset};
+
+use crate::log_parser::{clean_line, LogEntry, ParseOptions, ParsedLog, UNKNOWN};
+
+const TIMESTAMP_FORMAT: &[FormatItem] =
+    format_description!("[year]-[month]-[day] [hour]:[minute]:[second][optional [.[subsecond]]]");
+
+/// Parses the statements logged by PostgreSQL with `log_min_duration_statement`, assuming a
+/// `log_line_prefix` of `%m [%p] ` or `%m [%p] %q%u@%d `, e.g.
+/// `2021-05-11 07:00:13.123 UTC [123] LOG:  duration: 778.443 ms  statement: SELECT ...`.
+/// Statements spanning several lines are joined, and all other messages are ignored.
+/// The fields that only MySQL logs, such as the lock time and row counts, are left empty, and
+/// entries without a user in their prefix get the same placeholder as in MySQL logs.
+pub fn parse_postgres_log(log: impl Read, options: &ParseOptions) -> ParsedLog {
+    let reader = BufReader::new(log);
+    let mut result = ParsedLog::default();
+
+    let prefix_regex = Regex::new(r"^\d{4}-\d{2}-\d{2} \d{2}:\d{2}:\d{2}").unwrap();
+    let statement_regex = Regex::new(
+        r"^(?P<time>\S+ \S+) (?P<zone>\S+) \[(?P<pid>\d+)\](?:-\d+)? (?:(?P<user>[^@\s]*)@(?P<db>\S*) )?LOG:\s+duration: (?P<duration>[\d.]+) ms\s+(?:statement|execute [^:]*): (?P<query>.*)$",
+    )
+    .unwrap();
+
+    let mut current: Option<LogEntry> = None;
+    for (i, l) in reader.lines().enumerate() {
+        if options.strict && result.malformed_entries > 0 {
+            break;
+        }
+
+        let line = match l {
+            Ok(line) => clean_line(i, line),
+            Err(err) => {
+                // the statement the line belongs to, if any, cannot be reported correctly
+                current = None;
+                result.skip_entry(options, format!("Unreadable line {}: {}", i + 1, err));
+                continue;
+            }
+        };
+        if !prefix_regex.is_match(&line) {
+            // continuation of a statement spanning several lines
+            if let Some(entry) = current.as_mut() {
+                entry.query.push('\n');
+                entry.query.push_str(&line);
+            }
+            continue;
+        }
+
+        if let Some(entry) = current.take() {
+            result.push_entry(options, entry);
+        }
+        let Some(caps) = statement_regex.captures(&line) else {
+            continue;
+        };
+
+        let timestamp = PrimitiveDateTime::parse(&caps["time"], TIMESTAMP_FORMAT)
+            .ok()
+            .zip(parse_time_zone(&caps["zone"]));
+        let Some((timestamp, offset)) = timestamp else {
+            result.skip_entry(options, format!("Invalid timestamp in line: {line}"));
+            continue;
+        };
+        let Ok(duration_ms) = caps["duration"].parse::<f64>() else {
+            result.skip_entry(options, format!("Invalid duration in line: {line}"));
+            continue;
+        };
+
+        current = Some(LogEntry {
+            timestamp: timestamp.assume_offset(offset),
+            user: caps
+                .name("user")
+                .map(|m| m.as_str())
+                .filter(|user| !user.is_empty())
+                .unwrap_or(UNKNOWN)
+                .to_string(),
+            query_time: Duration::microseconds((duration_ms * 1000.0).round() as i64),
+            thread_id: caps["pid"].parse().ok(),
+            database: caps.name("db").map(|m| m.as_str().to_string()).filter(|db| !db.is_empty()),
+            query: caps["query"].trim().to_string(),
+            ..Default::default()
+        });
+    }
+
+    if let Some(entry) = current {
+        result.push_entry(options, entry);
+    }
+    result
+}
+
+/// Parses the time zone of a timestamp, which may be UTC or GMT, or a numeric offset such as
+/// `+02` or `-05:30`. Other abbreviations are ambiguous and not supported.
+fn parse_time_zone(zone: &str) -> Option<UtcOffset> {
+    if matches!(zone, "UTC" | "GMT") {
+        return Some(UtcOffset::UTC);
+    }
+    let sign = match zone.get(..1)? {
+        "+" => 1,
+        "-" => -1,
+        _ => return None,
+    };
+    let digits: String = zone[1..].chars().filter(|&c| c != ':').collect();
+    if !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let (hours, minutes) = match digits.len() {
+        2 => (digits.parse::<i8>().ok()?, 0),
+        4 => (digits[..2].parse::<i8>().ok()?, digits[2..].parse::<i8>().ok()?),
+        _ => return None,
+    };
+    UtcOffset::from_hms(sign * hours, sign * minutes, 0).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use indoc::indoc;
+    use time::macros::{datetime, offset};
+
+    use super::*;
+
+    #[test]
+    fn it_parses_postgres_logs() {
+        let log = indoc!(
+            b"
+            2021-05-11 07:00:13.123 UTC [123] LOG:  duration: 778.443 ms  statement: SELECT * FROM foo WHERE id = 1;
+            2021-05-11 07:00:14 UTC [124] app@shop LOG:  duration: 1500.000 ms  execute S_1: SELECT * FROM bar
+            \tWHERE baz = $1
+            2021-05-11 07:00:14 UTC [124] app@shop DETAIL:  parameters: $1 = '5'
+            2021-05-11 07:00:15 UTC [125] LOG:  connection received: host=127.0.0.1 port=5432
+            2021-05-11 09:00:16 +02 [126] LOG:  duration: 1.5 ms  statement: UPDATE foo SET a = 1;
+        "
+        );
+
+        let parsed = parse_postgres_log(log as &[u8], &ParseOptions::default());
+        assert_eq!(0, parsed.malformed_entries);
+        assert_eq!(3, parsed.entries.len());
+
+        let e1 = &parsed.entries[0];
+        assert_eq!(datetime!(2021-05-11 07:00:13.123 UTC), e1.timestamp);
+        assert_eq!(778_443, e1.query_time.whole_microseconds());
+        assert_eq!(Some(123), e1.thread_id);
+        assert_eq!(UNKNOWN, e1.user);
+        assert_eq!(None, e1.database);
+        assert_eq!("SELECT * FROM foo WHERE id = 1;", e1.query);
+
+        let e2 = &parsed.entries[1];
+        assert_eq!(1_500_000, e2.query_time.whole_microseconds());
+        assert_eq!("app", e2.user);
+        assert_eq!(Some("shop".to_string()), e2.database);
+        assert_eq!("SELECT * FROM bar\n\tWHERE baz = $1", e2.query);
+
+        let e3 = &parsed.entries[2];
+        assert_eq!(datetime!(2021-05-11 07:00:16 UTC), e3.timestamp);
+        assert_eq!(1500, e3.query_time.whole_microseconds());
+    }
+
+    #[test]
+    fn it_skips_unreadable_lines() {
+        let mut log =
+            b"2021-05-11 07:00:13 UTC [123] LOG:  duration: 1.0 ms  statement: SELECT 1;\n\
+            2021-05-11 07:00:14 UTC [124] LOG:  duration: 2.0 ms  statement: SELECT 'a\n"
+                .to_vec();
+        log.extend_from_slice(b"\xff';\n");
+        log.extend_from_slice(
+            b"2021-05-11 07:00:15 UTC [125] LOG:  duration: 3.0 ms  statement: SELECT 3;\n",
+        );
+
+        let parsed = parse_postgres_log(log.as_slice(), &ParseOptions::default());
+        assert_eq!(1, parsed.malformed_entries);
+        assert_eq!(
+            vec!["SELECT 1;", "SELECT 3;"],
+            parsed.entries.iter().map(|e| e.query.as_str()).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn it_skips_entries_with_ambiguous_time_zones() {
+        let log = b"2021-05-11 07:00:13 CEST [123] LOG:  duration: 1.000 ms  statement: SELECT 1;";
+        let parsed = parse_postgres_log(log as &[u8], &ParseOptions::default());
+        assert_eq!(0, parsed.entries.len());
+        assert_eq!(1, parsed.malformed_entries);
+    }
+
+    #[test]
+    fn it_parses_time_zones() {
+        assert_eq!(Some(UtcOffset::UTC), parse_time_zone("GMT"));
+        assert_eq!(Some(offset!(+2)), parse_time_zone("+02"));
+        assert_eq!(Some(offset!(-5:30)), parse_time_zone("-05:30"));
+        assert_eq!(Some(offset!(+1)), parse_time_zone("+0100"));
+        assert_eq!(None, parse_time_zone("CEST"));
+        assert_eq!(None, parse_time_zone("+2"));
+    }
+}
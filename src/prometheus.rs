@@ -0,0 +1,162 @@
+use std::fmt::Write;
+
+use crate::aggregate::AggregateLogEntry;
+use crate::normalize::fingerprint;
+
+/// The metrics written for each aggregated record, with their help text
+const METRICS: [(&str, &str); 5] = [
+    ("slow_query_count", "Number of executions in the analyzed log"),
+    ("slow_query_total_time_seconds", "Combined execution time in the analyzed log"),
+    ("slow_query_avg_time_seconds", "Average execution time in the analyzed log"),
+    ("slow_query_max_time_seconds", "Highest execution time in the analyzed log"),
+    ("slow_query_p95_time_seconds", "95th percentile of the execution time in the analyzed log"),
+];
+
+/// Renders aggregated records in the Prometheus text exposition format. Every metric is a gauge
+/// with one sample per record, labelled with the fingerprint of its query. The samples carry no
+/// timestamp, so that Prometheus assigns the time of the scrape or push.
+pub fn exposition(entries: &[&AggregateLogEntry]) -> String {
+    let mut out = String::new();
+    for (i, (name, help)) in METRICS.into_iter().enumerate() {
+        writeln!(out, "# HELP {} {}", name, help).unwrap();
+        writeln!(out, "# TYPE {} gauge", name).unwrap();
+        for entry in entries {
+            let label = escape_label_value(&fingerprint(&entry.query));
+            writeln!(out, "{}{{fingerprint=\"{}\"}} {}", name, label, values(entry)[i]).unwrap();
+        }
+    }
+    out
+}
+
+/// The values of a record, in the order of `METRICS`
+fn values(entry: &AggregateLogEntry) -> [f64; METRICS.len()] {
+    let seconds = |usec: i128| usec as f64 / 1_000_000.0;
+    [
+        entry.count as f64,
+        seconds(entry.total_query_time),
+        seconds(entry.avg_query_time),
+        seconds(entry.max_query_time),
+        seconds(entry.p95_query_time),
+    ]
+}
+
+/// Label values need backslashes, double quotes and line breaks escaped
+fn escape_label_value(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+/// Pushes metrics in the exposition format to a Prometheus Pushgateway, replacing the metrics
+/// of the same names that were previously pushed for the job
+#[cfg(feature = "push")]
+pub fn push(url: &str, job: &str, body: &str) -> Result<(), String> {
+    let endpoint = format!("{}/metrics/job/{}", url.trim_end_matches('/'), encode_path(job));
+    match ureq::post(&endpoint).set("Content-Type", "text/plain; version=0.0.4").send_string(body) {
+        Ok(_) => Ok(()),
+        Err(ureq::Error::Status(status, response)) => {
+            let message = response.into_string().unwrap_or_default();
+            Err(format!("Pushgateway responded with status {}: {}", status, message.trim()))
+        }
+        Err(error) => Err(format!("Unable to reach the Pushgateway at {}: {}", url, error)),
+    }
+}
+
+/// Percent-encodes everything but unreserved characters, so that a job name cannot add segments
+/// to the URL path
+#[cfg(feature = "push")]
+fn encode_path(segment: &str) -> String {
+    segment
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::aggregate::{aggregate_entries, AggregateOptions};
+    use crate::log_parser::LogEntry;
+
+    use super::*;
+
+    fn aggregated() -> Vec<AggregateLogEntry> {
+        let entry = |query: &str, msec: i64| LogEntry {
+            query: query.to_string(),
+            query_time: time::Duration::milliseconds(msec),
+            ..Default::default()
+        };
+        let entries = vec![entry("SELECT 1;", 1500), entry("SELECT 1;", 500)];
+        aggregate_entries(entries, AggregateOptions::default()).into_values().collect()
+    }
+
+    #[test]
+    fn it_renders_the_exposition_format() {
+        let aggregated = aggregated();
+        let output = exposition(&aggregated.iter().collect::<Vec<_>>());
+        let label = format!("{{fingerprint=\"{}\"}}", fingerprint("SELECT 1;"));
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(15, lines.len());
+        assert_eq!("# HELP slow_query_count Number of executions in the analyzed log", lines[0]);
+        assert_eq!("# TYPE slow_query_count gauge", lines[1]);
+        assert_eq!(format!("slow_query_count{} 2", label), lines[2]);
+        assert_eq!(format!("slow_query_total_time_seconds{} 2", label), lines[5]);
+        assert_eq!(format!("slow_query_max_time_seconds{} 1.5", label), lines[11]);
+        assert_eq!(r#"a\"b\\c\n"#, escape_label_value("a\"b\\c\n"));
+    }
+
+    #[cfg(feature = "push")]
+    #[test]
+    fn it_pushes_the_exposition_to_a_pushgateway() {
+        use std::io::{BufRead, BufReader, Read, Write};
+        use std::net::TcpListener;
+
+        // answers a single request with the given status and returns its request line and body
+        let serve = |status: &'static str| {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let url = format!("http://{}", listener.local_addr().unwrap());
+            let handle = std::thread::spawn(move || {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream);
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap();
+                let mut length = 0;
+                loop {
+                    let mut header = String::new();
+                    reader.read_line(&mut header).unwrap();
+                    if header.trim().is_empty() {
+                        break;
+                    }
+                    if let Some((name, value)) = header.split_once(':') {
+                        if name.eq_ignore_ascii_case("content-length") {
+                            length = value.trim().parse().unwrap();
+                        }
+                    }
+                }
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+                let response = format!("HTTP/1.1 {}\r\nContent-Length: 4\r\n\r\nnope", status);
+                reader.get_mut().write_all(response.as_bytes()).unwrap();
+                (request_line.trim().to_string(), String::from_utf8(body).unwrap())
+            });
+            (url, handle)
+        };
+
+        let aggregated = aggregated();
+        let body = exposition(&aggregated.iter().collect::<Vec<_>>());
+
+        let (url, handle) = serve("200 OK");
+        push(&url, "myslowlog", &body).unwrap();
+        let (request_line, pushed) = handle.join().unwrap();
+        assert_eq!("POST /metrics/job/myslowlog HTTP/1.1", request_line);
+        assert_eq!(body, pushed);
+
+        let (url, handle) = serve("500 Internal Server Error");
+        let error = push(&url, "nightly/db1", &body).unwrap_err();
+        assert_eq!("Pushgateway responded with status 500: nope", error);
+        let (request_line, _) = handle.join().unwrap();
+        assert_eq!("POST /metrics/job/nightly%2Fdb1 HTTP/1.1", request_line);
+    }
+}
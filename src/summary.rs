@@ -0,0 +1,163 @@
+use std::fmt;
+
+use ahash::{HashSet, HashSetExt};
+use serde::Serialize;
+use time::OffsetDateTime;
+
+use crate::aggregate::nearest_rank;
+use crate::log_parser::LogEntry;
+
+/// Statistics over all matching entries. All durations are given in microseconds.
+#[derive(Debug, Default, Serialize)]
+pub struct Summary {
+    pub total_queries: usize,
+    pub unique_queries: usize,
+    pub total_query_time: i128,
+    pub min_query_time: i128,
+    pub avg_query_time: i128,
+    pub max_query_time: i128,
+    pub p50_query_time: i128,
+    pub p90_query_time: i128,
+    pub p95_query_time: i128,
+    pub p99_query_time: i128,
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub first_timestamp: Option<OffsetDateTime>,
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub last_timestamp: Option<OffsetDateTime>,
+    pub time_span: i128,
+    pub queries_per_second: f64,
+}
+
+pub fn summarize(entries: &[LogEntry]) -> Summary {
+    if entries.is_empty() {
+        return Summary::default();
+    }
+
+    let mut unique = HashSet::new();
+    let mut query_times = Vec::with_capacity(entries.len());
+    let mut summary = Summary { min_query_time: i128::MAX, ..Default::default() };
+    for entry in entries {
+        let query_time = entry.query_time.whole_microseconds();
+        unique.insert(entry.query.as_str());
+        query_times.push(query_time);
+        summary.total_query_time += query_time;
+        summary.min_query_time = summary.min_query_time.min(query_time);
+        summary.max_query_time = summary.max_query_time.max(query_time);
+        summary.first_timestamp = Some(
+            summary.first_timestamp.map_or(entry.timestamp, |first| first.min(entry.timestamp)),
+        );
+        summary.last_timestamp =
+            Some(summary.last_timestamp.map_or(entry.timestamp, |last| last.max(entry.timestamp)));
+    }
+
+    summary.total_queries = entries.len();
+    summary.unique_queries = unique.len();
+    summary.avg_query_time = summary.total_query_time / entries.len() as i128;
+    query_times.sort_unstable();
+    summary.p50_query_time = nearest_rank(&query_times, 50.0);
+    summary.p90_query_time = nearest_rank(&query_times, 90.0);
+    summary.p95_query_time = nearest_rank(&query_times, 95.0);
+    summary.p99_query_time = nearest_rank(&query_times, 99.0);
+    if let (Some(first), Some(last)) = (summary.first_timestamp, summary.last_timestamp) {
+        summary.time_span = (last - first).whole_microseconds();
+    }
+    if summary.time_span > 0 {
+        summary.queries_per_second =
+            entries.len() as f64 / (summary.time_span as f64 / 1_000_000.0);
+    }
+    summary
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Queries: {} ({} unique)", self.total_queries, self.unique_queries)?;
+        writeln!(
+            f,
+            "Query time: total {:.3} s, min {:.3} s, avg {:.3} s, max {:.3} s",
+            self.total_query_time as f64 / 1_000_000.0,
+            self.min_query_time as f64 / 1_000_000.0,
+            self.avg_query_time as f64 / 1_000_000.0,
+            self.max_query_time as f64 / 1_000_000.0,
+        )?;
+        writeln!(
+            f,
+            "Percentiles: p50 {:.3} s, p90 {:.3} s, p95 {:.3} s, p99 {:.3} s",
+            self.p50_query_time as f64 / 1_000_000.0,
+            self.p90_query_time as f64 / 1_000_000.0,
+            self.p95_query_time as f64 / 1_000_000.0,
+            self.p99_query_time as f64 / 1_000_000.0,
+        )?;
+        if let (Some(first), Some(last)) = (self.first_timestamp, self.last_timestamp) {
+            writeln!(
+                f,
+                "Time span: {} to {} ({:.3} s, {:.3} queries/s)",
+                first,
+                last,
+                self.time_span as f64 / 1_000_000.0,
+                self.queries_per_second,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use time::macros::datetime;
+    use time::Duration;
+
+    use super::*;
+
+    fn entry(query: &str, timestamp: OffsetDateTime, query_time_ms: i64) -> LogEntry {
+        LogEntry {
+            timestamp,
+            query_time: Duration::milliseconds(query_time_ms),
+            query: query.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn it_summarizes_entries() {
+        let start = datetime!(2021-05-11 07:00:00 UTC);
+        let entries = vec![
+            entry("SELECT 1;", start + Duration::minutes(30), 1000),
+            entry("SELECT 1;", start, 2000),
+            entry("SELECT 2;", start + Duration::hours(1), 6000),
+            entry("SELECT 3;", start + Duration::minutes(15), 3000),
+        ];
+
+        let summary = summarize(&entries);
+        assert_eq!(4, summary.total_queries);
+        assert_eq!(3, summary.unique_queries);
+        assert_eq!(12_000_000, summary.total_query_time);
+        assert_eq!(1_000_000, summary.min_query_time);
+        assert_eq!(3_000_000, summary.avg_query_time);
+        assert_eq!(6_000_000, summary.max_query_time);
+        assert_eq!(Some(start), summary.first_timestamp);
+        assert_eq!(Some(start + Duration::hours(1)), summary.last_timestamp);
+        assert_eq!(3_600_000_000, summary.time_span);
+        assert!((summary.queries_per_second - 4.0 / 3600.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn it_computes_percentiles_over_all_entries() {
+        let start = datetime!(2021-05-11 07:00:00 UTC);
+        let entries: Vec<LogEntry> =
+            (1..=100).rev().map(|i| entry(&format!("SELECT {};", i % 7), start, i * 10)).collect();
+
+        let summary = summarize(&entries);
+        assert_eq!(500_000, summary.p50_query_time);
+        assert_eq!(900_000, summary.p90_query_time);
+        assert_eq!(950_000, summary.p95_query_time);
+        assert_eq!(990_000, summary.p99_query_time);
+    }
+
+    #[test]
+    fn it_summarizes_empty_input() {
+        let summary = summarize(&[]);
+        assert_eq!(0, summary.total_queries);
+        assert_eq!(0, summary.time_span);
+        assert_eq!(0.0, summary.queries_per_second);
+    }
+}
@@ -0,0 +1,115 @@
+use std::io::{self, Read, Seek, SeekFrom};
+
+const TIME_MARKER: &[u8] = b"# Time:";
+const CHUNK_SIZE: u64 = 64 * 1024;
+
+/// Reads the end of a slow log, returning the last `count` blocks that start with a `# Time:`
+/// line. The log is searched backwards from EOF in chunks, so only the part that is returned
+/// has to be read in full. If the log has fewer blocks, it is returned in its entirety.
+pub fn read_last_blocks(mut log: impl Read + Seek, count: usize) -> io::Result<Vec<u8>> {
+    let start = find_last_blocks(&mut log, count)?;
+    let mut tail = Vec::new();
+    log.seek(SeekFrom::Start(start))?;
+    log.read_to_end(&mut tail)?;
+    Ok(tail)
+}
+
+/// Finds the offset of the `count`th last block
+fn find_last_blocks(log: &mut (impl Read + Seek), count: usize) -> io::Result<u64> {
+    let mut position = log.seek(SeekFrom::End(0))?;
+    if count == 0 {
+        return Ok(position);
+    }
+
+    let mut remaining = count;
+    // The first bytes of the chunk read previously, so that a marker that straddles the
+    // boundary between two chunks is still found
+    let mut carry: Vec<u8> = Vec::new();
+    while position > 0 {
+        let chunk_size = CHUNK_SIZE.min(position);
+        position -= chunk_size;
+        log.seek(SeekFrom::Start(position))?;
+        let mut chunk = vec![0; chunk_size as usize];
+        log.read_exact(&mut chunk)?;
+        let next_carry = chunk[..TIME_MARKER.len().min(chunk.len())].to_vec();
+        chunk.append(&mut carry);
+
+        let starts = block_starts(&chunk, position == 0);
+        if starts.len() >= remaining {
+            return Ok(position + starts[starts.len() - remaining] as u64);
+        }
+        remaining -= starts.len();
+        carry = next_carry;
+    }
+    Ok(0)
+}
+
+/// Finds the offsets of all lines starting with the `# Time:` marker. A marker at the very
+/// start of the data only counts if it is also the start of the file, since otherwise it is
+/// not known yet whether it starts a line.
+fn block_starts(data: &[u8], at_file_start: bool) -> Vec<usize> {
+    let mut starts = Vec::new();
+    if at_file_start && data.starts_with(TIME_MARKER) {
+        starts.push(0);
+    }
+    for (i, window) in data.windows(TIME_MARKER.len() + 1).enumerate() {
+        if window[0] == b'\n' && &window[1..] == TIME_MARKER {
+            starts.push(i + 1);
+        }
+    }
+    starts
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::log_parser::{parse_log, ParseOptions};
+
+    fn log(entries: usize) -> String {
+        (0..entries)
+            .map(|i| {
+                format!(
+                    "# Time: 2019-07-30T{:02}:{:02}:{:02}.000000Z\n\
+                    # User@Host: foo[bar] @  [127.0.0.1]  Id: 1337\n\
+                    # Query_time: 1.289039  Lock_time: 0.000061 Rows_sent: 1  Rows_examined: 1\n\
+                    SELECT {};\n",
+                    i / 3600,
+                    i / 60 % 60,
+                    i % 60,
+                    i
+                )
+            })
+            .collect()
+    }
+
+    fn last_queries(log: &str, count: usize) -> Vec<String> {
+        let tail = read_last_blocks(Cursor::new(log.as_bytes()), count).unwrap();
+        parse_log(&tail[..], &ParseOptions::default())
+            .entries
+            .into_iter()
+            .map(|e| e.query)
+            .collect()
+    }
+
+    #[test]
+    fn it_reads_the_last_entries() {
+        // large enough to span several chunks, so that some entries cross a chunk boundary
+        let log = log(5000);
+        assert!(log.len() as u64 > 3 * CHUNK_SIZE);
+
+        assert_eq!(vec!["SELECT 4998;", "SELECT 4999;"], last_queries(&log, 2));
+        let queries = last_queries(&log, 3000);
+        assert_eq!(3000, queries.len());
+        assert_eq!("SELECT 2000;", queries[0]);
+    }
+
+    #[test]
+    fn it_reads_the_whole_log_if_it_has_fewer_entries() {
+        let log = log(3);
+        assert_eq!(vec!["SELECT 0;", "SELECT 1;", "SELECT 2;"], last_queries(&log, 5));
+        assert_eq!(vec!["SELECT 0;", "SELECT 1;", "SELECT 2;"], last_queries(&log, 3));
+        assert!(last_queries(&log, 0).is_empty());
+    }
+}
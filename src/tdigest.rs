@@ -0,0 +1,271 @@
+use std::f64::consts::PI;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// Controls the trade-off between accuracy and size: a digest holds at most about this many
+/// centroids, plus a buffer of values that have not been merged into them yet
+const COMPRESSION: f64 = 100.0;
+const BUFFER_SIZE: usize = 500;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Centroid {
+    mean: f64,
+    weight: f64,
+}
+
+/// A merging t-digest (Dunning & Ertl, "Computing Extremely Accurate Quantiles Using t-Digests")
+/// for estimating percentiles in bounded memory, no matter how many values it has seen.
+///
+/// Values are clustered into centroids whose size is limited by the `k1` scale function, which
+/// keeps the centroids near both ends of the distribution small. The estimates are therefore
+/// most accurate for tail percentiles such as p99, with a relative error in the rank typically
+/// well below 1%, and somewhat less accurate around the median. For small numbers of values
+/// (up to a few dozen), every value keeps a centroid of its own, so that the result is exact
+/// and identical to the one of the nearest-rank method.
+#[derive(Clone, Debug, Default)]
+pub struct TDigest {
+    centroids: Vec<Centroid>,
+    buffer: Vec<f64>,
+    count: f64,
+    min: f64,
+    max: f64,
+}
+
+impl TDigest {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0.0
+    }
+
+    /// Builds a digest from `(mean, weight)` pairs, e.g. to approximate a distribution that is
+    /// only known by a few of its percentiles
+    pub fn from_centroids(centroids: impl IntoIterator<Item = (f64, f64)>) -> Self {
+        let mut centroids: Vec<Centroid> = centroids
+            .into_iter()
+            .filter(|&(_, weight)| weight > 0.0)
+            .map(|(mean, weight)| Centroid { mean, weight })
+            .collect();
+        centroids.sort_by(|a, b| a.mean.total_cmp(&b.mean));
+        let (Some(first), Some(last)) = (centroids.first(), centroids.last()) else {
+            return Self::default();
+        };
+        let (min, max) = (first.mean, last.mean);
+        let count = centroids.iter().map(|centroid| centroid.weight).sum();
+        TDigest { centroids, buffer: Vec::new(), count, min, max }
+    }
+
+    pub fn add(&mut self, value: f64) {
+        if self.is_empty() {
+            self.min = value;
+            self.max = value;
+        } else {
+            self.min = self.min.min(value);
+            self.max = self.max.max(value);
+        }
+        self.count += 1.0;
+        self.buffer.push(value);
+        if self.buffer.len() >= BUFFER_SIZE {
+            self.compress();
+        }
+    }
+
+    /// Adds all values seen by another digest to this one
+    pub fn merge(&mut self, other: &TDigest) {
+        if other.is_empty() {
+            return;
+        }
+        if self.is_empty() {
+            *self = other.clone();
+            return;
+        }
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+        self.count += other.count;
+        self.centroids.extend_from_slice(&other.centroids);
+        self.buffer.extend_from_slice(&other.buffer);
+        self.compress();
+    }
+
+    /// Estimates the value below or at which the given percentage of values fall. Between the
+    /// centers of two centroids, the value is interpolated linearly.
+    pub fn percentile(&self, percentile: f64) -> Option<f64> {
+        if self.is_empty() {
+            return None;
+        }
+        if !self.buffer.is_empty() {
+            let mut compressed = self.clone();
+            compressed.compress();
+            return compressed.percentile(percentile);
+        }
+
+        // the position of the value that the nearest-rank method would select, so that the
+        // result is exact for centroids holding a single value
+        let rank = (percentile / 100.0 * self.count).ceil().clamp(1.0, self.count);
+        let position = rank - 0.5;
+
+        let mut previous = (0.0, self.min);
+        let mut cumulative = 0.0;
+        for centroid in &self.centroids {
+            let center = cumulative + centroid.weight / 2.0;
+            if position <= center {
+                return Some(interpolate(previous, (center, centroid.mean), position));
+            }
+            previous = (center, centroid.mean);
+            cumulative += centroid.weight;
+        }
+        Some(interpolate(previous, (self.count, self.max), position))
+    }
+
+    /// Merges the buffered values into the centroids, combining neighbouring centroids as long
+    /// as they do not exceed the size limit for their position in the distribution
+    fn compress(&mut self) {
+        let mut all = std::mem::take(&mut self.centroids);
+        all.extend(self.buffer.drain(..).map(|mean| Centroid { mean, weight: 1.0 }));
+        all.sort_by(|a, b| a.mean.total_cmp(&b.mean));
+
+        let mut merged: Vec<Centroid> = Vec::with_capacity(COMPRESSION as usize);
+        let mut cumulative = 0.0;
+        for centroid in all {
+            if let Some(last) = merged.last_mut() {
+                let start = cumulative - last.weight;
+                let combined = last.weight + centroid.weight;
+                if scale(cumulative + centroid.weight, self.count) - scale(start, self.count) <= 1.0
+                {
+                    last.mean += (centroid.mean - last.mean) * centroid.weight / combined;
+                    last.weight = combined;
+                    cumulative += centroid.weight;
+                    continue;
+                }
+            }
+            cumulative += centroid.weight;
+            merged.push(centroid);
+        }
+        self.centroids = merged;
+    }
+}
+
+/// The serialized form of a digest, with the buffered values merged into the centroids, which
+/// are written as `[mean, weight]` pairs
+#[derive(Serialize, Deserialize)]
+struct SerializedDigest {
+    centroids: Vec<(f64, f64)>,
+    min: f64,
+    max: f64,
+}
+
+impl Serialize for TDigest {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut compressed = self.clone();
+        if !compressed.buffer.is_empty() {
+            compressed.compress();
+        }
+        SerializedDigest {
+            centroids: compressed.centroids.iter().map(|c| (c.mean, c.weight)).collect(),
+            min: compressed.min,
+            max: compressed.max,
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for TDigest {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let serialized = SerializedDigest::deserialize(deserializer)?;
+        let mut digest = TDigest::from_centroids(serialized.centroids);
+        if !digest.is_empty() {
+            digest.min = serialized.min;
+            digest.max = serialized.max;
+        }
+        Ok(digest)
+    }
+}
+
+/// The k1 scale function, mapping a rank to the index of the centroid it should fall into
+fn scale(rank: f64, count: f64) -> f64 {
+    let q = (rank / count).clamp(0.0, 1.0);
+    COMPRESSION / (2.0 * PI) * (2.0 * q - 1.0).asin()
+}
+
+fn interpolate((x0, y0): (f64, f64), (x1, y1): (f64, f64), x: f64) -> f64 {
+    if x1 <= x0 {
+        return y1;
+    }
+    y0 + (y1 - y0) * (x - x0) / (x1 - x0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aggregate::nearest_rank;
+
+    #[test]
+    fn it_is_exact_for_few_values() {
+        let mut digest = TDigest::new();
+        (0..18).for_each(|_| digest.add(10.0));
+        (0..2).for_each(|_| digest.add(5000.0));
+        assert_eq!(Some(10.0), digest.percentile(50.0));
+        assert_eq!(Some(10.0), digest.percentile(90.0));
+        assert_eq!(Some(5000.0), digest.percentile(95.0));
+        assert_eq!(Some(5000.0), digest.percentile(100.0));
+        assert_eq!(None, TDigest::new().percentile(50.0));
+    }
+
+    #[test]
+    fn it_approximates_percentiles_of_many_values() {
+        // a skewed, deterministic distribution resembling query times: mostly fast, long tail
+        let values: Vec<i128> =
+            (0..100_000_i128).map(|i| (i * 7919) % 100_000).map(|x| x * x / 1000).collect();
+        let mut digest = TDigest::new();
+        values.iter().for_each(|&value| digest.add(value as f64));
+        let mut sorted = values.clone();
+        sorted.sort_unstable();
+
+        assert!(digest.centroids.len() < 2 * COMPRESSION as usize);
+        for percentile in [50.0, 90.0, 95.0, 99.0, 99.9] {
+            let exact = nearest_rank(&sorted, percentile) as f64;
+            let estimate = digest.percentile(percentile).unwrap();
+            let error = (estimate - exact).abs() / exact;
+            assert!(error < 0.01, "p{}: {} instead of {}", percentile, estimate, exact);
+        }
+    }
+
+    #[test]
+    fn it_is_deterministic() {
+        // no randomness is involved, so the same values in the same order give the same estimates
+        let run = || {
+            let mut digest = TDigest::new();
+            (0..50_000_u64).for_each(|i| digest.add(((i * 7919) % 10_007) as f64));
+            [50.0, 90.0, 95.0, 99.0].map(|percentile| digest.percentile(percentile).unwrap())
+        };
+        assert_eq!(run(), run());
+    }
+
+    #[test]
+    fn it_merges_digests() {
+        let (mut first, mut second) = (TDigest::new(), TDigest::new());
+        (0..1000).for_each(|i| first.add(i as f64));
+        (1000..2000).for_each(|i| second.add(i as f64));
+        first.merge(&second);
+
+        let median = first.percentile(50.0).unwrap();
+        assert!((median - 1000.0).abs() < 20.0, "median {}", median);
+        assert_eq!(Some(1999.0), first.percentile(100.0));
+    }
+
+    #[test]
+    fn it_round_trips_through_json() {
+        let mut digest = TDigest::new();
+        (0..10_000).for_each(|i| digest.add(((i * 7919) % 10_007) as f64));
+
+        let json = serde_json::to_string(&digest).unwrap();
+        let restored: TDigest = serde_json::from_str(&json).unwrap();
+        for percentile in [0.0, 50.0, 90.0, 95.0, 99.0, 100.0] {
+            let (expected, actual) =
+                (digest.percentile(percentile).unwrap(), restored.percentile(percentile).unwrap());
+            assert!((expected - actual).abs() < 1e-6, "p{}: {}", percentile, actual);
+        }
+    }
+}
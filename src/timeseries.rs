@@ -0,0 +1,213 @@
+use std::fmt;
+
+use ahash::{HashMap, HashMapExt};
+use serde::ser::SerializeStruct;
+use serde::{Serialize, Serializer};
+use time::format_description::well_known::Rfc3339;
+use time::{Duration, OffsetDateTime};
+
+use crate::log_parser::LogEntry;
+use crate::normalize::fingerprint;
+
+/// The number and combined query time of the executions within one interval
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Point {
+    #[serde(with = "time::serde::rfc3339")]
+    pub start: OffsetDateTime,
+    pub count: i64,
+    #[serde(rename = "total_query_time_us")]
+    pub total_query_time: i128,
+}
+
+/// The executions of a query over time. Only the intervals in which the query was executed are
+/// stored, so that a short interval over a long log does not take up memory for every query.
+#[derive(Debug)]
+pub struct TimeSeries {
+    pub query: String,
+    pub fingerprint: String,
+    /// The intervals containing executions, in chronological order
+    occupied: Vec<Point>,
+    /// The start of the first and of the last interval covered by the log
+    first: OffsetDateTime,
+    last: OffsetDateTime,
+    interval: Duration,
+}
+
+impl TimeSeries {
+    pub fn total_query_time(&self) -> i128 {
+        self.occupied.iter().map(|point| point.total_query_time).sum()
+    }
+
+    /// Yields one point for each interval covered by the log, with zeros where the query did not
+    /// occur
+    pub fn points(&self) -> impl Iterator<Item = Point> + '_ {
+        let mut occupied = self.occupied.iter().peekable();
+        let starts = std::iter::successors(Some(self.first), |start| {
+            Some(*start + self.interval).filter(|next| *next <= self.last)
+        });
+        starts.map(move |start| match occupied.next_if(|point| point.start == start) {
+            Some(point) => Point { start, ..*point },
+            None => Point { start, count: 0, total_query_time: 0 },
+        })
+    }
+}
+
+/// Serializes the points of a time series without collecting them first
+struct Points<'a>(&'a TimeSeries);
+
+impl Serialize for Points<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.0.points())
+    }
+}
+
+impl Serialize for TimeSeries {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("TimeSeries", 3)?;
+        state.serialize_field("query", &self.query)?;
+        state.serialize_field("fingerprint", &self.fingerprint)?;
+        state.serialize_field("points", &Points(self))?;
+        state.end()
+    }
+}
+
+impl fmt::Display for TimeSeries {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{}", self.query)?;
+        for point in self.points() {
+            writeln!(
+                f,
+                "  {}: {} queries, {:.3} s",
+                point.start.format(&Rfc3339).map_err(|_| fmt::Error)?,
+                point.count,
+                point.total_query_time as f64 / 1_000_000.0
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Parses an interval such as `30s`, `15m`, `1h` or `1d`
+pub fn parse_interval(value: &str) -> Result<Duration, String> {
+    let error = || format!("Interval must be a number followed by s, m, h or d, found '{}'", value);
+    let unit_start = value.find(|c: char| !c.is_ascii_digit()).ok_or_else(error)?;
+    let amount = value[..unit_start].parse::<i64>().map_err(|_err| error())?;
+    let unit_seconds = match &value[unit_start..] {
+        "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        "d" => 86400,
+        _ => return Err(error()),
+    };
+    let seconds = amount
+        .checked_mul(unit_seconds)
+        .ok_or_else(|| format!("Interval '{}' is too long", value))?;
+    if seconds == 0 {
+        return Err(error());
+    }
+    Ok(Duration::seconds(seconds))
+}
+
+/// Buckets the executions of each query into intervals of the given length, aligned to the
+/// Unix epoch. Every series covers the same range of intervals, from the one containing the
+/// earliest entry to the one containing the latest, with zeros where a query did not occur.
+pub fn time_series(entries: Vec<(String, LogEntry)>, interval: Duration) -> Vec<TimeSeries> {
+    let interval_ns = interval.whole_nanoseconds();
+    let start = |entry: &LogEntry| {
+        let bucket = entry.timestamp.unix_timestamp_nanos().div_euclid(interval_ns);
+        OffsetDateTime::from_unix_timestamp_nanos(bucket * interval_ns)
+            .expect("bucket lies within the range of the log's timestamps")
+    };
+    let (Some(first), Some(last)) =
+        (entries.iter().map(|(_, e)| start(e)).min(), entries.iter().map(|(_, e)| start(e)).max())
+    else {
+        return Vec::new();
+    };
+
+    let mut buckets: HashMap<String, HashMap<OffsetDateTime, Point>> = HashMap::new();
+    for (query, entry) in entries {
+        let start = start(&entry);
+        let point = buckets.entry(query).or_default().entry(start).or_insert(Point {
+            start,
+            count: 0,
+            total_query_time: 0,
+        });
+        point.count += 1;
+        point.total_query_time += entry.query_time.whole_microseconds();
+    }
+    buckets
+        .into_iter()
+        .map(|(query, points)| {
+            let mut occupied: Vec<Point> = points.into_values().collect();
+            occupied.sort_unstable_by_key(|point| point.start);
+            TimeSeries { fingerprint: fingerprint(&query), query, occupied, first, last, interval }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use time::macros::datetime;
+
+    use super::*;
+
+    fn entry(query: &str, timestamp: OffsetDateTime, query_time_ms: i64) -> (String, LogEntry) {
+        let entry = LogEntry {
+            timestamp,
+            query_time: Duration::milliseconds(query_time_ms),
+            query: query.to_string(),
+            ..Default::default()
+        };
+        (query.to_string(), entry)
+    }
+
+    #[test]
+    fn it_parses_intervals() {
+        assert_eq!(Ok(Duration::seconds(30)), parse_interval("30s"));
+        assert_eq!(Ok(Duration::hours(1)), parse_interval("1h"));
+        assert_eq!(Ok(Duration::days(2)), parse_interval("2d"));
+        assert!(parse_interval("0m").is_err());
+        assert!(parse_interval("h").is_err());
+        assert!(parse_interval("5").is_err());
+        assert!(parse_interval("1w").is_err());
+        assert!(parse_interval("999999999999999d").is_err());
+    }
+
+    #[test]
+    fn it_buckets_queries_by_hour() {
+        let entries = vec![
+            entry("SELECT 1;", datetime!(2021-05-11 07:15:00 UTC), 1000),
+            entry("SELECT 1;", datetime!(2021-05-11 07:45:00 UTC), 2000),
+            entry("SELECT 1;", datetime!(2021-05-11 08:30:00 UTC), 500),
+            entry("SELECT 2;", datetime!(2021-05-11 08:59:59 UTC), 100),
+        ];
+
+        let mut series = time_series(entries, Duration::hours(1));
+        series.sort_by(|a, b| a.query.cmp(&b.query));
+        assert_eq!(2, series.len());
+
+        let expected = vec![
+            Point {
+                start: datetime!(2021-05-11 07:00:00 UTC),
+                count: 2,
+                total_query_time: 3_000_000,
+            },
+            Point {
+                start: datetime!(2021-05-11 08:00:00 UTC),
+                count: 1,
+                total_query_time: 500_000,
+            },
+        ];
+        assert_eq!(expected, series[0].points().collect::<Vec<_>>());
+
+        let expected = vec![
+            Point { start: datetime!(2021-05-11 07:00:00 UTC), count: 0, total_query_time: 0 },
+            Point {
+                start: datetime!(2021-05-11 08:00:00 UTC),
+                count: 1,
+                total_query_time: 100_000,
+            },
+        ];
+        assert_eq!(expected, series[1].points().collect::<Vec<_>>());
+    }
+}
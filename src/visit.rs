@@ -0,0 +1,254 @@
+use std::cell::Cell;
+use std::panic::{self, UnwindSafe};
+
+use sqlparser::ast::{
+    Distinct, Expr, FunctionArg, FunctionArgExpr, GroupByExpr, Join, JoinConstraint, JoinOperator,
+    Query, Select, SelectItem, SetExpr, Statement, TableFactor, TableWithJoins,
+};
+use sqlparser::dialect::MySqlDialect;
+use sqlparser::parser::{Parser, ParserError};
+
+pub const PARSER_PANIC: &str = "the parser panicked";
+
+thread_local! {
+    /// Set while the parser runs, so that the panic hook can tell its panics from all others
+    static IN_PARSER: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Installs a panic hook that keeps quiet about panics of the parser, which are caught and
+/// reported as parse errors instead, and passes all other panics on to the previous hook
+pub fn silence_parser_panics() {
+    let previous = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        if !IN_PARSER.with(Cell::get) {
+            previous(info);
+        }
+    }));
+}
+
+/// Parses a query with the same dialect that is used for normalization,
+/// returning `None` if it cannot be parsed
+pub fn parse(query: &str) -> Option<Vec<Statement>> {
+    try_parse(query).ok()
+}
+
+/// Parses a query with the same dialect that is used for normalization, returning the parser's
+/// error message if it cannot be parsed. The parser may panic on adversarial input instead of
+/// returning an error, which is caught here so that it does not abort the whole run.
+pub fn try_parse(query: &str) -> Result<Vec<Statement>, String> {
+    catch_parser_panic(|| Parser::parse_sql(&MySqlDialect {}, query))
+}
+
+fn catch_parser_panic(
+    parse: impl FnOnce() -> Result<Vec<Statement>, ParserError> + UnwindSafe,
+) -> Result<Vec<Statement>, String> {
+    IN_PARSER.with(|in_parser| in_parser.set(true));
+    let result = panic::catch_unwind(parse);
+    IN_PARSER.with(|in_parser| in_parser.set(false));
+    match result {
+        Ok(result) => result.map_err(|err| err.to_string()),
+        Err(_) => Err(PARSER_PANIC.to_string()),
+    }
+}
+
+/// Read-only traversal of a statement's AST, following the same structure as the normalization.
+/// Each node is passed to the visitor before its children are visited.
+pub trait Visitor {
+    fn visit_statement(&mut self, _statement: &Statement) {}
+    fn visit_query(&mut self, _query: &Query) {}
+    fn visit_select(&mut self, _select: &Select) {}
+    fn visit_table_factor(&mut self, _table_factor: &TableFactor) {}
+    fn visit_join(&mut self, _join: &Join) {}
+    fn visit_expr(&mut self, _expr: &Expr) {}
+}
+
+pub fn walk_statements(ast: &[Statement], visitor: &mut impl Visitor) {
+    ast.iter().for_each(|stmt| walk_statement(stmt, visitor));
+}
+
+fn walk_statement(stmt: &Statement, visitor: &mut impl Visitor) {
+    visitor.visit_statement(stmt);
+    match stmt {
+        Statement::Query(query) => walk_query(query, visitor),
+        Statement::Insert { source, .. } => walk_query(source, visitor),
+        Statement::Update { table, assignments, from, selection, .. } => {
+            walk_table_with_joins(table, visitor);
+            assignments.iter().for_each(|assignment| walk_expr(&assignment.value, visitor));
+            from.iter().for_each(|twj| walk_table_with_joins(twj, visitor));
+            selection.iter().for_each(|expr| walk_expr(expr, visitor));
+        }
+        Statement::Delete { from, using, selection, order_by, .. } => {
+            from.iter().for_each(|twj| walk_table_with_joins(twj, visitor));
+            using.iter().flatten().for_each(|twj| walk_table_with_joins(twj, visitor));
+            selection.iter().for_each(|expr| walk_expr(expr, visitor));
+            order_by.iter().for_each(|order_by| walk_expr(&order_by.expr, visitor));
+        }
+        _ => (),
+    }
+}
+
+fn walk_query(query: &Query, visitor: &mut impl Visitor) {
+    visitor.visit_query(query);
+    if let Some(with) = &query.with {
+        with.cte_tables.iter().for_each(|cte| walk_query(&cte.query, visitor));
+    }
+    walk_set_expr(&query.body, visitor);
+    query.order_by.iter().for_each(|order_by| walk_expr(&order_by.expr, visitor));
+    query.limit.iter().for_each(|expr| walk_expr(expr, visitor));
+    query.offset.iter().for_each(|offset| walk_expr(&offset.value, visitor));
+}
+
+fn walk_set_expr(set_expr: &SetExpr, visitor: &mut impl Visitor) {
+    match set_expr {
+        SetExpr::Select(select) => walk_select(select, visitor),
+        SetExpr::Query(query) => walk_query(query, visitor),
+        SetExpr::SetOperation { left, right, .. } => {
+            walk_set_expr(left, visitor);
+            walk_set_expr(right, visitor);
+        }
+        SetExpr::Values(values) => {
+            values.rows.iter().flatten().for_each(|expr| walk_expr(expr, visitor));
+        }
+        SetExpr::Insert(stmt) | SetExpr::Update(stmt) => walk_statement(stmt, visitor),
+        SetExpr::Table(_) => (),
+    }
+}
+
+fn walk_select(select: &Select, visitor: &mut impl Visitor) {
+    visitor.visit_select(select);
+    if let Some(Distinct::On(exprs)) = &select.distinct {
+        exprs.iter().for_each(|expr| walk_expr(expr, visitor));
+    }
+    for item in &select.projection {
+        match item {
+            SelectItem::UnnamedExpr(expr) | SelectItem::ExprWithAlias { expr, .. } => {
+                walk_expr(expr, visitor)
+            }
+            _ => (),
+        }
+    }
+    select.from.iter().for_each(|twj| walk_table_with_joins(twj, visitor));
+    select.selection.iter().for_each(|expr| walk_expr(expr, visitor));
+    if let GroupByExpr::Expressions(exprs) = &select.group_by {
+        exprs.iter().for_each(|expr| walk_expr(expr, visitor));
+    }
+    select.having.iter().for_each(|expr| walk_expr(expr, visitor));
+    select.qualify.iter().for_each(|expr| walk_expr(expr, visitor));
+}
+
+fn walk_table_with_joins(twj: &TableWithJoins, visitor: &mut impl Visitor) {
+    walk_table_factor(&twj.relation, visitor);
+    twj.joins.iter().for_each(|join| walk_join(join, visitor));
+}
+
+fn walk_join(join: &Join, visitor: &mut impl Visitor) {
+    visitor.visit_join(join);
+    walk_table_factor(&join.relation, visitor);
+    if let Some(JoinConstraint::On(expr)) = join_constraint(&join.join_operator) {
+        walk_expr(expr, visitor);
+    }
+}
+
+pub fn join_constraint(operator: &JoinOperator) -> Option<&JoinConstraint> {
+    match operator {
+        JoinOperator::Inner(constraint)
+        | JoinOperator::LeftOuter(constraint)
+        | JoinOperator::RightOuter(constraint)
+        | JoinOperator::FullOuter(constraint) => Some(constraint),
+        _ => None,
+    }
+}
+
+fn walk_table_factor(tf: &TableFactor, visitor: &mut impl Visitor) {
+    visitor.visit_table_factor(tf);
+    match tf {
+        TableFactor::Derived { subquery, .. } => walk_query(subquery, visitor),
+        TableFactor::NestedJoin { table_with_joins, .. } => {
+            walk_table_with_joins(table_with_joins, visitor)
+        }
+        _ => (),
+    }
+}
+
+fn walk_expr(expr: &Expr, visitor: &mut impl Visitor) {
+    visitor.visit_expr(expr);
+    match expr {
+        Expr::IsNull(e)
+        | Expr::IsNotNull(e)
+        | Expr::UnaryOp { expr: e, .. }
+        | Expr::Cast { expr: e, .. }
+        | Expr::Extract { expr: e, .. }
+        | Expr::Collate { expr: e, .. }
+        | Expr::Nested(e) => walk_expr(e, visitor),
+        Expr::InList { expr, list, .. } => {
+            walk_expr(expr, visitor);
+            list.iter().for_each(|e| walk_expr(e, visitor));
+        }
+        Expr::InSubquery { expr, subquery, .. } => {
+            walk_expr(expr, visitor);
+            walk_query(subquery, visitor);
+        }
+        Expr::Between { expr, low, high, .. } => {
+            walk_expr(expr, visitor);
+            walk_expr(low, visitor);
+            walk_expr(high, visitor);
+        }
+        Expr::BinaryOp { left, right, .. } => {
+            walk_expr(left, visitor);
+            walk_expr(right, visitor);
+        }
+        Expr::Like { expr, pattern, .. } | Expr::ILike { expr, pattern, .. } => {
+            walk_expr(expr, visitor);
+            walk_expr(pattern, visitor);
+        }
+        Expr::Case { operand, conditions, results, else_result } => {
+            operand.iter().for_each(|e| walk_expr(e, visitor));
+            conditions.iter().for_each(|e| walk_expr(e, visitor));
+            results.iter().for_each(|e| walk_expr(e, visitor));
+            else_result.iter().for_each(|e| walk_expr(e, visitor));
+        }
+        Expr::Exists { subquery, .. } | Expr::Subquery(subquery) => walk_query(subquery, visitor),
+        Expr::Function(function) => {
+            for arg in &function.args {
+                match arg {
+                    FunctionArg::Named { arg: FunctionArgExpr::Expr(e), .. }
+                    | FunctionArg::Unnamed(FunctionArgExpr::Expr(e)) => walk_expr(e, visitor),
+                    _ => (),
+                }
+            }
+        }
+        _ => (),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    #[test]
+    fn it_turns_parser_panics_into_errors() {
+        assert_eq!(Err(PARSER_PANIC.to_string()), catch_parser_panic(|| panic!("parser bug")));
+        assert!(try_parse("SELECT 1;").is_ok());
+        assert!(try_parse("SELEC 1;").is_err());
+    }
+
+    #[test]
+    fn it_keeps_quiet_about_parser_panics() {
+        static REPORTED: AtomicUsize = AtomicUsize::new(0);
+        const MESSAGE: &str = "parser bug for the panic hook";
+        panic::set_hook(Box::new(|info| {
+            if info.payload().downcast_ref::<&str>() == Some(&MESSAGE) {
+                REPORTED.fetch_add(1, Ordering::SeqCst);
+            }
+        }));
+        silence_parser_panics();
+
+        assert!(catch_parser_panic(|| panic::panic_any(MESSAGE)).is_err());
+        assert_eq!(0, REPORTED.load(Ordering::SeqCst));
+        assert!(panic::catch_unwind(|| panic::panic_any(MESSAGE)).is_err());
+        assert_eq!(1, REPORTED.load(Ordering::SeqCst));
+        drop(panic::take_hook());
+    }
+}